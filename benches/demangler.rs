@@ -0,0 +1,43 @@
+//! Benchmarks for demangling a batch of symbols, as when processing a whole
+//! symbol table.
+
+#![cfg_attr(feature = "nightly", feature(test))]
+
+#[cfg(feature = "nightly")]
+mod benches {
+    extern crate cpp_demangle;
+    extern crate test;
+
+    use self::cpp_demangle::{Demangler, Symbol};
+
+    const SYMBOLS: &'static [&'static [u8]] = &[
+        b"_ZN5space3fooEibc",
+        b"_ZN5space3fooEPNS_3BarES1_",
+        b"_Z1fI1AI1BIiEEEvv",
+        b"_ZNSt6vectorIiSaIiEE9push_backERKi",
+        b"_ZNKSt8_Rb_treeIiSt4pairIKiiESt10_Select1stIS2_ESt4lessIiESaIS2_EE4findERS1_",
+        b"_ZN2JS6HandleIP8JSObjectEC2ERKNS_13MutableHandleIS2_EE",
+        b"_ZTV3Foo",
+        b"_Z3barv",
+    ];
+
+    #[bench]
+    fn one_shot(b: &mut test::Bencher) {
+        b.iter(|| {
+            for symbol in SYMBOLS {
+                let sym = Symbol::new(*symbol).unwrap();
+                test::black_box(sym.demangle().unwrap());
+            }
+        });
+    }
+
+    #[bench]
+    fn reused_demangler(b: &mut test::Bencher) {
+        let mut demangler = Demangler::new();
+        b.iter(|| {
+            for symbol in SYMBOLS {
+                test::black_box(demangler.demangle(symbol).unwrap().len());
+            }
+        });
+    }
+}
@@ -0,0 +1,41 @@
+//! Benchmarks for rejecting input that isn't a mangled symbol, which is the
+//! overwhelmingly common case when filtering arbitrary text.
+
+#![cfg_attr(feature = "nightly", feature(test))]
+
+#[cfg(feature = "nightly")]
+mod benches {
+    extern crate cpp_demangle;
+    extern crate test;
+
+    use self::cpp_demangle::Symbol;
+
+    const LINES: &'static [&'static [u8]] = &[
+        b"    0x0000000000401136 <+0>:     push   %rbp",
+        b"Disassembly of section .text:",
+        b"main",
+        b"printf",
+        b"SomeSourceName",
+        b"Z_interior_ptr",
+        b"__libc_start_main",
+        b"1234567890",
+    ];
+
+    #[bench]
+    fn new(b: &mut test::Bencher) {
+        b.iter(|| {
+            for line in LINES {
+                test::black_box(Symbol::new(*line).is_err());
+            }
+        });
+    }
+
+    #[bench]
+    fn new_lenient(b: &mut test::Bencher) {
+        b.iter(|| {
+            for line in LINES {
+                test::black_box(Symbol::new_lenient(*line).is_err());
+            }
+        });
+    }
+}
@@ -155,7 +155,7 @@ fn test_libiberty_demangle_{}_() {{
     let mangled = br#"{}"#;
     println!("Parsing mangled symbol: {{}}", String::from_utf8_lossy(mangled));
 
-    let sym = cpp_demangle::Symbol::new(&mangled[..])
+    let sym = cpp_demangle::Symbol::new_lenient(&mangled[..])
         .expect("should parse mangled symbol");
 
     let expected = r#"{}"#;
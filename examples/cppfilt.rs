@@ -1,3 +1,12 @@
+//! A `c++filt`-like filter: copies stdin to stdout, replacing every mangled
+//! C++ symbol with its demangled form. Symbols that fail to demangle are left
+//! unchanged.
+//!
+//! ```text
+//! $ echo _ZN5space3fooEibc | cargo run --example cppfilt
+//! space::foo(int, bool, char)
+//! ```
+
 extern crate cpp_demangle;
 
 use cpp_demangle::BorrowedSymbol;
@@ -7,10 +16,11 @@ use std::process;
 /// Find the index of the first (potential) occurrence of a mangled C++ symbol
 /// in the given `haystack`.
 fn find_mangled(haystack: &[u8]) -> Option<usize> {
-    for i in 0..haystack.len() - 1 {
+    for i in 0..haystack.len().saturating_sub(1) {
         if haystack[i] == b'_' {
             let next = haystack[i + 1];
-            if next == b'Z' || next == b'_' && haystack.get(i + 2) == Some(&b'Z') {
+            if next == b'Z' || next == b'_' && haystack.get(i + 2) == Some(&b'Z') ||
+               haystack[i..].starts_with(b"_GLOBAL_") {
                 return Some(i);
             }
         }
@@ -19,6 +29,12 @@ fn find_mangled(haystack: &[u8]) -> Option<usize> {
     None
 }
 
+/// Can the given byte appear within a mangled symbol?
+fn is_symbol_byte(byte: u8) -> bool {
+    (b'a' <= byte && byte <= b'z') || (b'A' <= byte && byte <= b'Z') ||
+    (b'0' <= byte && byte <= b'9') || byte == b'_' || byte == b'$'
+}
+
 /// Print the given `line` to `out`, with all mangled C++ symbols replaced with
 /// their demangled form.
 fn demangle_line<W>(out: &mut W, line: &[u8]) -> io::Result<()>
@@ -28,13 +44,24 @@ fn demangle_line<W>(out: &mut W, line: &[u8]) -> io::Result<()>
 
     while let Some(idx) = find_mangled(line) {
         try!(write!(out, "{}", String::from_utf8_lossy(&line[..idx])));
+        line = &line[idx..];
 
-        if let Ok((sym, tail)) = BorrowedSymbol::with_tail(&line[idx..]) {
-            try!(write!(out, "{}", sym));
-            line = tail;
-        } else {
-            try!(write!(out, "_Z"));
-            line = &line[2..];
+        // Only demangle a symbol that makes up the whole token, so that a
+        // token with a valid symbol as a prefix, like `_Z3foo3`, is not
+        // demangled as `foo` followed by garbage.
+        match BorrowedSymbol::with_tail(line) {
+            Ok((sym, tail)) if tail.first().map_or(true, |&b| !is_symbol_byte(b)) => {
+                try!(write!(out, "{}", sym));
+                line = tail;
+            }
+            _ => {
+                // Echo the whole token unchanged.
+                let end = line.iter()
+                    .position(|&b| !is_symbol_byte(b))
+                    .unwrap_or(line.len());
+                try!(write!(out, "{}", String::from_utf8_lossy(&line[..end])));
+                line = &line[end..];
+            }
         }
     }
 
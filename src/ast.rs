@@ -2,15 +2,15 @@
 
 extern crate fixedbitset;
 
-use error::{self, Result};
+use error::{self, DemangleResult, Result};
 use index_str::IndexStr;
 use self::fixedbitset::FixedBitSet;
 #[cfg(feature = "logging")]
 use std::cell::RefCell;
-use std::error::Error;
 use std::fmt;
 use std::io::{self, Write};
 use subs::{Substitutable, SubstitutionTable};
+use {ComplexSpelling, DemangleOptions, Flavor, Markup, MarkupRegion};
 
 struct AutoLogParse;
 
@@ -82,6 +82,39 @@ pub trait Parse: Sized {
                      -> Result<(Self, IndexStr<'b>)>;
 }
 
+/// The span of mangled input that a major production was parsed from. These
+/// are only recorded when parsing with `Symbol::new_with_spans`.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct ParsedSpan {
+    /// The name of the production, such as `"SourceName"`.
+    pub production: &'static str,
+    /// The index of the first byte of input that the production was parsed
+    /// from.
+    pub start: usize,
+    /// The index just past the last byte of input that the production was
+    /// parsed from.
+    pub end: usize,
+}
+
+// Parse a major production with `parse`, keeping track of how deeply major
+// productions are nested, and record the span of input that it consumed if the
+// substitution table is recording spans.
+fn parse_with_span<'a, 'b, P, F>(production: &'static str,
+                                 subs: &'a mut SubstitutionTable,
+                                 input: IndexStr<'b>,
+                                 parse: F)
+                                 -> Result<(P, IndexStr<'b>)>
+    where F: FnOnce(&mut SubstitutionTable, IndexStr<'b>) -> Result<(P, IndexStr<'b>)>
+{
+    subs.enter_production();
+    let result = parse(subs, input);
+    subs.exit_production();
+
+    let (parsed, tail) = try!(result);
+    subs.record_span(production, input.index(), tail.index());
+    Ok((parsed, tail))
+}
+
 /// A trait to abstract looking ahead one byte during parsing.
 trait StartsWith {
     /// Does this production start with the given byte?
@@ -101,6 +134,51 @@ trait GetTemplateArgs {
                              -> Option<&'a TemplateArgs>;
 }
 
+/// Get the innermost, unqualified name of whatever entity this AST node names.
+/// For example, the leaf name of `space::Foo::bar<int>` is `bar`.
+trait GetLeafName {
+    /// Returns `Some` if this names an entity with an unqualified name, `None`
+    /// otherwise.
+    fn get_leaf_name<'a>(&'a self,
+                         subs: &'a SubstitutionTable)
+                         -> Option<&'a UnqualifiedName>;
+}
+
+/// Match the scope chain of whatever entity this AST node names against a
+/// sequence of identifiers, outermost first. For example, the scope chain of
+/// `space::Foo<int>::bar` is `space`, `Foo`, `bar`.
+///
+/// Matching stops at the first component that differs, and as soon as every
+/// identifier in the sequence has matched, so the rest of the name is never
+/// looked at.
+trait MatchScope {
+    /// Returns the identifiers of `scope` that remain unmatched after this
+    /// node's components, or `None` if one of its components did not match.
+    fn match_scope<'s, 't>(&self,
+                           subs: &SubstitutionTable,
+                           input: &[u8],
+                           scope: &'s [&'t [u8]])
+                           -> Option<&'s [&'t [u8]]>;
+}
+
+/// Match one component of a scope chain against the next identifier of
+/// `scope`. A component without an identifier, such as an operator name,
+/// never matches.
+fn match_scope_component<'s, 't>(scope: &'s [&'t [u8]],
+                                  component: Option<&[u8]>)
+                                  -> Option<&'s [&'t [u8]]> {
+    match scope.split_first() {
+        None => Some(scope),
+        Some((first, rest)) => {
+            if component == Some(*first) {
+                Some(rest)
+            } else {
+                None
+            }
+        }
+    }
+}
+
 /// When formatting a mangled symbol's parsed AST as a demangled symbol, we need
 /// to resolve indirect references to template and function arguments with
 /// direct `TemplateArg` and `Type` references respectively.
@@ -121,6 +199,13 @@ trait ArgResolver: fmt::Debug {
 
     /// Get the current context's `idx`th function argument's type.
     fn get_function_arg(&self, idx: usize) -> Result<&Type>;
+
+    /// Get the current context's `idx`th template parameter's declaration, if
+    /// the context declares its template parameters rather than instantiating
+    /// them with concrete arguments, such as a generic lambda.
+    fn get_template_param_decl(&self, _: usize) -> Option<&TemplateParamDecl> {
+        None
+    }
 }
 
 /// An `ArgStack` represents the current function and template demangling scope
@@ -182,6 +267,22 @@ impl<'a, 'b> ArgResolver for Option<ArgStack<'a, 'b>> {
 
         Err(error::Error::BadFunctionArgReference)
     }
+
+    fn get_template_param_decl(&self, idx: usize) -> Option<&TemplateParamDecl> {
+        let mut stack = *self;
+        while let Some(s) = stack {
+            // A concrete argument in an inner scope shadows outer declarations.
+            if s.item.get_template_arg(idx).is_ok() {
+                return None;
+            }
+            if let Some(decl) = s.item.get_template_param_decl(idx) {
+                return Some(decl);
+            }
+            stack = s.prev.cloned();
+        }
+
+        None
+    }
 }
 
 /// Common state that is required when demangling a mangled symbol's parsed AST.
@@ -207,6 +308,10 @@ pub struct DemangleContext<'a, W>
     // The last byte written to `out`, if any.
     last_byte_written: Option<u8>,
 
+    // The value of `bytes_written` just after the last newline was written,
+    // or zero if none has been. Used to compute the current column.
+    line_start: usize,
+
     // Any time we start demangling an entry from the substitutions table, we
     // mark its corresponding bit here. Before we begin demangling such an
     // entry, we check whether the bit is set. If it is set, then we have
@@ -217,6 +322,30 @@ pub struct DemangleContext<'a, W>
     // always backwards mean that there can't be cycles? Alternatively, is that
     // check too strict, and should it be relaxed?
     mark_bits: FixedBitSet,
+
+    // The options controlling how we demangle.
+    options: DemangleOptions,
+
+    // How many `<template-args>` we are currently nested within.
+    template_depth: usize,
+
+    // While expanding a `Dp` pack expansion, which element of the pack is
+    // being printed. A template parameter that refers to an argument pack
+    // prints just that element.
+    pack_index: Option<usize>,
+
+    // The caller's hook to tell where each `MarkupRegion` starts and ends, if
+    // any.
+    markup: Option<MarkupHook<'a>>,
+}
+
+// A markup hook is a closure, so it can't derive `Debug` itself.
+struct MarkupHook<'a>(&'a mut FnMut(Markup, &mut io::Write) -> io::Result<()>);
+
+impl<'a> fmt::Debug for MarkupHook<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "MarkupHook")
+    }
 }
 
 impl<'a, W> io::Write for DemangleContext<'a, W>
@@ -229,6 +358,9 @@ impl<'a, W> io::Write for DemangleContext<'a, W>
 
         self.out.write(buf).map(|n| {
             self.last_byte_written = buf.last().cloned();
+            if let Some(idx) = buf[..n].iter().rposition(|&b| b == b'\n') {
+                self.line_start = self.bytes_written + idx + 1;
+            }
             self.bytes_written += n;
             n
         })
@@ -242,21 +374,70 @@ impl<'a, W> io::Write for DemangleContext<'a, W>
 impl<'a, W> DemangleContext<'a, W>
     where W: io::Write
 {
-    /// Construct a new `DemangleContext`.
+    /// Construct a new `DemangleContext` with the default options.
     pub fn new(subs: &'a SubstitutionTable,
                input: &'a [u8],
                out: W)
                -> DemangleContext<'a, W> {
+        DemangleContext::new_with_options(subs, input, out, Default::default())
+    }
+
+    /// Construct a new `DemangleContext` with the given options.
+    pub fn new_with_options(subs: &'a SubstitutionTable,
+                            input: &'a [u8],
+                            out: W,
+                            options: DemangleOptions)
+                            -> DemangleContext<'a, W> {
         DemangleContext {
             subs: subs,
             input: input,
             out: out,
             bytes_written: 0,
             last_byte_written: None,
+            line_start: 0,
             mark_bits: FixedBitSet::with_capacity(subs.len()),
+            options: options,
+            template_depth: 0,
+            pack_index: None,
+            markup: None,
+        }
+    }
+
+    /// The number of bytes of demangled output written so far, not counting
+    /// any markup.
+    pub fn bytes_written(&self) -> usize {
+        self.bytes_written
+    }
+
+    /// Call `hook` at the start and end of each `MarkupRegion` demangled with
+    /// this context, with the output that the hook may write markers to.
+    pub fn set_markup(&mut self,
+                      hook: &'a mut FnMut(Markup, &mut io::Write) -> io::Result<()>) {
+        self.markup = Some(MarkupHook(hook));
+    }
+
+    // Markers go straight to the output, bypassing our `Write` impl, so that
+    // they don't count towards the line width or the last byte written.
+    fn markup(&mut self, markup: Markup) -> io::Result<()> {
+        match self.markup {
+            Some(MarkupHook(ref mut hook)) => hook(markup, &mut self.out),
+            None => Ok(()),
         }
     }
 
+    fn demangle_region<D>(&mut self,
+                          region: MarkupRegion,
+                          inner: &D,
+                          stack: Option<ArgStack>)
+                          -> DemangleResult<()>
+        where D: ?Sized + Demangle
+    {
+        try!(self.markup(Markup::Start(region)));
+        try!(inner.demangle(self, stack));
+        try!(self.markup(Markup::End(region)));
+        Ok(())
+    }
+
     fn set_mark_bit(&mut self, idx: usize) {
         self.mark_bits.set(idx, true);
     }
@@ -269,6 +450,36 @@ impl<'a, W> DemangleContext<'a, W>
         self.mark_bits[idx]
     }
 
+    /// The column that the next byte will be written at, counting from zero
+    /// at the start of the current line.
+    pub fn column(&self) -> usize {
+        self.bytes_written - self.line_start
+    }
+
+    // Write the separator between two arguments in a list. If the current
+    // line is already at least `max_width` wide, break it after the comma.
+    fn write_arg_separator(&mut self) -> io::Result<()> {
+        match self.options.max_width {
+            Some(max_width) if self.column() >= max_width => {
+                try!(write!(self, ",\n"));
+            }
+            _ => try!(write!(self, ", ")),
+        }
+        Ok(())
+    }
+
+    // The stack to resolve template parameters against: none at all when they
+    // are printed as they were mangled.
+    fn template_arg_stack<'s, 't>(&self,
+                                  stack: Option<ArgStack<'s, 't>>)
+                                  -> Option<ArgStack<'s, 't>> {
+        if self.options.raw_template_params {
+            None
+        } else {
+            stack
+        }
+    }
+
     fn ensure_space(&mut self) -> io::Result<()> {
         if let Some(b' ') = self.last_byte_written {
             Ok(())
@@ -277,6 +488,53 @@ impl<'a, W> DemangleContext<'a, W>
             Ok(())
         }
     }
+
+    // Every spelling that depends on `options.flavor` is written by one of the
+    // methods below, so that the differences between flavors live in one place.
+
+    fn write_nullptr_type(&mut self) -> io::Result<()> {
+        match self.options.flavor {
+            Flavor::GnuV3 => write!(self, "decltype(nullptr)"),
+            Flavor::Llvm => write!(self, "std::nullptr_t"),
+        }
+    }
+
+    fn write_unnamed_type(&mut self, number: Option<usize>) -> io::Result<()> {
+        match (self.options.flavor, number) {
+            (Flavor::GnuV3, _) => {
+                write!(self, "{{unnamed type#{}}}", number.map_or(1, |n| n + 2))
+            }
+            (Flavor::Llvm, Some(n)) => write!(self, "'unnamed{}'", n),
+            (Flavor::Llvm, None) => write!(self, "'unnamed'"),
+        }
+    }
+
+    fn write_lambda_prefix(&mut self, number: Option<usize>) -> io::Result<()> {
+        match (self.options.flavor, number) {
+            (Flavor::GnuV3, _) => write!(self, "{{lambda"),
+            (Flavor::Llvm, Some(n)) => write!(self, "'lambda{}'", n),
+            (Flavor::Llvm, None) => write!(self, "'lambda'"),
+        }
+    }
+
+    fn write_lambda_suffix(&mut self, number: Option<usize>) -> io::Result<()> {
+        match self.options.flavor {
+            Flavor::GnuV3 => write!(self, "#{}}}", number.map_or(1, |n| n + 2)),
+            Flavor::Llvm => Ok(()),
+        }
+    }
+
+    fn write_template_param_decl_name(&mut self,
+                                      kind: &str,
+                                      index: usize,
+                                      position: usize)
+                                      -> io::Result<()> {
+        match (self.options.flavor, index) {
+            (Flavor::GnuV3, _) => write!(self, "${}{}", kind, position),
+            (Flavor::Llvm, 0) => write!(self, "${}", kind),
+            (Flavor::Llvm, n) => write!(self, "${}{}", kind, n - 1),
+        }
+    }
 }
 
 /// Any AST node that can be printed in a demangled form.
@@ -286,7 +544,7 @@ pub trait Demangle {
     fn demangle<W>(&self,
                    ctx: &mut DemangleContext<W>,
                    stack: Option<ArgStack>)
-                   -> io::Result<()>
+                   -> DemangleResult<()>
         where W: io::Write;
 }
 
@@ -295,7 +553,7 @@ impl Demangle for str {
     fn demangle<W>(&self,
                    ctx: &mut DemangleContext<W>,
                    _: Option<ArgStack>)
-                   -> io::Result<()>
+                   -> DemangleResult<()>
         where W: io::Write
     {
         try!(write!(ctx, "{}", self));
@@ -319,7 +577,7 @@ pub trait DemangleWithInner {
                                  inner: Option<&D>,
                                  ctx: &mut DemangleContext<W>,
                                  stack: Option<ArgStack>)
-                                 -> io::Result<()>
+                                 -> DemangleResult<()>
         where D: ?Sized + Demangle,
               W: io::Write;
 }
@@ -330,7 +588,7 @@ impl<D> Demangle for D
     fn demangle<W>(&self,
                    ctx: &mut DemangleContext<W>,
                    stack: Option<ArgStack>)
-                   -> io::Result<()>
+                   -> DemangleResult<()>
         where W: io::Write
     {
         let inner: Option<&str> = None;
@@ -350,7 +608,7 @@ impl<'a, 'b, T, U> Demangle for Concat<'a, 'b, T, U>
     fn demangle<W>(&self,
                    ctx: &mut DemangleContext<W>,
                    stack: Option<ArgStack>)
-                   -> io::Result<()>
+                   -> DemangleResult<()>
         where W: io::Write
     {
         try!(self.0.demangle(ctx, stack));
@@ -364,7 +622,7 @@ impl<'a> Demangle for FunctionArgList<'a> {
     fn demangle<W>(&self,
                    ctx: &mut DemangleContext<W>,
                    stack: Option<ArgStack>)
-                   -> io::Result<()>
+                   -> DemangleResult<()>
         where W: io::Write
     {
         try!(write!(ctx, "("));
@@ -378,10 +636,18 @@ impl<'a> Demangle for FunctionArgList<'a> {
 
         let mut need_comma = false;
         for arg in self.0 {
+            // A pack expansion of an empty pack contributes no arguments at
+            // all, so it must not contribute a separator either.
+            if let Some(&Type::PackExpansion(ref pattern)) = ctx.subs.get_type(arg) {
+                let arg_stack = ctx.template_arg_stack(stack);
+                if pack_expansion_length(ctx.subs, pattern, arg_stack) == Some(0) {
+                    continue;
+                }
+            }
             if need_comma {
-                try!(write!(ctx, ", "));
+                try!(ctx.write_arg_separator());
             }
-            try!(arg.demangle(ctx, stack));
+            try!(ctx.demangle_region(MarkupRegion::Type, arg, stack));
             need_comma = true;
         }
 
@@ -390,6 +656,210 @@ impl<'a> Demangle for FunctionArgList<'a> {
     }
 }
 
+/// The two symbols being compared by `SemanticEq`: the substitution table and
+/// raw input that each one's back references and identifiers point into.
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct SemanticEqContext<'a> {
+    lhs_subs: &'a SubstitutionTable,
+    lhs_input: &'a [u8],
+    rhs_subs: &'a SubstitutionTable,
+    rhs_input: &'a [u8],
+}
+
+impl<'a> SemanticEqContext<'a> {
+    /// Construct a new `SemanticEqContext` for comparing AST nodes from the
+    /// symbol parsed from `lhs_input` with those from the symbol parsed from
+    /// `rhs_input`.
+    pub fn new(lhs_subs: &'a SubstitutionTable,
+               lhs_input: &'a [u8],
+               rhs_subs: &'a SubstitutionTable,
+               rhs_input: &'a [u8])
+               -> SemanticEqContext<'a> {
+        SemanticEqContext {
+            lhs_subs: lhs_subs,
+            lhs_input: lhs_input,
+            rhs_subs: rhs_subs,
+            rhs_input: rhs_input,
+        }
+    }
+}
+
+/// Structural equality of AST nodes that looks through back references to the
+/// components they refer to, and compares identifiers and literals by their
+/// bytes rather than their offsets into the input. Unlike `PartialEq`, this
+/// considers the same entity mangled with and without substitutions equal,
+/// which is useful for deduplicating symbols.
+#[doc(hidden)]
+pub trait SemanticEq {
+    /// Is this AST node, from the left hand side of `ctx`, semantically equal
+    /// to `other`, from its right hand side?
+    fn semantic_eq(&self, ctx: &SemanticEqContext, other: &Self) -> bool;
+}
+
+impl<T> SemanticEq for Box<T>
+    where T: SemanticEq
+{
+    #[inline]
+    fn semantic_eq(&self, ctx: &SemanticEqContext, other: &Box<T>) -> bool {
+        (**self).semantic_eq(ctx, &**other)
+    }
+}
+
+impl<T> SemanticEq for Option<T>
+    where T: SemanticEq
+{
+    fn semantic_eq(&self, ctx: &SemanticEqContext, other: &Option<T>) -> bool {
+        match (self, other) {
+            (&Some(ref lhs), &Some(ref rhs)) => lhs.semantic_eq(ctx, rhs),
+            (&None, &None) => true,
+            _ => false,
+        }
+    }
+}
+
+impl<T> SemanticEq for Vec<T>
+    where T: SemanticEq
+{
+    fn semantic_eq(&self, ctx: &SemanticEqContext, other: &Vec<T>) -> bool {
+        self.len() == other.len() &&
+        self.iter().zip(other.iter()).all(|(lhs, rhs)| lhs.semantic_eq(ctx, rhs))
+    }
+}
+
+/// Implement `SemanticEq` in terms of `PartialEq` for AST nodes that contain
+/// neither back references nor offsets into the input.
+macro_rules! semantic_eq_by_partial_eq {
+    ( $( $typename:ty ),* ) => {
+        $(
+            impl SemanticEq for $typename {
+                #[inline]
+                fn semantic_eq(&self, _: &SemanticEqContext, other: &$typename) -> bool {
+                    self == other
+                }
+            }
+        )*
+    }
+}
+
+semantic_eq_by_partial_eq!(usize,
+                           CallOffset,
+                           CtorDtorName,
+                           CvQualifiers,
+                           Discriminator,
+                           FixedPointType,
+                           FunctionParam,
+                           OperatorName,
+                           RefQualifier,
+                           StandardBuiltinType,
+                           TemplateParam,
+                           UnnamedTypeName,
+                           WellKnownComponent);
+
+/// The result of remangling an AST node: `Err(())` if the node, or one of
+/// its children, is a construct that `Remangle` does not support yet.
+#[doc(hidden)]
+pub type RemangleResult = ::std::result::Result<(), ()>;
+
+/// The state threaded through `Remangle`: the parsed symbol's substitution
+/// table and raw input, the mangled bytes written so far, and which
+/// substitutions have already been written out in full.
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct RemangleContext<'a> {
+    subs: &'a SubstitutionTable,
+    input: &'a [u8],
+    out: Vec<u8>,
+    emitted: Vec<bool>,
+}
+
+impl<'a> RemangleContext<'a> {
+    /// Construct a new `RemangleContext` for the symbol parsed from `input`.
+    pub fn new(subs: &'a SubstitutionTable, input: &'a [u8]) -> RemangleContext<'a> {
+        RemangleContext {
+            subs: subs,
+            input: input,
+            out: vec![],
+            emitted: vec![false; subs.len()],
+        }
+    }
+
+    /// Consume the context, returning the mangled bytes written so far.
+    pub fn finish(self) -> Vec<u8> {
+        self.out
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.out.extend_from_slice(bytes);
+    }
+
+    // The first time a substitution is reached, write out what it refers to.
+    // Every time after that, write a back reference to it instead.
+    fn remangle_back_reference(&mut self, idx: usize) -> RemangleResult {
+        if !self.subs.contains(idx) {
+            return Err(());
+        }
+
+        if !self.emitted[idx] {
+            let subs = self.subs;
+            try!(subs[idx].remangle(self));
+            self.emitted[idx] = true;
+            return Ok(());
+        }
+
+        self.write(b"S");
+        if idx > 0 {
+            // The `<seq-id>` is the index minus one, in upper case base 36.
+            let mut digits = vec![];
+            let mut n = idx - 1;
+            loop {
+                digits.push(b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ"[n % 36]);
+                n /= 36;
+                if n == 0 {
+                    break;
+                }
+            }
+            digits.reverse();
+            self.write(&digits);
+        }
+        self.write(b"_");
+        Ok(())
+    }
+}
+
+/// Serialize an AST node back into its mangled form, the inverse of `Parse`.
+///
+/// Back references are written out in full the first time they are reached,
+/// and as `S <seq-id> _` substitutions after that. The parser numbers
+/// substitutions in the order it completes them, which is the order they are
+/// reached here, so remangling a parsed symbol regenerates the same
+/// substitutions that it was mangled with.
+#[doc(hidden)]
+pub trait Remangle {
+    /// Write the mangled form of this AST node to `ctx`.
+    fn remangle(&self, ctx: &mut RemangleContext) -> RemangleResult;
+}
+
+impl<T> Remangle for Box<T>
+    where T: Remangle
+{
+    #[inline]
+    fn remangle(&self, ctx: &mut RemangleContext) -> RemangleResult {
+        (**self).remangle(ctx)
+    }
+}
+
+impl<T> Remangle for Vec<T>
+    where T: Remangle
+{
+    fn remangle(&self, ctx: &mut RemangleContext) -> RemangleResult {
+        for item in self {
+            try!(item.remangle(ctx));
+        }
+        Ok(())
+    }
+}
+
 /// Define a handle to a AST type that lives inside the substitution table. A
 /// handle is always either an index into the substitution table, or it is a
 /// reference to a "well-known" component.
@@ -400,6 +870,9 @@ impl<'a> Demangle for FunctionArgList<'a> {
 ///   reference to a "well-known" component
 /// - a `Demangle` impl that proxies to the appropriate `Substitutable` in the
 ///   `SubstitutionTable`
+/// - a `SemanticEq` impl that compares the `Substitutable`s that back
+///   references refer to, rather than their indices
+/// - a `Remangle` impl that writes back references as substitutions
 macro_rules! define_handle {
     (
         $(#[$attr:meta])*
@@ -444,20 +917,32 @@ macro_rules! define_handle {
                     _ => None,
                 }
             }
+
+            // Construct a `BackReference` to the given index while parsing.
+            // Substitutions are numbered in parse order, so the index must
+            // already be in the table; catch any grammar-handling bug that
+            // mis-numbers entries early in debug builds.
+            #[inline]
+            fn new_back_reference(subs: &SubstitutionTable, idx: usize) -> $typename {
+                debug_assert!(subs.contains(idx),
+                              "back reference @ {} is out of bounds of the substitution table (len {})",
+                              idx,
+                              subs.len());
+                $typename::BackReference(idx)
+            }
         }
 
         impl Demangle for $typename {
             fn demangle<W>(&self,
                            ctx: &mut DemangleContext<W>, stack: Option<ArgStack>)
-                           -> io::Result<()>
+                           -> DemangleResult<()>
                 where W: io::Write
             {
                 match *self {
                     $typename::WellKnown(ref comp) => comp.demangle(ctx, stack),
                     $typename::BackReference(idx) => {
                         if ctx.mark_bit_is_set(idx) {
-                            return Err(io::Error::new(io::ErrorKind::Other,
-                                                      error::Error::RecursiveDemangling.description()));
+                            return Err(error::Error::RecursiveDemangling.into());
                         }
 
                         ctx.set_mark_bit(idx);
@@ -471,9 +956,84 @@ macro_rules! define_handle {
                 }
             }
         }
+
+        impl SemanticEq for $typename {
+            fn semantic_eq(&self, ctx: &SemanticEqContext, other: &$typename) -> bool {
+                match (self, other) {
+                    (&$typename::WellKnown(ref lhs), &$typename::WellKnown(ref rhs)) => {
+                        lhs == rhs
+                    }
+                    (&$typename::BackReference(lhs), &$typename::BackReference(rhs)) => {
+                        ctx.lhs_subs[lhs].semantic_eq(ctx, &ctx.rhs_subs[rhs])
+                    }
+                    $(
+                        (&$typename::$extra_variant(ref lhs),
+                         &$typename::$extra_variant(ref rhs)) => lhs.semantic_eq(ctx, rhs),
+                    )*
+                    _ => false,
+                }
+            }
+        }
+
+        impl Remangle for $typename {
+            fn remangle(&self, ctx: &mut RemangleContext) -> RemangleResult {
+                match *self {
+                    $typename::WellKnown(ref comp) => comp.remangle(ctx),
+                    $typename::BackReference(idx) => ctx.remangle_back_reference(idx),
+                    $(
+                        $typename::$extra_variant(ref extra) => extra.remangle(ctx),
+                    )*
+                }
+            }
+        }
     };
 }
 
+/// A reference to a component that was parsed, but which is not a
+/// substitution candidate, and therefore lives apart from the substitution
+/// table's numbered components.
+///
+/// For example, the last component of a `<nested-name>`'s `<prefix>` is not a
+/// substitution candidate: in `_ZN1a1bEN1c1dES0_`, `S0_` refers to `c`, not to
+/// `a::b`.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct NonSubstitution(pub usize);
+
+impl Demangle for NonSubstitution {
+    fn demangle<W>(&self,
+                   ctx: &mut DemangleContext<W>,
+                   stack: Option<ArgStack>)
+                   -> DemangleResult<()>
+        where W: io::Write
+    {
+        let subs = ctx.subs;
+        match subs.non_substitution(self.0) {
+            Some(entity) => entity.demangle(ctx, stack),
+            None => Err(error::Error::BadBackReference.into()),
+        }
+    }
+}
+
+impl SemanticEq for NonSubstitution {
+    fn semantic_eq(&self, ctx: &SemanticEqContext, other: &NonSubstitution) -> bool {
+        match (ctx.lhs_subs.non_substitution(self.0),
+               ctx.rhs_subs.non_substitution(other.0)) {
+            (Some(lhs), Some(rhs)) => lhs.semantic_eq(ctx, rhs),
+            _ => false,
+        }
+    }
+}
+
+impl Remangle for NonSubstitution {
+    fn remangle(&self, ctx: &mut RemangleContext) -> RemangleResult {
+        let subs = ctx.subs;
+        match subs.non_substitution(self.0) {
+            Some(entity) => entity.remangle(ctx),
+            None => Err(()),
+        }
+    }
+}
+
 /// Define a "vocabulary" nonterminal, something like `OperatorName` or
 /// `CtorDtorName` that's basically a big list of constant strings.
 ///
@@ -483,6 +1043,9 @@ macro_rules! define_handle {
 /// - a `Parse` impl
 /// - a `StartsWith` impl
 /// - a `Demangle` impl
+/// - a `Remangle` impl
+/// - a `vocabulary` method listing every variant with its mangled and
+///   printable forms
 ///
 /// See the definition of `CTorDtorName` for an example of its use.
 macro_rules! define_vocabulary {
@@ -498,6 +1061,19 @@ macro_rules! define_vocabulary {
             ),*
         }
 
+        impl $typename {
+            /// Every variant, along with its mangled form and the spelling it
+            /// is demangled to, in the order they are tried when parsing.
+            pub fn vocabulary() -> &'static [($typename, &'static [u8], &'static str)] {
+                static VOCABULARY: &'static [($typename, &'static [u8], &'static str)] = &[
+                    $(
+                        ($typename::$variant, $mangled, $printable)
+                    ),*
+                ];
+                VOCABULARY
+            }
+        }
+
         impl Parse for $typename {
             fn parse<'a, 'b>(_subs: &'a mut SubstitutionTable,
                              input: IndexStr<'b>)
@@ -528,14 +1104,26 @@ macro_rules! define_vocabulary {
         impl Demangle for $typename {
             fn demangle<W>(&self,
                            ctx: &mut DemangleContext<W>, _: Option<ArgStack>)
-                           -> io::Result<()>
+                           -> DemangleResult<()>
                 where W: io::Write
             {
-                write!(ctx, "{}", match *self {
+                try!(write!(ctx, "{}", match *self {
                     $(
                         $typename::$variant => $printable
                     ),*
-                })
+                }));
+                Ok(())
+            }
+        }
+
+        impl Remangle for $typename {
+            fn remangle(&self, ctx: &mut RemangleContext) -> RemangleResult {
+                ctx.write(match *self {
+                    $(
+                        $typename::$variant => &$mangled[..]
+                    ),*
+                });
+                Ok(())
             }
         }
 
@@ -567,29 +1155,52 @@ pub enum MangledName {
     /// A top-level type. Technically not allowed by the standard, however in
     /// practice this can happen, and is tested for by libiberty.
     Type(TypeHandle),
+
+    /// A GCC static initialization or destruction function. Not part of the
+    /// standard either, but libiberty demangles these too.
+    GlobalCtorDtor(GlobalCtorDtor),
 }
 
 impl Parse for MangledName {
     fn parse<'a, 'b>(subs: &'a mut SubstitutionTable,
                      input: IndexStr<'b>)
                      -> Result<(MangledName, IndexStr<'b>)> {
+        parse_with_span("MangledName", subs, input, MangledName::parse_without_span)
+    }
+}
+
+impl MangledName {
+    fn parse_without_span<'a, 'b>(subs: &'a mut SubstitutionTable,
+                                  input: IndexStr<'b>)
+                                  -> Result<(MangledName, IndexStr<'b>)> {
         log_parse!("MangledName", input);
 
+        if input.as_ref().starts_with(b"_GLOBAL_") {
+            let (global, tail) = try!(GlobalCtorDtor::parse(subs, input));
+            return Ok((MangledName::GlobalCtorDtor(global), tail));
+        }
+
         // The _Z from the spec is really just a suggestion... Sometimes there
         // is an extra leading underscore (like what we get out of `nm`) and
         // sometimes it appears to be completely missing, if libiberty tests are
-        // to be trusted...
-        let tail = if let Ok(tail) = consume(b"__Z", input) {
-            tail
+        // to be trusted... Only a single extra underscore is accepted, since
+        // that is all that Mach-O's global symbol prefix adds, and `___Z` names
+        // (like Apple's block invocation functions) are not mangled encodings.
+        let prefixed_tail = if let Ok(tail) = consume(b"__Z", input) {
+            Some(tail)
         } else {
-            if let Ok(tail) = consume(b"_Z", input) {
-                tail
-            } else {
-                input
-            }
+            consume(b"_Z", input).ok()
         };
 
-        if let Ok((encoding, tail)) = Encoding::parse(subs, tail) {
+        if let Some(tail) = prefixed_tail {
+            // Once we have seen the prefix, this must be an encoding, and if
+            // we ran out of input while parsing it, we report that rather than
+            // complaining that the prefix itself is not a type.
+            let (encoding, tail) = try!(Encoding::parse(subs, tail));
+            return Ok((MangledName::Encoding(encoding), tail));
+        }
+
+        if let Ok((encoding, tail)) = Encoding::parse(subs, input) {
             return Ok((MangledName::Encoding(encoding), tail))
         };
 
@@ -599,31 +1210,320 @@ impl Parse for MangledName {
     }
 }
 
+impl MangledName {
+    /// If this mangled name is a constructor or destructor, get which kind of
+    /// constructor or destructor it is.
+    #[doc(hidden)]
+    pub fn ctor_dtor_kind(&self, subs: &SubstitutionTable) -> Option<CtorDtorKind> {
+        match self.get_leaf_name(subs) {
+            Some(&UnqualifiedName::CtorDtor(ref ctor_dtor)) => Some(ctor_dtor.kind()),
+            _ => None,
+        }
+    }
+
+    /// Is this mangled name a function or variable declared within the given
+    /// scope, such as `["space", "Foo"]` for `space::Foo::bar`?
+    ///
+    /// The entity's own name is not part of its scope, and special names and
+    /// top-level types are not declared within any scope.
+    #[doc(hidden)]
+    pub fn matches_scope(&self,
+                         subs: &SubstitutionTable,
+                         input: &[u8],
+                         scope: &[&[u8]])
+                         -> bool {
+        match *self {
+            MangledName::Encoding(Encoding::Function(ref name, _, _)) |
+            MangledName::Encoding(Encoding::Data(ref name)) => {
+                name.match_enclosing_scope(subs, input, scope)
+                    .map_or(false, |rest| rest.is_empty())
+            }
+            _ => false,
+        }
+    }
+
+    /// If this mangled name is a function whose return type is mangled,
+    /// demangle just its return type and return `true`. Otherwise, write
+    /// nothing and return `false`.
+    #[doc(hidden)]
+    pub fn demangle_return_type<W>(&self,
+                                   ctx: &mut DemangleContext<W>,
+                                   stack: Option<ArgStack>)
+                                   -> DemangleResult<bool>
+        where W: io::Write
+    {
+        match *self {
+            MangledName::Encoding(ref enc) => enc.demangle_return_type(ctx, stack),
+            MangledName::Type(_) |
+            MangledName::GlobalCtorDtor(_) => Ok(false),
+        }
+    }
+
+    /// If this mangled name is a local entity, demangle just the function it
+    /// is declared within and return `true`. Otherwise, write nothing and
+    /// return `false`.
+    #[doc(hidden)]
+    pub fn demangle_enclosing_function<W>(&self,
+                                          ctx: &mut DemangleContext<W>,
+                                          stack: Option<ArgStack>)
+                                          -> DemangleResult<bool>
+        where W: io::Write
+    {
+        let local = match *self {
+            MangledName::Encoding(Encoding::Function(Name::Local(ref local), _, _)) |
+            MangledName::Encoding(Encoding::Data(Name::Local(ref local))) => local,
+            _ => return Ok(false),
+        };
+
+        try!(local.enclosing_encoding().demangle(ctx, stack));
+        Ok(true)
+    }
+
+    /// If this mangled name is a template, demangle just its template
+    /// arguments and return `true`. Otherwise, write nothing and return
+    /// `false`.
+    #[doc(hidden)]
+    pub fn demangle_template_args<W>(&self,
+                                     ctx: &mut DemangleContext<W>,
+                                     stack: Option<ArgStack>)
+                                     -> DemangleResult<bool>
+        where W: io::Write
+    {
+        match *self {
+            MangledName::Encoding(ref enc) => enc.demangle_template_args(ctx, stack),
+            MangledName::Type(_) |
+            MangledName::GlobalCtorDtor(_) => Ok(false),
+        }
+    }
+}
+
+impl GetLeafName for MangledName {
+    fn get_leaf_name<'a>(&'a self,
+                         subs: &'a SubstitutionTable)
+                         -> Option<&'a UnqualifiedName> {
+        match *self {
+            MangledName::Encoding(ref enc) => enc.get_leaf_name(subs),
+            MangledName::Type(_) |
+            MangledName::GlobalCtorDtor(_) => None,
+        }
+    }
+}
+
 impl Demangle for MangledName {
     fn demangle<W>(&self,
                    ctx: &mut DemangleContext<W>,
                    stack: Option<ArgStack>)
-                   -> io::Result<()>
+                   -> DemangleResult<()>
         where W: io::Write
     {
         match *self {
             MangledName::Encoding(ref enc)=> enc.demangle(ctx, stack),
             MangledName::Type(ref ty) => ty.demangle(ctx, stack),
+            MangledName::GlobalCtorDtor(ref global) => global.demangle(ctx, stack),
         }
     }
 }
 
-/// The `<encoding>` production.
-///
-/// ```text
-/// <encoding> ::= <function name> <bare-function-type>
-///            ::= <data name>
-///            ::= <special-name>
-/// ```
-#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+impl SemanticEq for MangledName {
+    fn semantic_eq(&self, ctx: &SemanticEqContext, other: &MangledName) -> bool {
+        match (self, other) {
+            (&MangledName::Encoding(ref lhs), &MangledName::Encoding(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&MangledName::Type(ref lhs), &MangledName::Type(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&MangledName::GlobalCtorDtor(ref lhs), &MangledName::GlobalCtorDtor(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Remangle for MangledName {
+    fn remangle(&self, ctx: &mut RemangleContext) -> RemangleResult {
+        match *self {
+            MangledName::Encoding(ref enc) => {
+                ctx.write(b"_Z");
+                enc.remangle(ctx)
+            }
+            MangledName::Type(ref ty) => ty.remangle(ctx),
+            MangledName::GlobalCtorDtor(_) => Err(()),
+        }
+    }
+}
+
+/// The names GCC gives to the functions that run a translation unit's static
+/// constructors and destructors. They are keyed to either a mangled symbol or
+/// the name of the source file.
+///
+/// ```text
+/// <global-ctor-dtor> ::= _GLOBAL_ [._$] [sub_] I _ <key>
+///                    ::= _GLOBAL_ [._$] [sub_] D _ <key>
+/// <key> ::= <mangled-name>
+///       ::= <file name>
+/// ```
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub enum GlobalCtorDtor {
+    /// `global constructors keyed to <key>`
+    Ctor(GlobalCtorDtorKey),
+
+    /// `global destructors keyed to <key>`
+    Dtor(GlobalCtorDtorKey),
+}
+
+impl Parse for GlobalCtorDtor {
+    fn parse<'a, 'b>(subs: &'a mut SubstitutionTable,
+                     input: IndexStr<'b>)
+                     -> Result<(GlobalCtorDtor, IndexStr<'b>)> {
+        log_parse!("GlobalCtorDtor", input);
+
+        let tail = try!(consume(b"_GLOBAL_", input));
+        let tail = match tail.peek() {
+            Some(b'.') | Some(b'_') | Some(b'$') => tail.range_from(1..),
+            Some(_) => return Err(error::Error::UnexpectedText),
+            None => return Err(error::Error::UnexpectedEnd),
+        };
+        let tail = consume(b"sub_", tail).unwrap_or(tail);
+
+        let (is_ctor, tail) = match tail.try_split_at(2) {
+            Some((head, tail)) if head.as_ref() == b"I_" => (true, tail),
+            Some((head, tail)) if head.as_ref() == b"D_" => (false, tail),
+            Some(_) => return Err(error::Error::UnexpectedText),
+            None => return Err(error::Error::UnexpectedEnd),
+        };
+
+        let (key, tail) = try!(GlobalCtorDtorKey::parse(subs, tail));
+        if is_ctor {
+            Ok((GlobalCtorDtor::Ctor(key), tail))
+        } else {
+            Ok((GlobalCtorDtor::Dtor(key), tail))
+        }
+    }
+}
+
+impl Demangle for GlobalCtorDtor {
+    fn demangle<W>(&self,
+                   ctx: &mut DemangleContext<W>,
+                   stack: Option<ArgStack>)
+                   -> DemangleResult<()>
+        where W: io::Write
+    {
+        match *self {
+            GlobalCtorDtor::Ctor(ref key) => {
+                try!(write!(ctx, "global constructors keyed to "));
+                key.demangle(ctx, stack)
+            }
+            GlobalCtorDtor::Dtor(ref key) => {
+                try!(write!(ctx, "global destructors keyed to "));
+                key.demangle(ctx, stack)
+            }
+        }
+    }
+}
+
+impl SemanticEq for GlobalCtorDtor {
+    fn semantic_eq(&self, ctx: &SemanticEqContext, other: &GlobalCtorDtor) -> bool {
+        match (self, other) {
+            (&GlobalCtorDtor::Ctor(ref lhs), &GlobalCtorDtor::Ctor(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&GlobalCtorDtor::Dtor(ref lhs), &GlobalCtorDtor::Dtor(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// What a `GlobalCtorDtor` is keyed to.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub enum GlobalCtorDtorKey {
+    /// A mangled symbol, such as `_Z2fnv`.
+    MangledName(Box<MangledName>),
+
+    /// Anything else, usually the name of the source file, such as `main.cpp`.
+    FileName(Identifier),
+}
+
+impl Parse for GlobalCtorDtorKey {
+    fn parse<'a, 'b>(subs: &'a mut SubstitutionTable,
+                     input: IndexStr<'b>)
+                     -> Result<(GlobalCtorDtorKey, IndexStr<'b>)> {
+        log_parse!("GlobalCtorDtorKey", input);
+
+        // Only try to parse a mangled key if it has the prefix, since plenty
+        // of file names would otherwise parse as bare types.
+        if input.as_ref().starts_with(b"_Z") || input.as_ref().starts_with(b"__Z") {
+            if let Ok((name, tail)) = MangledName::parse(subs, input) {
+                return Ok((GlobalCtorDtorKey::MangledName(Box::new(name)), tail));
+            }
+        }
+
+        let end = input.as_ref()
+            .iter()
+            .map(|&c| c as char)
+            .take_while(|&c| c == '_' || c == '.' || c == '$' || c == '-' || c.is_digit(36))
+            .count();
+        if end == 0 {
+            return if input.is_empty() {
+                Err(error::Error::UnexpectedEnd)
+            } else {
+                Err(error::Error::UnexpectedText)
+            };
+        }
+
+        let tail = input.range_from(end..);
+        let identifier = Identifier {
+            start: input.index(),
+            end: tail.index(),
+        };
+        Ok((GlobalCtorDtorKey::FileName(identifier), tail))
+    }
+}
+
+impl Demangle for GlobalCtorDtorKey {
+    fn demangle<W>(&self,
+                   ctx: &mut DemangleContext<W>,
+                   stack: Option<ArgStack>)
+                   -> DemangleResult<()>
+        where W: io::Write
+    {
+        match *self {
+            GlobalCtorDtorKey::MangledName(ref name) => name.demangle(ctx, stack),
+            GlobalCtorDtorKey::FileName(ref name) => name.demangle(ctx, stack),
+        }
+    }
+}
+
+impl SemanticEq for GlobalCtorDtorKey {
+    fn semantic_eq(&self, ctx: &SemanticEqContext, other: &GlobalCtorDtorKey) -> bool {
+        match (self, other) {
+            (&GlobalCtorDtorKey::MangledName(ref lhs),
+             &GlobalCtorDtorKey::MangledName(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&GlobalCtorDtorKey::FileName(ref lhs), &GlobalCtorDtorKey::FileName(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The `<encoding>` production.
+///
+/// ```text
+/// <encoding> ::= <function name> <bare-function-type> [Q <requires-clause expression>]
+///            ::= <data name>
+///            ::= <special-name>
+/// ```
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
 pub enum Encoding {
-    /// An encoded function.
-    Function(Name, BareFunctionType),
+    /// An encoded function, along with its trailing requires-clause, if the
+    /// function is constrained, as in `void f(T) requires C<T>`.
+    Function(Name, BareFunctionType, Option<Box<Expression>>),
 
     /// An encoded static variable.
     Data(Name),
@@ -636,18 +1536,140 @@ impl Parse for Encoding {
     fn parse<'a, 'b>(subs: &'a mut SubstitutionTable,
                      input: IndexStr<'b>)
                      -> Result<(Encoding, IndexStr<'b>)> {
+        parse_with_span("Encoding", subs, input, Encoding::parse_without_span)
+    }
+}
+
+impl Encoding {
+    fn parse_without_span<'a, 'b>(subs: &'a mut SubstitutionTable,
+                                  input: IndexStr<'b>)
+                                  -> Result<(Encoding, IndexStr<'b>)> {
         log_parse!("Encoding", input);
 
-        if let Ok((name, tail)) = Name::parse(subs, input) {
-            if let Ok((ty, tail)) = BareFunctionType::parse(subs, tail) {
-                return Ok((Encoding::Function(name, ty), tail));
-            } else {
-                return Ok((Encoding::Data(name), tail));
+        let name_error = match Name::parse(subs, input) {
+            Ok((name, tail)) => {
+                match BareFunctionType::parse(subs, tail) {
+                    Ok((ty, tail)) => {
+                        let (requires, tail) = match consume(b"Q", tail) {
+                            Ok(tail) => {
+                                let (expr, tail) = try!(Expression::parse(subs, tail));
+                                (Some(Box::new(expr)), tail)
+                            }
+                            Err(_) => (None, tail),
+                        };
+                        return Ok((Encoding::Function(name, ty, requires), tail));
+                    }
+                    // A function type that runs off the end of the input is
+                    // truncated, not data followed by garbage.
+                    Err(error::Error::UnexpectedEnd) if !tail.is_empty() => {
+                        return Err(error::Error::UnexpectedEnd);
+                    }
+                    Err(_) => return Ok((Encoding::Data(name), tail)),
+                }
+            }
+            Err(error) => error,
+        };
+
+        // Only a `<special-name>` can start with `T` or `G`, so don't mask
+        // the reason we failed to parse a `<name>` otherwise.
+        match input.peek() {
+            Some(b'T') | Some(b'G') => {
+                let (name, tail) = try!(SpecialName::parse(subs, input));
+                Ok((Encoding::Special(name), tail))
+            }
+            _ => Err(name_error),
+        }
+    }
+}
+
+impl Encoding {
+    /// If this is a function whose return type is mangled, get that return
+    /// type. Only templates other than constructors and destructors have their
+    /// return types mangled, so this is `None` for any other function.
+    fn return_type(&self, subs: &SubstitutionTable) -> Option<&TypeHandle> {
+        match *self {
+            Encoding::Function(ref name, ref fun_ty, _) => {
+                if name.get_template_args(subs).is_none() {
+                    return None;
+                }
+                match name.get_leaf_name(subs) {
+                    Some(&UnqualifiedName::CtorDtor(_)) => None,
+                    _ => Some(fun_ty.ret()),
+                }
+            }
+            Encoding::Data(_) |
+            Encoding::Special(_) => None,
+        }
+    }
+
+    /// If this is a function whose return type is mangled, demangle just its
+    /// return type and return `true`. Otherwise, write nothing and return
+    /// `false`.
+    #[doc(hidden)]
+    pub fn demangle_return_type<W>(&self,
+                                   ctx: &mut DemangleContext<W>,
+                                   stack: Option<ArgStack>)
+                                   -> DemangleResult<bool>
+        where W: io::Write
+    {
+        let ret = match self.return_type(ctx.subs) {
+            Some(ret) => ret,
+            None => return Ok(false),
+        };
+
+        // The return type may refer to the function's own template arguments.
+        let stack = match *self {
+            Encoding::Function(ref name, _, _) => {
+                match name.get_template_args(ctx.subs) {
+                    Some(template_args) => stack.push(template_args),
+                    None => stack,
+                }
+            }
+            Encoding::Data(_) |
+            Encoding::Special(_) => stack,
+        };
+
+        try!(ret.demangle(ctx, stack));
+        Ok(true)
+    }
+
+    /// If this is a template function or variable, demangle just its template
+    /// arguments and return `true`. Otherwise, write nothing and return
+    /// `false`.
+    #[doc(hidden)]
+    pub fn demangle_template_args<W>(&self,
+                                     ctx: &mut DemangleContext<W>,
+                                     stack: Option<ArgStack>)
+                                     -> DemangleResult<bool>
+        where W: io::Write
+    {
+        let template_args = match *self {
+            Encoding::Function(ref name, _, _) |
+            Encoding::Data(ref name) => name.get_template_args(ctx.subs),
+            Encoding::Special(_) => None,
+        };
+
+        match template_args {
+            Some(template_args) => {
+                // The args may refer to each other, e.g. `f<int, int*>` can be
+                // mangled as `fIiPT_E`.
+                try!(template_args.demangle(ctx, stack.push(template_args)));
+                Ok(true)
             }
+            None => Ok(false),
         }
+    }
+}
 
-        let (name, tail) = try!(SpecialName::parse(subs, input));
-        Ok((Encoding::Special(name), tail))
+impl GetLeafName for Encoding {
+    fn get_leaf_name<'a>(&'a self,
+                         subs: &'a SubstitutionTable)
+                         -> Option<&'a UnqualifiedName> {
+        match *self {
+            Encoding::Function(ref name, _, _) |
+            Encoding::Data(ref name) => name.get_leaf_name(subs),
+            Encoding::Special(_) => None,
+        }
     }
 }
 
@@ -655,11 +1677,11 @@ impl Demangle for Encoding {
     fn demangle<W>(&self,
                    ctx: &mut DemangleContext<W>,
                    stack: Option<ArgStack>)
-                   -> io::Result<()>
+                   -> DemangleResult<()>
         where W: io::Write
     {
         match *self {
-            Encoding::Function(ref name, ref fun_ty) => {
+            Encoding::Function(ref name, ref fun_ty, ref requires) => {
                 // Even if this function takes no args and doesn't have a return
                 // value (see below), it will have the void parameter.
                 debug_assert!(fun_ty.0.len() >= 1);
@@ -675,31 +1697,41 @@ impl Demangle for Encoding {
                 // position do not have a return type.
                 //
                 // We know we are not printing a type, so we only need to check
-                // whether this is a template.
+                // whether this is a template (other than a constructor or
+                // destructor, which never have return types).
                 //
                 // For the details, see
                 // http://mentorembedded.github.io/cxx-abi/abi.html#mangle.function-type
-                // let args = if name.is_template_function(ctx.subs) {
-                //     try!(fun_ty.0[0].demangle(ctx));
-                let (stack, function_args) = if let Some(template_args) =
-                    name.get_template_args(ctx.subs) {
-                    let stack = stack.push(template_args);
-                    let function_args = FunctionArgList(&fun_ty.0[1..]);
-
-                    try!(fun_ty.0[0].demangle(ctx, stack));
+                let stack = match name.get_template_args(ctx.subs) {
+                    Some(template_args) => stack.push(template_args),
+                    None => stack,
+                };
+                let function_args = if let Some(ret) = self.return_type(ctx.subs) {
+                    try!(ctx.demangle_region(MarkupRegion::Type, ret, stack));
                     try!(write!(ctx, " "));
-
-                    (stack, function_args)
+                    FunctionArgList(fun_ty.args())
                 } else {
-                    (stack, FunctionArgList(&fun_ty.0[..]))
+                    FunctionArgList(&fun_ty.0[..])
                 };
 
-                if let Name::Nested(ref name) = *name {
-                    return name.demangle_with_inner(Some(&function_args), ctx, stack);
+                match *name {
+                    Name::Nested(ref name) => {
+                        try!(name.demangle_with_inner(Some(&function_args), ctx, stack));
+                    }
+                    Name::Local(ref name) => {
+                        try!(name.demangle_with_inner(Some(&function_args), ctx, stack));
+                    }
+                    _ => {
+                        try!(name.demangle(ctx, stack));
+                        try!(function_args.demangle(ctx, stack));
+                    }
                 }
 
-                try!(name.demangle(ctx, stack));
-                function_args.demangle(ctx, stack)
+                if let Some(ref requires) = *requires {
+                    try!(write!(ctx, " requires "));
+                    try!(requires.demangle(ctx, stack));
+                }
+                Ok(())
             }
             Encoding::Data(ref name) => name.demangle(ctx, stack),
             Encoding::Special(ref name) => name.demangle(ctx, stack),
@@ -707,6 +1739,38 @@ impl Demangle for Encoding {
     }
 }
 
+impl SemanticEq for Encoding {
+    fn semantic_eq(&self, ctx: &SemanticEqContext, other: &Encoding) -> bool {
+        match (self, other) {
+            (&Encoding::Function(ref lhs_name, ref lhs_fun_ty, ref lhs_requires),
+             &Encoding::Function(ref rhs_name, ref rhs_fun_ty, ref rhs_requires)) => {
+                lhs_name.semantic_eq(ctx, rhs_name) &&
+                lhs_fun_ty.semantic_eq(ctx, rhs_fun_ty) &&
+                lhs_requires.semantic_eq(ctx, rhs_requires)
+            }
+            (&Encoding::Data(ref lhs), &Encoding::Data(ref rhs)) => lhs.semantic_eq(ctx, rhs),
+            (&Encoding::Special(ref lhs), &Encoding::Special(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Remangle for Encoding {
+    fn remangle(&self, ctx: &mut RemangleContext) -> RemangleResult {
+        match *self {
+            Encoding::Function(ref name, ref fun_ty, None) => {
+                try!(name.remangle(ctx));
+                fun_ty.remangle(ctx)
+            }
+            Encoding::Function(_, _, Some(_)) => Err(()),
+            Encoding::Data(ref name) => name.remangle(ctx),
+            Encoding::Special(_) => Err(()),
+        }
+    }
+}
+
 /// The `<name>` production.
 ///
 /// ```text
@@ -738,37 +1802,65 @@ impl Parse for Name {
     fn parse<'a, 'b>(subs: &'a mut SubstitutionTable,
                      input: IndexStr<'b>)
                      -> Result<(Name, IndexStr<'b>)> {
+        parse_with_span("Name", subs, input, Name::parse_without_span)
+    }
+}
+
+impl Name {
+    fn parse_without_span<'a, 'b>(subs: &'a mut SubstitutionTable,
+                                  input: IndexStr<'b>)
+                                  -> Result<(Name, IndexStr<'b>)> {
         log_parse!("Name", input);
 
-        if let Ok((name, tail)) = NestedName::parse(subs, input) {
-            return Ok((Name::Nested(name), tail));
+        // Only a `<nested-name>` starts with `N`, and only a `<local-name>`
+        // starts with `Z`, so dispatch on them directly to report the real
+        // reason a name fails to parse.
+        match input.peek() {
+            None => return Err(error::Error::UnexpectedEnd),
+            Some(b'N') => {
+                let (name, tail) = try!(NestedName::parse(subs, input));
+                return Ok((Name::Nested(name), tail));
+            }
+            Some(b'Z') => {
+                let (name, tail) = try!(LocalName::parse(subs, input));
+                return Ok((Name::Local(name), tail));
+            }
+            _ => {}
         }
 
         if let Ok(tail) = consume(b"St", input) {
             let (name, tail) = try!(UnqualifiedName::parse(subs, tail));
-            return Ok((Name::Std(name), tail));
+            // `St <unqualified-name> <template-args>` is an
+            // <unscoped-template-name> with template args, which is handled
+            // below.
+            if tail.peek() != Some(b'I') {
+                return Ok((Name::Std(name), tail));
+            }
         }
 
-        if let Ok((name, tail)) = UnscopedName::parse(subs, input) {
-            if tail.peek() == Some(b'I') {
-                let name = UnscopedTemplateName(name);
-                let idx = subs.insert(Substitutable::UnscopedTemplateName(name));
-                let handle = UnscopedTemplateNameHandle::BackReference(idx);
+        let unscoped_error = match UnscopedName::parse(subs, input) {
+            Ok((name, tail)) => {
+                if tail.peek() == Some(b'I') {
+                    let name = UnscopedTemplateName(name);
+                    let idx = try!(subs.insert(Substitutable::UnscopedTemplateName(name)));
+                    let handle = UnscopedTemplateNameHandle::new_back_reference(subs, idx);
 
-                let (args, tail) = try!(TemplateArgs::parse(subs, tail));
-                return Ok((Name::UnscopedTemplate(handle, args), tail));
-            } else {
-                return Ok((Name::Unscoped(name), tail));
+                    let (args, tail) = try!(TemplateArgs::parse(subs, tail));
+                    return Ok((Name::UnscopedTemplate(handle, args), tail));
+                } else {
+                    return Ok((Name::Unscoped(name), tail));
+                }
             }
-        }
+            Err(error) => error,
+        };
 
-        if let Ok((name, tail)) = UnscopedTemplateNameHandle::parse(subs, input) {
+        if input.peek() == Some(b'S') {
+            let (name, tail) = try!(UnscopedTemplateNameHandle::parse(subs, input));
             let (args, tail) = try!(TemplateArgs::parse(subs, tail));
             return Ok((Name::UnscopedTemplate(name, args), tail));
         }
 
-        let (name, tail) = try!(LocalName::parse(subs, input));
-        Ok((Name::Local(name), tail))
+        Err(unscoped_error)
     }
 }
 
@@ -776,14 +1868,17 @@ impl Demangle for Name {
     fn demangle<W>(&self,
                    ctx: &mut DemangleContext<W>,
                    stack: Option<ArgStack>)
-                   -> io::Result<()>
+                   -> DemangleResult<()>
         where W: io::Write
     {
         match *self {
             Name::Nested(ref nested) => nested.demangle(ctx, stack),
             Name::Unscoped(ref unscoped) => unscoped.demangle(ctx, stack),
             Name::UnscopedTemplate(ref template, ref args) => {
-                try!(template.demangle(ctx, stack.push(args)));
+                // The template may be a substitution for a template template
+                // param, which refers to the enclosing template's args rather
+                // than to its own.
+                try!(template.demangle(ctx, stack));
                 args.demangle(ctx, stack)
             }
             Name::Local(ref local) => local.demangle(ctx, stack),
@@ -795,6 +1890,41 @@ impl Demangle for Name {
     }
 }
 
+impl SemanticEq for Name {
+    fn semantic_eq(&self, ctx: &SemanticEqContext, other: &Name) -> bool {
+        match (self, other) {
+            (&Name::Nested(ref lhs), &Name::Nested(ref rhs)) => lhs.semantic_eq(ctx, rhs),
+            (&Name::Unscoped(ref lhs), &Name::Unscoped(ref rhs)) => lhs.semantic_eq(ctx, rhs),
+            (&Name::UnscopedTemplate(ref lhs_name, ref lhs_args),
+             &Name::UnscopedTemplate(ref rhs_name, ref rhs_args)) => {
+                lhs_name.semantic_eq(ctx, rhs_name) &&
+                lhs_args.semantic_eq(ctx, rhs_args)
+            }
+            (&Name::Local(ref lhs), &Name::Local(ref rhs)) => lhs.semantic_eq(ctx, rhs),
+            (&Name::Std(ref lhs), &Name::Std(ref rhs)) => lhs.semantic_eq(ctx, rhs),
+            _ => false,
+        }
+    }
+}
+
+impl Remangle for Name {
+    fn remangle(&self, ctx: &mut RemangleContext) -> RemangleResult {
+        match *self {
+            Name::Nested(ref nested) => nested.remangle(ctx),
+            Name::Unscoped(ref unscoped) => unscoped.remangle(ctx),
+            Name::UnscopedTemplate(ref template, ref args) => {
+                try!(template.remangle(ctx));
+                args.remangle(ctx)
+            }
+            Name::Local(_) => Err(()),
+            Name::Std(ref name) => {
+                ctx.write(b"St");
+                name.remangle(ctx)
+            }
+        }
+    }
+}
+
 impl GetTemplateArgs for Name {
     fn get_template_args<'a>(&'a self,
                              subs: &'a SubstitutionTable)
@@ -809,6 +1939,56 @@ impl GetTemplateArgs for Name {
     }
 }
 
+impl GetLeafName for Name {
+    fn get_leaf_name<'a>(&'a self,
+                         subs: &'a SubstitutionTable)
+                         -> Option<&'a UnqualifiedName> {
+        match *self {
+            Name::Nested(ref nested) => nested.get_leaf_name(subs),
+            Name::Unscoped(ref unscoped) => unscoped.get_leaf_name(subs),
+            Name::UnscopedTemplate(ref template, _) => template.get_leaf_name(subs),
+            Name::Local(ref local) => local.get_leaf_name(subs),
+            Name::Std(ref name) => Some(name),
+        }
+    }
+}
+
+impl MatchScope for Name {
+    fn match_scope<'s, 't>(&self,
+                           subs: &SubstitutionTable,
+                           input: &[u8],
+                           scope: &'s [&'t [u8]])
+                           -> Option<&'s [&'t [u8]]> {
+        self.match_enclosing_scope(subs, input, scope).and_then(|rest| {
+            let leaf = self.get_leaf_name(subs).and_then(|name| name.identifier(input));
+            match_scope_component(rest, leaf)
+        })
+    }
+}
+
+impl Name {
+    // Like `MatchScope::match_scope`, but stops before this name's own leaf
+    // name.
+    fn match_enclosing_scope<'s, 't>(&self,
+                                     subs: &SubstitutionTable,
+                                     input: &[u8],
+                                     scope: &'s [&'t [u8]])
+                                     -> Option<&'s [&'t [u8]]> {
+        match *self {
+            Name::Nested(NestedName(_, _, ref prefix)) => {
+                prefix.match_enclosing_scope(subs, input, scope)
+            }
+            Name::Unscoped(UnscopedName::Unqualified(_)) => Some(scope),
+            Name::Unscoped(UnscopedName::Std(_)) |
+            Name::Std(_) => match_scope_component(scope, Some(b"std")),
+            Name::UnscopedTemplate(ref template, _) => {
+                template.match_enclosing_scope(subs, input, scope)
+            }
+            Name::Local(ref local) => local.match_enclosing_scope(subs, input, scope),
+        }
+    }
+}
+
 /// The `<unscoped-name>` production.
 ///
 /// ```text
@@ -840,11 +2020,20 @@ impl Parse for UnscopedName {
     }
 }
 
+impl GetLeafName for UnscopedName {
+    fn get_leaf_name<'a>(&'a self, _: &'a SubstitutionTable) -> Option<&'a UnqualifiedName> {
+        match *self {
+            UnscopedName::Unqualified(ref name) |
+            UnscopedName::Std(ref name) => Some(name),
+        }
+    }
+}
+
 impl Demangle for UnscopedName {
     fn demangle<W>(&self,
                    ctx: &mut DemangleContext<W>,
                    stack: Option<ArgStack>)
-                   -> io::Result<()>
+                   -> DemangleResult<()>
         where W: io::Write
     {
         match *self {
@@ -859,6 +2048,32 @@ impl Demangle for UnscopedName {
     }
 }
 
+impl SemanticEq for UnscopedName {
+    fn semantic_eq(&self, ctx: &SemanticEqContext, other: &UnscopedName) -> bool {
+        match (self, other) {
+            (&UnscopedName::Unqualified(ref lhs), &UnscopedName::Unqualified(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&UnscopedName::Std(ref lhs), &UnscopedName::Std(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Remangle for UnscopedName {
+    fn remangle(&self, ctx: &mut RemangleContext) -> RemangleResult {
+        match *self {
+            UnscopedName::Unqualified(ref name) => name.remangle(ctx),
+            UnscopedName::Std(ref name) => {
+                ctx.write(b"St");
+                name.remangle(ctx)
+            }
+        }
+    }
+}
+
 /// The `<unscoped-template-name>` production.
 ///
 /// ```text
@@ -881,8 +2096,8 @@ impl Parse for UnscopedTemplateNameHandle {
 
         if let Ok((name, tail)) = UnscopedName::parse(subs, input) {
             let name = UnscopedTemplateName(name);
-            let idx = subs.insert(Substitutable::UnscopedTemplateName(name));
-            let handle = UnscopedTemplateNameHandle::BackReference(idx);
+            let idx = try!(subs.insert(Substitutable::UnscopedTemplateName(name)));
+            let handle = UnscopedTemplateNameHandle::new_back_reference(subs, idx);
             return Ok((handle, tail));
         }
 
@@ -895,7 +2110,38 @@ impl Parse for UnscopedTemplateNameHandle {
             Substitution::BackReference(idx) => {
                 // TODO: should this check/assert that subs[idx] is an
                 // UnscopedTemplateName?
-                Ok((UnscopedTemplateNameHandle::BackReference(idx), tail))
+                Ok((UnscopedTemplateNameHandle::new_back_reference(subs, idx), tail))
+            }
+        }
+    }
+}
+
+impl GetLeafName for UnscopedTemplateNameHandle {
+    fn get_leaf_name<'a>(&'a self,
+                         subs: &'a SubstitutionTable)
+                         -> Option<&'a UnqualifiedName> {
+        subs.get_unscoped_template_name(self).and_then(|name| name.0.get_leaf_name(subs))
+    }
+}
+
+impl UnscopedTemplateNameHandle {
+    fn match_enclosing_scope<'s, 't>(&self,
+                                     subs: &SubstitutionTable,
+                                     _: &[u8],
+                                     scope: &'s [&'t [u8]])
+                                     -> Option<&'s [&'t [u8]]> {
+        match *self {
+            UnscopedTemplateNameHandle::WellKnown(ref component) => {
+                component.match_enclosing_scope(scope)
+            }
+            _ => {
+                match subs.get_unscoped_template_name(self) {
+                    Some(&UnscopedTemplateName(UnscopedName::Unqualified(_))) => Some(scope),
+                    Some(&UnscopedTemplateName(UnscopedName::Std(_))) => {
+                        match_scope_component(scope, Some(b"std"))
+                    }
+                    None => None,
+                }
             }
         }
     }
@@ -905,13 +2151,27 @@ impl Demangle for UnscopedTemplateName {
     fn demangle<W>(&self,
                    ctx: &mut DemangleContext<W>,
                    stack: Option<ArgStack>)
-                   -> io::Result<()>
+                   -> DemangleResult<()>
         where W: io::Write
     {
         self.0.demangle(ctx, stack)
     }
 }
 
+impl SemanticEq for UnscopedTemplateName {
+    fn semantic_eq(&self, ctx: &SemanticEqContext, other: &UnscopedTemplateName) -> bool {
+        let UnscopedTemplateName(ref lhs) = *self;
+        let UnscopedTemplateName(ref rhs) = *other;
+        lhs.semantic_eq(ctx, rhs)
+    }
+}
+
+impl Remangle for UnscopedTemplateName {
+    fn remangle(&self, ctx: &mut RemangleContext) -> RemangleResult {
+        self.0.remangle(ctx)
+    }
+}
+
 /// The `<nested-name>` production.
 ///
 /// ```text
@@ -925,6 +2185,14 @@ impl Parse for NestedName {
     fn parse<'a, 'b>(subs: &'a mut SubstitutionTable,
                      input: IndexStr<'b>)
                      -> Result<(NestedName, IndexStr<'b>)> {
+        parse_with_span("NestedName", subs, input, NestedName::parse_without_span)
+    }
+}
+
+impl NestedName {
+    fn parse_without_span<'a, 'b>(subs: &'a mut SubstitutionTable,
+                                  input: IndexStr<'b>)
+                                  -> Result<(NestedName, IndexStr<'b>)> {
         log_parse!("NestedName", input);
 
         let tail = try!(consume(b"N", input));
@@ -944,17 +2212,26 @@ impl Parse for NestedName {
         };
 
         let (prefix, tail) = try!(PrefixHandle::parse(subs, tail));
-        if let PrefixHandle::BackReference(idx) = prefix {
-            match (*subs)[idx] {
-                // The <nested-name> must end with one of these kinds of prefix
-                // components.
-                Substitutable::Prefix(Prefix::Nested(..)) |
-                Substitutable::Prefix(Prefix::Template(..)) => {}
-                _ => return Err(error::Error::UnexpectedText),
-            }
+        let tail = try!(consume(b"E", tail));
+
+        match prefix {
+            PrefixHandle::BackReference(_) |
+            PrefixHandle::NonSubstitution(_) => {
+                match subs.get_prefix(&prefix) {
+                    // The <nested-name> must end with one of these kinds of
+                    // prefix components.
+                    Some(&Prefix::Nested(..)) |
+                    Some(&Prefix::Template(..)) => {}
+                    _ => return Err(error::Error::UnexpectedText),
+                }
+            }
+            // A lone well-known component, like `NStE`, has no trailing
+            // <unqualified-name> or <template-args>, so it can't be a whole
+            // <nested-name>. Well-known components followed by more prefix
+            // components, like `NSt3fooE`, are back references to those.
+            PrefixHandle::WellKnown(_) => return Err(error::Error::UnexpectedText),
         }
 
-        let tail = try!(consume(b"E", tail));
         Ok((NestedName(cv_qualifiers, ref_qualifier, prefix), tail))
     }
 }
@@ -964,7 +2241,7 @@ impl DemangleWithInner for NestedName {
                                  inner: Option<&D>,
                                  ctx: &mut DemangleContext<W>,
                                  stack: Option<ArgStack>)
-                                 -> io::Result<()>
+                                 -> DemangleResult<()>
         where D: ?Sized + Demangle,
               W: io::Write
     {
@@ -987,6 +2264,29 @@ impl DemangleWithInner for NestedName {
     }
 }
 
+impl SemanticEq for NestedName {
+    fn semantic_eq(&self, ctx: &SemanticEqContext, other: &NestedName) -> bool {
+        let NestedName(ref lhs_cv_qualifiers, ref lhs_ref_qualifier, ref lhs_prefix) = *self;
+        let NestedName(ref rhs_cv_qualifiers, ref rhs_ref_qualifier, ref rhs_prefix) = *other;
+        lhs_cv_qualifiers.semantic_eq(ctx, rhs_cv_qualifiers) &&
+        lhs_ref_qualifier.semantic_eq(ctx, rhs_ref_qualifier) &&
+        lhs_prefix.semantic_eq(ctx, rhs_prefix)
+    }
+}
+
+impl Remangle for NestedName {
+    fn remangle(&self, ctx: &mut RemangleContext) -> RemangleResult {
+        ctx.write(b"N");
+        try!(self.0.remangle(ctx));
+        if let Some(ref refs) = self.1 {
+            try!(refs.remangle(ctx));
+        }
+        try!(self.2.remangle(ctx));
+        ctx.write(b"E");
+        Ok(())
+    }
+}
+
 impl GetTemplateArgs for NestedName {
     fn get_template_args<'a>(&'a self,
                              subs: &'a SubstitutionTable)
@@ -995,6 +2295,14 @@ impl GetTemplateArgs for NestedName {
     }
 }
 
+impl GetLeafName for NestedName {
+    fn get_leaf_name<'a>(&'a self,
+                         subs: &'a SubstitutionTable)
+                         -> Option<&'a UnqualifiedName> {
+        self.2.get_leaf_name(subs)
+    }
+}
+
 /// The `<prefix>` production.
 ///
 /// ```text
@@ -1011,6 +2319,10 @@ impl GetTemplateArgs for NestedName {
 ///                   ::= <template-param>
 ///                   ::= <substitution>
 /// ```
+///
+/// Parsing a `<prefix>` is greedy: it consumes components until it reaches a
+/// byte that cannot continue it. In a well-formed symbol that byte is the `E`
+/// ending the enclosing `<nested-name>`.
 #[derive(Clone, Debug, Hash, PartialEq, Eq)]
 pub enum Prefix {
     /// An unqualified name.
@@ -1047,9 +2359,28 @@ impl GetTemplateArgs for Prefix {
     }
 }
 
+impl GetLeafName for Prefix {
+    fn get_leaf_name<'a>(&'a self,
+                         subs: &'a SubstitutionTable)
+                         -> Option<&'a UnqualifiedName> {
+        match *self {
+            Prefix::Unqualified(ref name) |
+            Prefix::Nested(_, ref name) => Some(name),
+            Prefix::Template(ref prefix, _) => prefix.get_leaf_name(subs),
+            Prefix::TemplateParam(_) |
+            Prefix::Decltype(_) |
+            Prefix::DataMember(_, _) => None,
+        }
+    }
+}
+
 define_handle! {
     /// A reference to a parsed `<prefix>` production.
-    pub enum PrefixHandle
+    pub enum PrefixHandle {
+        /// A reference to the last component of a `<nested-name>`'s prefix,
+        /// which is not a substitution candidate.
+        extra NonSubstitution(NonSubstitution),
+    }
 }
 
 impl Parse for PrefixHandle {
@@ -1058,9 +2389,20 @@ impl Parse for PrefixHandle {
                      -> Result<(PrefixHandle, IndexStr<'b>)> {
         log_parse!("PrefixHandle", input);
 
-        fn add_to_subs(subs: &mut SubstitutionTable, prefix: Prefix) -> PrefixHandle {
-            let idx = subs.insert(Substitutable::Prefix(prefix));
-            PrefixHandle::BackReference(idx)
+        // Every component of a prefix is a substitution candidate, except
+        // for the last one before the `E` closing its <nested-name>.
+        fn add_to_subs(subs: &mut SubstitutionTable,
+                       prefix: Prefix,
+                       tail: IndexStr)
+                       -> Result<PrefixHandle> {
+            let prefix = Substitutable::Prefix(prefix);
+            if tail.peek() == Some(b'E') {
+                let idx = subs.insert_non_substitution(prefix);
+                return Ok(PrefixHandle::NonSubstitution(NonSubstitution(idx)));
+            }
+
+            let idx = try!(subs.insert(prefix));
+            Ok(PrefixHandle::new_back_reference(subs, idx))
         }
 
         let mut tail = input;
@@ -1087,7 +2429,7 @@ impl Parse for PrefixHandle {
                         Substitution::BackReference(idx) => {
                             // TODO: do we need to check that the idx actually points to
                             // a Prefix?
-                            PrefixHandle::BackReference(idx)
+                            PrefixHandle::new_back_reference(subs, idx)
                         }
                     });
                     tail = tail_tail;
@@ -1095,7 +2437,8 @@ impl Parse for PrefixHandle {
                 Some(b'T') => {
                     // <prefix> ::= <template-param>
                     let (param, tail_tail) = try!(TemplateParam::parse(subs, tail));
-                    current = Some(add_to_subs(subs, Prefix::TemplateParam(param)));
+                    let prefix = Prefix::TemplateParam(param);
+                    current = Some(try!(add_to_subs(subs, prefix, tail_tail)));
                     tail = tail_tail;
                 }
                 Some(b'D') => {
@@ -1106,8 +2449,10 @@ impl Parse for PrefixHandle {
                     // or
                     //
                     //     <prefix> ::= <unqualified-name> ::= <ctor-dtor-name>
-                    if let Ok((decltype, tail_tail)) = Decltype::parse(subs, tail) {
-                        current = Some(add_to_subs(subs, Prefix::Decltype(decltype)));
+                    if peek_d_prefix(tail) == Some(DPrefix::Decltype) {
+                        let (decltype, tail_tail) = try!(Decltype::parse(subs, tail));
+                        let prefix = Prefix::Decltype(decltype);
+                        current = Some(try!(add_to_subs(subs, prefix, tail_tail)));
                         tail = tail_tail;
                     } else {
                         let (name, tail_tail) = try!(UnqualifiedName::parse(subs, tail));
@@ -1115,7 +2460,7 @@ impl Parse for PrefixHandle {
                             None => Prefix::Unqualified(name),
                             Some(handle) => Prefix::Nested(handle, name),
                         };
-                        current = Some(add_to_subs(subs, prefix));
+                        current = Some(try!(add_to_subs(subs, prefix, tail_tail)));
                         tail = tail_tail;
                     }
                 }
@@ -1124,7 +2469,7 @@ impl Parse for PrefixHandle {
                     // <prefix> ::= <template-prefix> <template-args>
                     let (args, tail_tail) = try!(TemplateArgs::parse(subs, tail));
                     let prefix = Prefix::Template(current.unwrap(), args);
-                    current = Some(add_to_subs(subs, prefix));
+                    current = Some(try!(add_to_subs(subs, prefix, tail_tail)));
                     tail = tail_tail;
                 }
                 Some(c) if current.is_some() && SourceName::starts_with(c) => {
@@ -1138,19 +2483,18 @@ impl Parse for PrefixHandle {
                     debug_assert!(UnqualifiedName::starts_with(c));
                     debug_assert!(DataMemberPrefix::starts_with(c));
 
-                    let (name, tail_tail) = try!(SourceName::parse(subs, tail));
-                    if tail_tail.peek() == Some(b'M') {
-                        let prefix = Prefix::DataMember(current.unwrap(),
-                                                        DataMemberPrefix(name));
-                        current = Some(add_to_subs(subs, prefix));
-                        tail = consume(b"M", tail_tail).unwrap();
+                    if let Ok((data_member, tail_tail)) = DataMemberPrefix::parse(subs, tail) {
+                        let prefix = Prefix::DataMember(current.unwrap(), data_member);
+                        current = Some(try!(add_to_subs(subs, prefix, tail_tail)));
+                        tail = tail_tail;
                     } else {
+                        let (name, tail_tail) = try!(SourceName::parse(subs, tail));
                         let name = UnqualifiedName::Source(name);
                         let prefix = match current {
                             None => Prefix::Unqualified(name),
                             Some(handle) => Prefix::Nested(handle, name),
                         };
-                        current = Some(add_to_subs(subs, prefix));
+                        current = Some(try!(add_to_subs(subs, prefix, tail_tail)));
                         tail = tail_tail;
                     }
                 }
@@ -1161,14 +2505,17 @@ impl Parse for PrefixHandle {
                         None => Prefix::Unqualified(name),
                         Some(handle) => Prefix::Nested(handle, name),
                     };
-                    current = Some(add_to_subs(subs, prefix));
+                    current = Some(try!(add_to_subs(subs, prefix, tail_tail)));
                     tail = tail_tail;
                 }
                 Some(_) => {
+                    // The only byte that legitimately follows a complete
+                    // <prefix> is the `E` closing its <nested-name>. Anything
+                    // else (a `Z`, or an `I` after a prefix that cannot take
+                    // template args, ...) still ends the prefix here, and it
+                    // is up to the caller to reject the unconsumed tail.
                     if let Some(handle) = current {
                         return Ok((handle, tail));
-                    } else if tail.is_empty() {
-                        return Err(error::Error::UnexpectedEnd);
                     } else {
                         return Err(error::Error::UnexpectedText);
                     }
@@ -1182,15 +2529,92 @@ impl GetTemplateArgs for PrefixHandle {
     fn get_template_args<'a>(&'a self,
                              subs: &'a SubstitutionTable)
                              -> Option<&'a TemplateArgs> {
+        subs.get_prefix(self).and_then(|p| p.get_template_args(subs))
+    }
+}
+
+impl GetLeafName for PrefixHandle {
+    fn get_leaf_name<'a>(&'a self,
+                         subs: &'a SubstitutionTable)
+                         -> Option<&'a UnqualifiedName> {
+        subs.get_prefix(self).and_then(|p| p.get_leaf_name(subs))
+    }
+}
+
+impl MatchScope for PrefixHandle {
+    fn match_scope<'s, 't>(&self,
+                           subs: &SubstitutionTable,
+                           input: &[u8],
+                           scope: &'s [&'t [u8]])
+                           -> Option<&'s [&'t [u8]]> {
         match *self {
-            PrefixHandle::BackReference(idx) => {
-                if let Some(&Substitutable::Prefix(ref p)) = subs.get(idx) {
-                    p.get_template_args(subs)
-                } else {
-                    None
-                }
+            PrefixHandle::WellKnown(ref component) => component.match_scope(scope),
+            _ => subs.get_prefix(self).and_then(|p| p.match_scope(subs, input, scope)),
+        }
+    }
+}
+
+impl PrefixHandle {
+    // Like `MatchScope::match_scope`, but stops before this prefix's last
+    // unqualified name.
+    fn match_enclosing_scope<'s, 't>(&self,
+                                     subs: &SubstitutionTable,
+                                     input: &[u8],
+                                     scope: &'s [&'t [u8]])
+                                     -> Option<&'s [&'t [u8]]> {
+        match *self {
+            PrefixHandle::WellKnown(ref component) => component.match_enclosing_scope(scope),
+            _ => {
+                subs.get_prefix(self)
+                    .and_then(|p| p.match_enclosing_scope(subs, input, scope))
+            }
+        }
+    }
+}
+
+impl MatchScope for Prefix {
+    fn match_scope<'s, 't>(&self,
+                           subs: &SubstitutionTable,
+                           input: &[u8],
+                           scope: &'s [&'t [u8]])
+                           -> Option<&'s [&'t [u8]]> {
+        match *self {
+            Prefix::Unqualified(ref name) => {
+                match_scope_component(scope, name.identifier(input))
+            }
+            Prefix::Nested(ref prefix, ref name) => {
+                prefix.match_scope(subs, input, scope)
+                    .and_then(|rest| match_scope_component(rest, name.identifier(input)))
+            }
+            // Template arguments don't add a component to the scope chain.
+            Prefix::Template(ref prefix, _) => prefix.match_scope(subs, input, scope),
+            Prefix::TemplateParam(_) |
+            Prefix::Decltype(_) => match_scope_component(scope, None),
+            Prefix::DataMember(ref prefix, DataMemberPrefix(ref name)) => {
+                prefix.match_scope(subs, input, scope)
+                    .and_then(|rest| {
+                        match_scope_component(rest, Some(name.identifier(input)))
+                    })
+            }
+        }
+    }
+}
+
+impl Prefix {
+    fn match_enclosing_scope<'s, 't>(&self,
+                                     subs: &SubstitutionTable,
+                                     input: &[u8],
+                                     scope: &'s [&'t [u8]])
+                                     -> Option<&'s [&'t [u8]]> {
+        match *self {
+            Prefix::Unqualified(_) |
+            Prefix::TemplateParam(_) |
+            Prefix::Decltype(_) => Some(scope),
+            Prefix::Nested(ref prefix, _) |
+            Prefix::DataMember(ref prefix, _) => prefix.match_scope(subs, input, scope),
+            Prefix::Template(ref prefix, _) => {
+                prefix.match_enclosing_scope(subs, input, scope)
             }
-            _ => None,
         }
     }
 }
@@ -1211,14 +2635,12 @@ impl Prefix {
 impl PrefixHandle {
     fn is_template_prefix(&self, subs: &SubstitutionTable) -> bool {
         match *self {
-            PrefixHandle::BackReference(idx) => {
-                if let Some(&Substitutable::Prefix(ref p)) = subs.get(idx) {
-                    p.is_template_prefix()
-                } else {
-                    false
-                }
-            }
-            _ => false,
+            // `std::allocator` and `std::basic_string` are class templates,
+            // while `std` is a namespace and the rest are specializations.
+            PrefixHandle::WellKnown(WellKnownComponent::StdAllocator) |
+            PrefixHandle::WellKnown(WellKnownComponent::StdString1) => true,
+            PrefixHandle::WellKnown(_) => false,
+            _ => subs.get_prefix(self).map_or(false, |p| p.is_template_prefix()),
         }
     }
 }
@@ -1227,7 +2649,7 @@ impl Demangle for Prefix {
     fn demangle<W>(&self,
                    ctx: &mut DemangleContext<W>,
                    stack: Option<ArgStack>)
-                   -> io::Result<()>
+                   -> DemangleResult<()>
         where W: io::Write
     {
         match *self {
@@ -1251,6 +2673,55 @@ impl Demangle for Prefix {
     }
 }
 
+impl SemanticEq for Prefix {
+    fn semantic_eq(&self, ctx: &SemanticEqContext, other: &Prefix) -> bool {
+        match (self, other) {
+            (&Prefix::Unqualified(ref lhs), &Prefix::Unqualified(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&Prefix::Nested(ref lhs_prefix, ref lhs_name),
+             &Prefix::Nested(ref rhs_prefix, ref rhs_name)) => {
+                lhs_prefix.semantic_eq(ctx, rhs_prefix) &&
+                lhs_name.semantic_eq(ctx, rhs_name)
+            }
+            (&Prefix::Template(ref lhs_prefix, ref lhs_args),
+             &Prefix::Template(ref rhs_prefix, ref rhs_args)) => {
+                lhs_prefix.semantic_eq(ctx, rhs_prefix) &&
+                lhs_args.semantic_eq(ctx, rhs_args)
+            }
+            (&Prefix::TemplateParam(ref lhs), &Prefix::TemplateParam(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&Prefix::Decltype(ref lhs), &Prefix::Decltype(ref rhs)) => lhs.semantic_eq(ctx, rhs),
+            (&Prefix::DataMember(ref lhs_prefix, ref lhs_member),
+             &Prefix::DataMember(ref rhs_prefix, ref rhs_member)) => {
+                lhs_prefix.semantic_eq(ctx, rhs_prefix) &&
+                lhs_member.semantic_eq(ctx, rhs_member)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Remangle for Prefix {
+    fn remangle(&self, ctx: &mut RemangleContext) -> RemangleResult {
+        match *self {
+            Prefix::Unqualified(ref name) => name.remangle(ctx),
+            Prefix::Nested(ref prefix, ref name) => {
+                try!(prefix.remangle(ctx));
+                name.remangle(ctx)
+            }
+            Prefix::Template(ref prefix, ref args) => {
+                try!(prefix.remangle(ctx));
+                args.remangle(ctx)
+            }
+            Prefix::TemplateParam(ref param) => param.remangle(ctx),
+            Prefix::Decltype(_) |
+            Prefix::DataMember(_, _) => Err(()),
+        }
+    }
+}
+
 
 /// The `<unqualified-name>` production.
 ///
@@ -1259,7 +2730,12 @@ impl Demangle for Prefix {
 ///                    ::= <ctor-dtor-name>
 ///                    ::= <source-name>
 ///                    ::= <unnamed-type-name>
+///                    ::= L <source-name> [<discriminator>]
 /// ```
+///
+/// The `L` form is a GCC extension marking entities with internal linkage, such
+/// as `static` functions and variables: `_ZL3foov` is `foo()`. It has no
+/// effect on the demangled form.
 #[derive(Clone, Debug, Hash, PartialEq, Eq)]
 pub enum UnqualifiedName {
     /// An operator name.
@@ -1270,14 +2746,34 @@ pub enum UnqualifiedName {
     Source(SourceName),
     /// A generated name for an unnamed type.
     UnnamedType(UnnamedTypeName),
+    /// A generated name for a lambda's closure type.
+    ClosureType(ClosureTypeName),
+    /// A source name for an entity with internal linkage.
+    LocalSourceName(SourceName, Option<Discriminator>),
 }
 
 impl Parse for UnqualifiedName {
     fn parse<'a, 'b>(subs: &'a mut SubstitutionTable,
                      input: IndexStr<'b>)
                      -> Result<(UnqualifiedName, IndexStr<'b>)> {
+        parse_with_span("UnqualifiedName", subs, input, UnqualifiedName::parse_without_span)
+    }
+}
+
+impl UnqualifiedName {
+    fn parse_without_span<'a, 'b>(subs: &'a mut SubstitutionTable,
+                                  input: IndexStr<'b>)
+                                  -> Result<(UnqualifiedName, IndexStr<'b>)> {
         log_parse!("UnqualifiedName", input);
 
+        match input.peek() {
+            None => return Err(error::Error::UnexpectedEnd),
+            Some(c) if !UnqualifiedName::starts_with(c) => {
+                return Err(error::Error::UnexpectedText);
+            }
+            _ => {}
+        }
+
         if let Ok((op, tail)) = OperatorName::parse(subs, input) {
             return Ok((UnqualifiedName::Operator(op), tail));
         }
@@ -1286,10 +2782,24 @@ impl Parse for UnqualifiedName {
             return Ok((UnqualifiedName::CtorDtor(ctor_dtor), tail));
         }
 
-        if let Ok((source, tail)) = SourceName::parse(subs, input) {
+        if input.peek().map_or(false, SourceName::starts_with) {
+            let (source, tail) = try!(SourceName::parse(subs, input));
             return Ok((UnqualifiedName::Source(source), tail));
         }
 
+        if let Ok(tail) = consume(b"L", input) {
+            let (source, tail) = try!(SourceName::parse(subs, tail));
+            let (discriminator, tail) = match Discriminator::parse(subs, tail) {
+                Ok((d, t)) => (Some(d), t),
+                Err(_) => (None, tail),
+            };
+            return Ok((UnqualifiedName::LocalSourceName(source, discriminator), tail));
+        }
+
+        if let Ok((closure, tail)) = ClosureTypeName::parse(subs, input) {
+            return Ok((UnqualifiedName::ClosureType(closure), tail));
+        }
+
         UnnamedTypeName::parse(subs, input)
             .map(|(unnamed, tail)| (UnqualifiedName::UnnamedType(unnamed), tail))
     }
@@ -1298,7 +2808,7 @@ impl Parse for UnqualifiedName {
 impl StartsWith for UnqualifiedName {
     #[inline]
     fn starts_with(byte: u8) -> bool {
-        OperatorName::starts_with(byte) || CtorDtorName::starts_with(byte) ||
+        byte == b'L' || OperatorName::starts_with(byte) || CtorDtorName::starts_with(byte) ||
         SourceName::starts_with(byte) || UnnamedTypeName::starts_with(byte)
     }
 }
@@ -1307,17 +2817,97 @@ impl Demangle for UnqualifiedName {
     fn demangle<W>(&self,
                    ctx: &mut DemangleContext<W>,
                    stack: Option<ArgStack>)
-                   -> io::Result<()>
+                   -> DemangleResult<()>
+        where W: io::Write
+    {
+        try!(ctx.markup(Markup::Start(MarkupRegion::Name)));
+        try!(self.demangle_name(ctx, stack));
+        try!(ctx.markup(Markup::End(MarkupRegion::Name)));
+        Ok(())
+    }
+}
+
+impl UnqualifiedName {
+    fn demangle_name<W>(&self,
+                        ctx: &mut DemangleContext<W>,
+                        stack: Option<ArgStack>)
+                        -> DemangleResult<()>
         where W: io::Write
     {
         match *self {
             UnqualifiedName::Operator(ref op_name) => {
-                try!(write!(ctx, "operator"));
+                if op_name.is_alphabetic() {
+                    try!(write!(ctx, "operator "));
+                } else {
+                    try!(write!(ctx, "operator"));
+                }
                 op_name.demangle(ctx, stack)
             }
             UnqualifiedName::CtorDtor(ref ctor_dtor) => ctor_dtor.demangle(ctx, stack),
             UnqualifiedName::Source(ref name) => name.demangle(ctx, stack),
             UnqualifiedName::UnnamedType(ref unnamed) => unnamed.demangle(ctx, stack),
+            UnqualifiedName::ClosureType(ref closure) => closure.demangle(ctx, stack),
+            UnqualifiedName::LocalSourceName(ref name, _) => name.demangle(ctx, stack),
+        }
+    }
+}
+
+impl UnqualifiedName {
+    // The identifier of this name within `input`, if it is a source name.
+    fn identifier<'i>(&self, input: &'i [u8]) -> Option<&'i [u8]> {
+        match *self {
+            UnqualifiedName::Source(ref name) |
+            UnqualifiedName::LocalSourceName(ref name, _) => Some(name.identifier(input)),
+            UnqualifiedName::Operator(_) |
+            UnqualifiedName::CtorDtor(_) |
+            UnqualifiedName::UnnamedType(_) |
+            UnqualifiedName::ClosureType(_) => None,
+        }
+    }
+}
+
+impl SemanticEq for UnqualifiedName {
+    fn semantic_eq(&self, ctx: &SemanticEqContext, other: &UnqualifiedName) -> bool {
+        match (self, other) {
+            (&UnqualifiedName::Operator(ref lhs), &UnqualifiedName::Operator(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&UnqualifiedName::CtorDtor(ref lhs), &UnqualifiedName::CtorDtor(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&UnqualifiedName::Source(ref lhs), &UnqualifiedName::Source(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&UnqualifiedName::UnnamedType(ref lhs), &UnqualifiedName::UnnamedType(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&UnqualifiedName::ClosureType(ref lhs),
+             &UnqualifiedName::ClosureType(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&UnqualifiedName::LocalSourceName(ref lhs_name, ref lhs_discriminator),
+             &UnqualifiedName::LocalSourceName(ref rhs_name, ref rhs_discriminator)) => {
+                lhs_name.semantic_eq(ctx, rhs_name) &&
+                lhs_discriminator.semantic_eq(ctx, rhs_discriminator)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Remangle for UnqualifiedName {
+    fn remangle(&self, ctx: &mut RemangleContext) -> RemangleResult {
+        match *self {
+            UnqualifiedName::Operator(ref op) => op.remangle(ctx),
+            UnqualifiedName::CtorDtor(ref ctor_dtor) => ctor_dtor.remangle(ctx),
+            UnqualifiedName::Source(ref name) => name.remangle(ctx),
+            UnqualifiedName::LocalSourceName(ref name, None) => {
+                ctx.write(b"L");
+                name.remangle(ctx)
+            }
+            UnqualifiedName::UnnamedType(_) |
+            UnqualifiedName::ClosureType(_) |
+            UnqualifiedName::LocalSourceName(_, Some(_)) => Err(()),
         }
     }
 }
@@ -1334,6 +2924,19 @@ impl Parse for SourceName {
     fn parse<'a, 'b>(subs: &'a mut SubstitutionTable,
                      input: IndexStr<'b>)
                      -> Result<(SourceName, IndexStr<'b>)> {
+        parse_with_span("SourceName", subs, input, SourceName::parse_without_span)
+    }
+}
+
+impl SourceName {
+    // The identifier of this source name within `input`.
+    fn identifier<'i>(&self, input: &'i [u8]) -> &'i [u8] {
+        &input[self.0.start..self.0.end]
+    }
+
+    fn parse_without_span<'a, 'b>(subs: &'a mut SubstitutionTable,
+                                  input: IndexStr<'b>)
+                                  -> Result<(SourceName, IndexStr<'b>)> {
         log_parse!("SourceName", input);
 
         let (source_name_len, input) = try!(parse_number(10, false, input));
@@ -1369,13 +2972,29 @@ impl Demangle for SourceName {
     fn demangle<W>(&self,
                    ctx: &mut DemangleContext<W>,
                    stack: Option<ArgStack>)
-                   -> io::Result<()>
+                   -> DemangleResult<()>
         where W: io::Write
     {
         self.0.demangle(ctx, stack)
     }
 }
 
+impl SemanticEq for SourceName {
+    fn semantic_eq(&self, ctx: &SemanticEqContext, other: &SourceName) -> bool {
+        let SourceName(ref lhs) = *self;
+        let SourceName(ref rhs) = *other;
+        lhs.semantic_eq(ctx, rhs)
+    }
+}
+
+impl Remangle for SourceName {
+    fn remangle(&self, ctx: &mut RemangleContext) -> RemangleResult {
+        let len = (self.0.end - self.0.start).to_string();
+        ctx.write(len.as_bytes());
+        self.0.remangle(ctx)
+    }
+}
+
 /// The `<identifier>` pseudo-terminal.
 ///
 /// ```text
@@ -1386,6 +3005,12 @@ impl Demangle for SourceName {
 /// > unqualified identifier for the entity in the source code. This ABI does not
 /// > yet specify a mangling for identifiers containing characters outside of
 /// > `_A-Za-z0-9`.
+///
+/// In practice, compilers also emit `$` and `.` in identifiers, such as Clang's
+/// `$_0` for unnamed lambdas, or GCC's `.constprop.0` clone suffixes, so we
+/// accept those too. Identifiers with extended characters are emitted as their
+/// raw (typically UTF-8) bytes, so we accept any non-ASCII byte as well, and
+/// write those bytes back out unchanged when demangling.
 #[derive(Clone, Debug, Hash, PartialEq, Eq)]
 pub struct Identifier {
     start: usize,
@@ -1405,7 +3030,9 @@ impl Parse for Identifier {
         let end = input.as_ref()
             .iter()
             .map(|&c| c as char)
-            .take_while(|&c| c == '_' || c.is_digit(36))
+            .take_while(|&c| {
+                c == '_' || c == '$' || c == '.' || c.is_digit(36) || (c as u32) >= 0x80
+            })
             .count();
 
         if end == 0 {
@@ -1427,11 +3054,25 @@ impl Demangle for Identifier {
     fn demangle<W>(&self,
                    ctx: &mut DemangleContext<W>,
                    _: Option<ArgStack>)
-                   -> io::Result<()>
+                   -> DemangleResult<()>
         where W: io::Write
     {
-        let ident = &ctx.input[self.start..self.end];
-        try!(write!(ctx, "{}", String::from_utf8_lossy(ident)));
+        let input = ctx.input;
+        try!(ctx.write_all(&input[self.start..self.end]));
+        Ok(())
+    }
+}
+
+impl SemanticEq for Identifier {
+    fn semantic_eq(&self, ctx: &SemanticEqContext, other: &Identifier) -> bool {
+        ctx.lhs_input[self.start..self.end] == ctx.rhs_input[other.start..other.end]
+    }
+}
+
+impl Remangle for Identifier {
+    fn remangle(&self, ctx: &mut RemangleContext) -> RemangleResult {
+        let input = ctx.input;
+        ctx.write(&input[self.start..self.end]);
         Ok(())
     }
 }
@@ -1530,6 +3171,20 @@ define_vocabulary! {
     }
 }
 
+impl OperatorName {
+    // Is this operator spelled with a keyword, and therefore needs a space
+    // after `operator`, as in `operator new`?
+    fn is_alphabetic(&self) -> bool {
+        match *self {
+            OperatorName::New |
+            OperatorName::NewArray |
+            OperatorName::Delete |
+            OperatorName::DeleteArray => true,
+            _ => false,
+        }
+    }
+}
+
 /// The `<call-offset>` production.
 ///
 /// ```text
@@ -1574,7 +3229,7 @@ impl Demangle for CallOffset {
     fn demangle<W>(&self,
                    ctx: &mut DemangleContext<W>,
                    _: Option<ArgStack>)
-                   -> io::Result<()>
+                   -> DemangleResult<()>
         where W: io::Write
     {
         match *self {
@@ -1635,18 +3290,85 @@ define_vocabulary! {
     /// <ctor-dtor-name> ::= C1  # complete object constructor
     ///                  ::= C2  # base object constructor
     ///                  ::= C3  # complete object allocating constructor
+    ///                  ::= C4  # maybe in-charge constructor (GCC extension)
     ///                  ::= D0  # deleting destructor
     ///                  ::= D1  # complete object destructor
     ///                  ::= D2  # base object destructor
+    ///                  ::= D4  # maybe in-charge destructor (GCC extension)
     /// ```
+    ///
+    /// GCC emits the `C4` and `D4` "maybe in-charge" variants for a single
+    /// constructor or destructor that serves as both the complete and base
+    /// object variants.
     #[derive(Clone, Debug, Hash, PartialEq, Eq)]
     pub enum CtorDtorName {
         CompleteConstructor             (b"C1", "complete object constructor"),
         BaseConstructor                 (b"C2", "base object constructor"),
         CompleteAllocatingConstructor   (b"C3", "complete object allocating constructor"),
+        MaybeInChargeConstructor        (b"C4", "maybe in-charge constructor"),
         DeletingDestructor              (b"D0", "deleting destructor"),
         CompleteDestructor              (b"D1", "complete object destructor"),
-        BaseDestructor                  (b"D2", "base object destructor")
+        BaseDestructor                  (b"D2", "base object destructor"),
+        MaybeInChargeDestructor         (b"D4", "maybe in-charge destructor")
+    }
+}
+
+impl CtorDtorName {
+    /// Get the kind of constructor or destructor this name refers to.
+    pub fn kind(&self) -> CtorDtorKind {
+        match *self {
+            CtorDtorName::CompleteConstructor => CtorDtorKind::CompleteConstructor,
+            CtorDtorName::BaseConstructor => CtorDtorKind::BaseConstructor,
+            CtorDtorName::CompleteAllocatingConstructor => {
+                CtorDtorKind::CompleteAllocatingConstructor
+            }
+            CtorDtorName::MaybeInChargeConstructor => CtorDtorKind::MaybeInChargeConstructor,
+            CtorDtorName::DeletingDestructor => CtorDtorKind::DeletingDestructor,
+            CtorDtorName::CompleteDestructor => CtorDtorKind::CompleteDestructor,
+            CtorDtorName::BaseDestructor => CtorDtorKind::BaseDestructor,
+            CtorDtorName::MaybeInChargeDestructor => CtorDtorKind::MaybeInChargeDestructor,
+        }
+    }
+}
+
+/// The kind of a constructor or destructor, as named by a `<ctor-dtor-name>`.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum CtorDtorKind {
+    /// A complete object constructor.
+    CompleteConstructor,
+    /// A base object constructor.
+    BaseConstructor,
+    /// A complete object allocating constructor.
+    CompleteAllocatingConstructor,
+    /// A GCC "maybe in-charge" constructor, which serves as both the complete
+    /// and base object constructors.
+    MaybeInChargeConstructor,
+    /// A deleting destructor.
+    DeletingDestructor,
+    /// A complete object destructor.
+    CompleteDestructor,
+    /// A base object destructor.
+    BaseDestructor,
+    /// A GCC "maybe in-charge" destructor, which serves as both the complete
+    /// and base object destructors.
+    MaybeInChargeDestructor,
+}
+
+impl CtorDtorKind {
+    /// Is this some kind of constructor?
+    pub fn is_constructor(&self) -> bool {
+        match *self {
+            CtorDtorKind::CompleteConstructor |
+            CtorDtorKind::BaseConstructor |
+            CtorDtorKind::CompleteAllocatingConstructor |
+            CtorDtorKind::MaybeInChargeConstructor => true,
+            _ => false,
+        }
+    }
+
+    /// Is this some kind of destructor?
+    pub fn is_destructor(&self) -> bool {
+        !self.is_constructor()
     }
 }
 
@@ -1727,6 +3449,55 @@ define_handle! {
     }
 }
 
+// If `ty` is a reference, or a template param whose argument is one, get
+// whether it is an rvalue reference, and the type that it refers to.
+fn referenced_type<W>(ctx: &DemangleContext<W>,
+                      stack: Option<ArgStack>,
+                      ty: &TypeHandle)
+                      -> Option<(bool, TypeHandle)>
+    where W: io::Write
+{
+    let arg_stack = ctx.template_arg_stack(stack);
+    let ty = match ctx.subs.get_type(ty) {
+        Some(&Type::TemplateParam(ref param)) => {
+            if stack.get_template_param_decl(param.0).is_some() {
+                return None;
+            }
+            let arg = match arg_stack.get_template_arg(param.0) {
+                Ok(&TemplateArg::ArgPack(ref args)) => {
+                    match ctx.pack_index.and_then(|index| args.get(index)) {
+                        Some(arg) => arg,
+                        None => return None,
+                    }
+                }
+                Ok(arg) => arg,
+                Err(_) => return None,
+            };
+            match *arg {
+                TemplateArg::Type(ref ty) => ctx.subs.get_type(ty),
+                _ => None,
+            }
+        }
+        ty => ty,
+    };
+
+    match ty {
+        Some(&Type::LvalueRef(ref ty)) => Some((false, ty.clone())),
+        Some(&Type::RvalueRef(ref ty)) => Some((true, ty.clone())),
+        _ => None,
+    }
+}
+
+// Is `ty` a function type, possibly qualified by vendor extended qualifiers
+// such as `U7stdcall`?
+fn is_function_type(subs: &SubstitutionTable, ty: &TypeHandle) -> bool {
+    match subs.get_type(ty) {
+        Some(&Type::Function(_)) => true,
+        Some(&Type::VendorExtension(_, _, ref ty)) => is_function_type(subs, ty),
+        _ => false,
+    }
+}
+
 impl TypeHandle {
     fn is_void(&self) -> bool {
         match *self {
@@ -1736,19 +3507,157 @@ impl TypeHandle {
     }
 }
 
+// A pointer, reference, or other type constructor wrapping an inner type.
+// `TypeHandle::parse` collects these in a loop rather than recursing once per
+// layer, since they can be nested arbitrarily deep.
+enum TypeWrapper {
+    Qualified(CvQualifiers),
+    PointerTo,
+    LvalueRef,
+    RvalueRef,
+    Complex,
+    Imaginary,
+}
+
+impl TypeWrapper {
+    fn wrap(self, ty: TypeHandle) -> Type {
+        match self {
+            TypeWrapper::Qualified(qualifiers) => Type::Qualified(qualifiers, ty),
+            TypeWrapper::PointerTo => Type::PointerTo(ty),
+            TypeWrapper::LvalueRef => Type::LvalueRef(ty),
+            TypeWrapper::RvalueRef => Type::RvalueRef(ty),
+            TypeWrapper::Complex => Type::Complex(ty),
+            TypeWrapper::Imaginary => Type::Imaginary(ty),
+        }
+    }
+}
+
+// The kinds of production that begin with `D`, told apart by their second
+// byte, so that we can dispatch on them directly instead of trying to parse
+// each in turn.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DPrefix {
+    // `Dt` or `DT`
+    Decltype,
+    // `Dp`
+    PackExpansion,
+    // `Dx`, which only begins a transaction safe function type.
+    TransactionSafe,
+    // `D0`, `D1`, `D2`, etc.
+    Destructor,
+    // Everything else, such as `Dn`, `Dd`, and `Dk`.
+    BuiltinType,
+}
+
+// Classify the `D`-prefixed production at the start of `input`, or return
+// `None` if it doesn't start with `D` followed by another byte.
+fn peek_d_prefix(input: IndexStr) -> Option<DPrefix> {
+    let bytes = input.as_ref();
+    if bytes.len() < 2 || bytes[0] != b'D' {
+        return None;
+    }
+
+    Some(match bytes[1] {
+        b't' | b'T' => DPrefix::Decltype,
+        b'p' => DPrefix::PackExpansion,
+        b'x' => DPrefix::TransactionSafe,
+        c if b'0' <= c && c <= b'9' => DPrefix::Destructor,
+        _ => DPrefix::BuiltinType,
+    })
+}
+
 impl Parse for TypeHandle {
     fn parse<'a, 'b>(subs: &'a mut SubstitutionTable,
                      input: IndexStr<'b>)
                      -> Result<(TypeHandle, IndexStr<'b>)> {
+        parse_with_span("TypeHandle", subs, input, TypeHandle::parse_without_span)
+    }
+}
+
+impl TypeHandle {
+    fn parse_without_span<'a, 'b>(subs: &'a mut SubstitutionTable,
+                                  input: IndexStr<'b>)
+                                  -> Result<(TypeHandle, IndexStr<'b>)> {
         log_parse!("TypeHandle", input);
 
+        if input.is_empty() {
+            return Err(error::Error::UnexpectedEnd);
+        }
+
+        /// Insert the given type into the substitution table, and return a
+        /// handle referencing the index in the table where it ended up.
+        fn insert_and_return_handle<'a, 'b>(ty: Type,
+                                            subs: &'a mut SubstitutionTable,
+                                            tail: IndexStr<'b>)
+                                            -> Result<(TypeHandle, IndexStr<'b>)> {
+            let ty = Substitutable::Type(ty);
+            let idx = try!(subs.insert(ty));
+            let handle = TypeHandle::new_back_reference(subs, idx);
+            Ok((handle, tail))
+        }
+
+        // Pointers, references, and the like can be nested arbitrarily deep,
+        // as in `PPPPi`. Rather than recursing once per layer and risking a
+        // stack overflow, peel them off in a loop, parse the type that they
+        // wrap, and then wrap it back up from the inside out. That inserts
+        // each layer into the substitution table in the same order that
+        // recursing would.
+        let mut wrappers = vec![];
+        let mut tail = input;
+        loop {
+            let (wrapper, next) = match tail.peek() {
+                Some(b'P') => (TypeWrapper::PointerTo, tail.range_from(1..)),
+                Some(b'R') => (TypeWrapper::LvalueRef, tail.range_from(1..)),
+                Some(b'O') => (TypeWrapper::RvalueRef, tail.range_from(1..)),
+                Some(b'G') => (TypeWrapper::Imaginary, tail.range_from(1..)),
+                // `C` followed by a digit or `I` might be a constructor name,
+                // so leave it to the recursive parse below.
+                Some(b'C') => {
+                    match tail.range_from(1..).peek() {
+                        Some(c) if (b'0' <= c && c <= b'9') || c == b'I' => break,
+                        _ => (TypeWrapper::Complex, tail.range_from(1..)),
+                    }
+                }
+                // Likewise, qualifiers followed by `F` or `D` might belong to
+                // a function type, and `r` might begin an operator name.
+                Some(b'V') | Some(b'K') => {
+                    let (qualifiers, next) = try!(CvQualifiers::parse(subs, tail));
+                    match next.peek() {
+                        Some(b'F') | Some(b'D') => break,
+                        _ => (TypeWrapper::Qualified(qualifiers), next),
+                    }
+                }
+                _ => break,
+            };
+            wrappers.push((tail.index(), wrapper));
+            tail = next;
+        }
+
+        if !wrappers.is_empty() {
+            let (mut handle, tail) = try!(TypeHandle::parse(subs, tail));
+            while let Some((start, wrapper)) = wrappers.pop() {
+                handle = try!(insert_and_return_handle(wrapper.wrap(handle), subs, tail)).0;
+                // The outermost layer's span is recorded by `TypeHandle::parse`
+                // itself, just as every inner layer's would have been.
+                if !wrappers.is_empty() {
+                    subs.record_span("TypeHandle", start, tail.index());
+                }
+            }
+            return Ok((handle, tail));
+        }
+
         if let Ok((sub, tail)) = Substitution::parse(subs, input) {
             // If we see an 'I', then this is actually a substitution for a
             // <template-template-param>, and the template args are what
             // follows. Throw away what we just parsed, and re-parse it in
             // `TemplateTemplateParamHandle::parse` for now, but it would be
             // nice not to duplicate work we've already done.
-            if tail.peek() != Some(b'I') {
+            //
+            // Similarly, `St` on its own is not a type, but the `::std::`
+            // prefix of a <class-enum-type>'s name, which is handled by
+            // `ClassEnumType::parse` below.
+            if tail.peek() != Some(b'I') &&
+               sub != Substitution::WellKnown(WellKnownComponent::Std) {
                 match sub {
                     Substitution::WellKnown(component) => {
                         return Ok((TypeHandle::WellKnown(component), tail));
@@ -1756,22 +3665,31 @@ impl Parse for TypeHandle {
                     Substitution::BackReference(idx) => {
                         // TODO: should this check if the back reference actually points
                         // to a <type>?
-                        return Ok((TypeHandle::BackReference(idx), tail));
+                        return Ok((TypeHandle::new_back_reference(subs, idx), tail));
                     }
                 }
             }
         }
 
-        /// Insert the given type into the substitution table, and return a
-        /// handle referencing the index in the table where it ended up.
-        fn insert_and_return_handle<'a, 'b>(ty: Type,
-                                            subs: &'a mut SubstitutionTable,
-                                            tail: IndexStr<'b>)
-                                            -> Result<(TypeHandle, IndexStr<'b>)> {
-            let ty = Substitutable::Type(ty);
-            let idx = subs.insert(ty);
-            let handle = TypeHandle::BackReference(idx);
-            Ok((handle, tail))
+        match peek_d_prefix(input) {
+            Some(DPrefix::Decltype) => {
+                let (decltype, tail) = try!(Decltype::parse(subs, input));
+                let ty = Type::Decltype(decltype);
+                return insert_and_return_handle(ty, subs, tail);
+            }
+            Some(DPrefix::PackExpansion) => {
+                let (ty, tail) = try!(TypeHandle::parse(subs, input.range_from(2..)));
+                let ty = Type::PackExpansion(ty);
+                return insert_and_return_handle(ty, subs, tail);
+            }
+            Some(DPrefix::TransactionSafe) => {
+                let (funty, tail) = try!(FunctionType::parse(subs, input));
+                let ty = Type::Function(funty);
+                return insert_and_return_handle(ty, subs, tail);
+            }
+            Some(DPrefix::Destructor) => return Err(error::Error::UnexpectedText),
+            Some(DPrefix::BuiltinType) |
+            None => {}
         }
 
         if let Ok((builtin, tail)) = BuiltinType::parse(subs, input) {
@@ -1817,15 +3735,11 @@ impl Parse for TypeHandle {
             return insert_and_return_handle(ty, subs, tail);
         }
 
-        if let Ok((param, tail)) = Decltype::parse(subs, input) {
-            let ty = Type::Decltype(param);
-            return insert_and_return_handle(ty, subs, tail);
-        }
-
         if let Ok((qualifiers, tail)) = CvQualifiers::parse(subs, input) {
             // CvQualifiers can parse successfully without consuming any input,
             // but we don't want to recurse unless we know we did consume some
             // input, lest we go into an infinite loop and blow the stack.
+            debug_assert_eq!(tail.len() < input.len(), !qualifiers.is_empty());
             if tail.len() < input.len() {
                 let (ty, tail) = try!(TypeHandle::parse(subs, tail));
                 let ty = Type::Qualified(qualifiers, ty);
@@ -1876,10 +3790,14 @@ impl Parse for TypeHandle {
             return insert_and_return_handle(ty, subs, tail);
         }
 
-        let tail = try!(consume(b"Dp", input));
-        let (ty, tail) = try!(TypeHandle::parse(subs, tail));
-        let ty = Type::PackExpansion(ty);
-        insert_and_return_handle(ty, subs, tail)
+        // Every type that is a single byte long was handled above, so with
+        // fewer than two bytes left, the input was truncated -- unless that
+        // byte is the `E` that closes whatever encloses this type.
+        if input.len() < 2 && input.peek() != Some(b'E') {
+            Err(error::Error::UnexpectedEnd)
+        } else {
+            Err(error::Error::UnexpectedText)
+        }
     }
 }
 
@@ -1888,7 +3806,7 @@ impl DemangleWithInner for Type {
                                  inner: Option<&D>,
                                  ctx: &mut DemangleContext<W>,
                                  stack: Option<ArgStack>)
-                                 -> io::Result<()>
+                                 -> DemangleResult<()>
         where D: ?Sized + Demangle,
               W: io::Write
     {
@@ -1917,7 +3835,7 @@ impl DemangleWithInner for Type {
                                           inner: &D,
                                           ctx: &mut DemangleContext<W>,
                                           stack: Option<ArgStack>)
-                                          -> io::Result<()>
+                                          -> DemangleResult<()>
                     where D: ?Sized + Demangle,
                           W: io::Write
                 {
@@ -1928,6 +3846,10 @@ impl DemangleWithInner for Type {
                         Some(&Type::Function(ref func)) => {
                             func.demangle_with_inner(Some(inner), ctx, stack)
                         }
+                        Some(&Type::VendorExtension(ref name, ref args, ref ty))
+                            if is_function_type(ctx.subs, ty) => {
+                            demangle_vendor_qualified(name, args, ty, Some(inner), ctx, stack)
+                        }
                         _ => {
                             try!(ty.demangle(ctx, stack));
                             try!(inner.demangle(ctx, stack));
@@ -1946,6 +3868,12 @@ impl DemangleWithInner for Type {
                 }
             }
             Type::LvalueRef(ref ty) => {
+                // References to references collapse: `T&` is `U&` when `T` is
+                // either `U&` or `U&&`.
+                if let Some((_, referent)) = referenced_type(ctx, stack, ty) {
+                    return Type::LvalueRef(referent).demangle(ctx, stack);
+                }
+
                 match ctx.subs.get_type(ty) {
                     Some(&Type::Array(ref array_type)) => {
                         array_type.demangle_with_inner(Some("&"), ctx, stack)
@@ -1953,6 +3881,10 @@ impl DemangleWithInner for Type {
                     Some(&Type::Function(ref func)) => {
                         func.demangle_with_inner(Some("&"), ctx, stack)
                     }
+                    Some(&Type::VendorExtension(ref name, ref args, ref ty))
+                        if is_function_type(ctx.subs, ty) => {
+                        demangle_vendor_qualified(name, args, ty, Some("&"), ctx, stack)
+                    }
                     _ => {
                         try!(ty.demangle(ctx, stack));
                         try!(write!(ctx, "&"));
@@ -1961,6 +3893,11 @@ impl DemangleWithInner for Type {
                 }
             }
             Type::RvalueRef(ref ty) => {
+                // And `T&&` is just `T` when `T` is a reference.
+                if referenced_type(ctx, stack, ty).is_some() {
+                    return ty.demangle(ctx, stack);
+                }
+
                 match ctx.subs.get_type(ty) {
                     Some(&Type::Array(ref array_type)) => {
                         array_type.demangle_with_inner(Some("&&"), ctx, stack)
@@ -1968,6 +3905,10 @@ impl DemangleWithInner for Type {
                     Some(&Type::Function(ref func)) => {
                         func.demangle_with_inner(Some("&&"), ctx, stack)
                     }
+                    Some(&Type::VendorExtension(ref name, ref args, ref ty))
+                        if is_function_type(ctx.subs, ty) => {
+                        demangle_vendor_qualified(name, args, ty, Some("&&"), ctx, stack)
+                    }
                     _ => {
                         try!(ty.demangle(ctx, stack));
                         try!(write!(ctx, "&&"));
@@ -1977,28 +3918,207 @@ impl DemangleWithInner for Type {
             }
             Type::Complex(ref ty) => {
                 try!(ty.demangle(ctx, stack));
-                try!(write!(ctx, " _Complex"));
+                match ctx.options.complex_spelling {
+                    ComplexSpelling::C => try!(write!(ctx, " _Complex")),
+                    ComplexSpelling::Gnu => try!(write!(ctx, " __complex__")),
+                }
                 Ok(())
             }
             Type::Imaginary(ref ty) => {
                 try!(ty.demangle(ctx, stack));
-                try!(write!(ctx, " _Imaginary"));
+                match ctx.options.complex_spelling {
+                    ComplexSpelling::C => try!(write!(ctx, " _Imaginary")),
+                    ComplexSpelling::Gnu => try!(write!(ctx, " __imaginary__")),
+                }
                 Ok(())
             }
             Type::VendorExtension(ref name, ref template_args, ref ty) => {
-                try!(ty.demangle(ctx, stack));
-                try!(write!(ctx, " "));
-                try!(name.demangle(ctx, stack));
+                demangle_vendor_qualified(name, template_args, ty, inner, ctx, stack)
+            }
+            Type::PackExpansion(ref ty) => {
+                // Like libiberty, expand the pattern once per element of the
+                // pack it refers to when that pack is known, and only fall
+                // back to printing `pattern...` when it isn't.
+                let arg_stack = ctx.template_arg_stack(stack);
+                let len = match pack_expansion_length(ctx.subs, ty, arg_stack) {
+                    Some(len) => len,
+                    None => {
+                        try!(ty.demangle(ctx, stack));
+                        try!(write!(ctx, "..."));
+                        return Ok(());
+                    }
+                };
+                let saved = ctx.pack_index;
+                for index in 0..len {
+                    if index > 0 {
+                        try!(ctx.write_arg_separator());
+                    }
+                    ctx.pack_index = Some(index);
+                    let result = ty.demangle(ctx, stack);
+                    ctx.pack_index = saved;
+                    try!(result);
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+// Demangle the vendor extended qualifier `name<template_args>` on `ty`,
+// followed by `inner`.
+fn demangle_vendor_qualified<D, W>(name: &SourceName,
+                                   template_args: &Option<TemplateArgs>,
+                                   ty: &TypeHandle,
+                                   inner: Option<&D>,
+                                   ctx: &mut DemangleContext<W>,
+                                   stack: Option<ArgStack>)
+                                   -> DemangleResult<()>
+    where D: ?Sized + Demangle,
+          W: io::Write
+{
+    // The ` name<args>` qualifiers, innermost first, followed by whatever
+    // `inner` was given to print after them.
+    struct Qualifiers<'a, D: 'a + ?Sized> {
+        quals: Vec<(&'a SourceName, &'a Option<TemplateArgs>)>,
+        inner: Option<&'a D>,
+    }
+
+    impl<'a, D> Demangle for Qualifiers<'a, D>
+        where D: 'a + ?Sized + Demangle
+    {
+        fn demangle<W>(&self,
+                       ctx: &mut DemangleContext<W>,
+                       stack: Option<ArgStack>)
+                       -> DemangleResult<()>
+            where W: io::Write
+        {
+            for &(name, template_args) in &self.quals {
+                try!(write!(ctx, " "));
+                try!(name.demangle(ctx, stack));
                 if let Some(ref args) = *template_args {
                     try!(args.demangle(ctx, stack));
                 }
-                Ok(())
+            }
+            if let Some(inner) = self.inner {
+                try!(inner.demangle(ctx, stack));
+            }
+            Ok(())
+        }
+    }
+
+    // Like libiberty, qualifiers on a function type are printed along with
+    // any pointer to it, inside the parentheses before its parameter list, as
+    // in `void ( stdcall*)(int)`.
+    if is_function_type(ctx.subs, ty) {
+        let mut quals = vec![(name, template_args)];
+        let mut ty = ty;
+        while let Some(&Type::VendorExtension(ref name, ref args, ref inner_ty)) =
+            ctx.subs.get_type(ty) {
+            quals.push((name, args));
+            ty = inner_ty;
+        }
+        if let Some(&Type::Function(ref func)) = ctx.subs.get_type(ty) {
+            quals.reverse();
+            let quals = Qualifiers {
+                quals: quals,
+                inner: inner,
+            };
+            return func.demangle_with_inner(Some(&quals), ctx, stack);
+        }
+    }
+
+    let quals = Qualifiers {
+        quals: vec![(name, template_args)],
+        inner: inner,
+    };
+    try!(ty.demangle(ctx, stack));
+    quals.demangle(ctx, stack)
+}
+
+impl SemanticEq for Type {
+    fn semantic_eq(&self, ctx: &SemanticEqContext, other: &Type) -> bool {
+        match (self, other) {
+            (&Type::Function(ref lhs), &Type::Function(ref rhs)) => lhs.semantic_eq(ctx, rhs),
+            (&Type::ClassEnum(ref lhs), &Type::ClassEnum(ref rhs)) => lhs.semantic_eq(ctx, rhs),
+            (&Type::Array(ref lhs), &Type::Array(ref rhs)) => lhs.semantic_eq(ctx, rhs),
+            (&Type::PointerToMember(ref lhs), &Type::PointerToMember(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&Type::TemplateParam(ref lhs), &Type::TemplateParam(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&Type::TemplateTemplate(ref lhs_param, ref lhs_args),
+             &Type::TemplateTemplate(ref rhs_param, ref rhs_args)) => {
+                lhs_param.semantic_eq(ctx, rhs_param) &&
+                lhs_args.semantic_eq(ctx, rhs_args)
+            }
+            (&Type::Decltype(ref lhs), &Type::Decltype(ref rhs)) => lhs.semantic_eq(ctx, rhs),
+            (&Type::Qualified(ref lhs_cv_qualifiers, ref lhs_ty),
+             &Type::Qualified(ref rhs_cv_qualifiers, ref rhs_ty)) => {
+                lhs_cv_qualifiers.semantic_eq(ctx, rhs_cv_qualifiers) &&
+                lhs_ty.semantic_eq(ctx, rhs_ty)
+            }
+            (&Type::PointerTo(ref lhs), &Type::PointerTo(ref rhs)) => lhs.semantic_eq(ctx, rhs),
+            (&Type::LvalueRef(ref lhs), &Type::LvalueRef(ref rhs)) => lhs.semantic_eq(ctx, rhs),
+            (&Type::RvalueRef(ref lhs), &Type::RvalueRef(ref rhs)) => lhs.semantic_eq(ctx, rhs),
+            (&Type::Complex(ref lhs), &Type::Complex(ref rhs)) => lhs.semantic_eq(ctx, rhs),
+            (&Type::Imaginary(ref lhs), &Type::Imaginary(ref rhs)) => lhs.semantic_eq(ctx, rhs),
+            (&Type::VendorExtension(ref lhs_name, ref lhs_args, ref lhs_ty),
+             &Type::VendorExtension(ref rhs_name, ref rhs_args, ref rhs_ty)) => {
+                lhs_name.semantic_eq(ctx, rhs_name) &&
+                lhs_args.semantic_eq(ctx, rhs_args) &&
+                lhs_ty.semantic_eq(ctx, rhs_ty)
+            }
+            (&Type::PackExpansion(ref lhs), &Type::PackExpansion(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Remangle for Type {
+    fn remangle(&self, ctx: &mut RemangleContext) -> RemangleResult {
+        match *self {
+            Type::Function(ref func_ty) => func_ty.remangle(ctx),
+            Type::ClassEnum(ref cls_enum_ty) => cls_enum_ty.remangle(ctx),
+            Type::PointerToMember(ref ptm) => ptm.remangle(ctx),
+            Type::TemplateParam(ref param) => param.remangle(ctx),
+            Type::TemplateTemplate(ref param, ref args) => {
+                try!(param.remangle(ctx));
+                args.remangle(ctx)
+            }
+            Type::Qualified(ref quals, ref ty) => {
+                try!(quals.remangle(ctx));
+                ty.remangle(ctx)
+            }
+            Type::PointerTo(ref ty) => {
+                ctx.write(b"P");
+                ty.remangle(ctx)
+            }
+            Type::LvalueRef(ref ty) => {
+                ctx.write(b"R");
+                ty.remangle(ctx)
+            }
+            Type::RvalueRef(ref ty) => {
+                ctx.write(b"O");
+                ty.remangle(ctx)
+            }
+            Type::Complex(ref ty) => {
+                ctx.write(b"C");
+                ty.remangle(ctx)
+            }
+            Type::Imaginary(ref ty) => {
+                ctx.write(b"G");
+                ty.remangle(ctx)
             }
             Type::PackExpansion(ref ty) => {
-                try!(ty.demangle(ctx, stack));
-                try!(write!(ctx, "..."));
-                Ok(())
+                ctx.write(b"Dp");
+                ty.remangle(ctx)
             }
+            Type::Array(_) |
+            Type::Decltype(_) |
+            Type::VendorExtension(_, _, _) => Err(()),
         }
     }
 }
@@ -2018,6 +4138,13 @@ pub struct CvQualifiers {
     pub const_: bool,
 }
 
+impl CvQualifiers {
+    #[inline]
+    fn is_empty(&self) -> bool {
+        !self.restrict && !self.volatile && !self.const_
+    }
+}
+
 impl Parse for CvQualifiers {
     fn parse<'a, 'b>(_subs: &'a mut SubstitutionTable,
                      input: IndexStr<'b>)
@@ -2056,7 +4183,7 @@ impl Demangle for CvQualifiers {
     fn demangle<W>(&self,
                    ctx: &mut DemangleContext<W>,
                    _: Option<ArgStack>)
-                   -> io::Result<()>
+                   -> DemangleResult<()>
         where W: io::Write
     {
         if self.const_ {
@@ -2078,6 +4205,21 @@ impl Demangle for CvQualifiers {
     }
 }
 
+impl Remangle for CvQualifiers {
+    fn remangle(&self, ctx: &mut RemangleContext) -> RemangleResult {
+        if self.restrict {
+            ctx.write(b"r");
+        }
+        if self.volatile {
+            ctx.write(b"V");
+        }
+        if self.const_ {
+            ctx.write(b"K");
+        }
+        Ok(())
+    }
+}
+
 define_vocabulary! {
     /// A <ref-qualifier> production.
     ///
@@ -2121,12 +4263,17 @@ define_vocabulary! {
     ///                ::= De # IEEE 754r decimal floating point (128 bits)
     ///                ::= Df # IEEE 754r decimal floating point (32 bits)
     ///                ::= Dh # IEEE 754r half-precision floating point (16 bits)
+    ///                ::= DF16b # C++23 std::bfloat16_t
     ///                ::= Di # char32_t
     ///                ::= Ds # char16_t
     ///                ::= Da # auto
     ///                ::= Dc # decltype(auto)
     ///                ::= Dn # std::nullptr_t (i.e., decltype(nullptr))
     /// ```
+    ///
+    /// `StandardBuiltinType::vocabulary()` lists every mangled code with its
+    /// spelling. The spelling of `Dn` there is `std::nullptr_t`, but the
+    /// default `Flavor::GnuV3` prints it as `decltype(nullptr)`.
     #[derive(Clone, Debug, Hash, PartialEq, Eq)]
     pub enum StandardBuiltinType {
         Void             (b"v",  "void"),
@@ -2153,7 +4300,8 @@ define_vocabulary! {
         DecimalFloat64   (b"Dd", "_Decimal64"),
         DecimalFloat128  (b"De", "_Decimal128"),
         DecimalFloat32   (b"Df", "_Decimal32"),
-        DecimalFloat16   (b"Dh", "_Decimal16"),
+        Half             (b"Dh", "half"),
+        BFloat16         (b"DF16b", "std::bfloat16_t"),
         Char32           (b"Di", "char32_t"),
         Char16           (b"Ds", "char16_t"),
         Auto             (b"Da", "auto"),
@@ -2174,6 +4322,31 @@ pub enum BuiltinType {
     /// <builtin-type> ::= u <source-name>   # vendor extended type
     /// ```
     Extension(SourceName),
+
+    /// A placeholder type constrained by a concept, such as `C auto`.
+    ///
+    /// ```text
+    /// <builtin-type> ::= Dk <type-constraint>   # constrained auto
+    /// <type-constraint> ::= <name>
+    /// ```
+    ConstrainedAuto(Name),
+
+    /// A `decltype(auto)` placeholder type constrained by a concept.
+    ///
+    /// ```text
+    /// <builtin-type> ::= DK <type-constraint>   # constrained decltype(auto)
+    /// ```
+    ConstrainedDecltypeAuto(Name),
+
+    /// A GCC fixed-point type, such as `_Sat short _Fract`.
+    FixedPoint(FixedPointType),
+
+    /// An ISO/IEC TS 18661 binary floating point type, such as `_Float16`.
+    ///
+    /// ```text
+    /// <builtin-type> ::= DF <number> _   # _FloatN
+    /// ```
+    FloatN(usize),
 }
 
 impl Parse for BuiltinType {
@@ -2182,13 +4355,41 @@ impl Parse for BuiltinType {
                      -> Result<(BuiltinType, IndexStr<'b>)> {
         log_parse!("BuiltinType", input);
 
-        if let Ok((ty, tail)) = StandardBuiltinType::parse(subs, input) {
-            return Ok((BuiltinType::Standard(ty), tail));
+        let standard_err = match StandardBuiltinType::parse(subs, input) {
+            Ok((ty, tail)) => return Ok((BuiltinType::Standard(ty), tail)),
+            Err(e) => e,
+        };
+
+        if let Ok(tail) = consume(b"Dk", input) {
+            let (name, tail) = try!(Name::parse(subs, tail));
+            return Ok((BuiltinType::ConstrainedAuto(name), tail));
+        }
+
+        if let Ok(tail) = consume(b"DK", input) {
+            let (name, tail) = try!(Name::parse(subs, tail));
+            return Ok((BuiltinType::ConstrainedDecltypeAuto(name), tail));
+        }
+
+        if let Ok(tail) = consume(b"u", input) {
+            let (name, tail) = try!(SourceName::parse(subs, tail));
+            return Ok((BuiltinType::Extension(name), tail));
+        }
+
+        if let Ok(tail) = consume(b"DF", input) {
+            if let Ok((bits, tail)) = parse_number(10, false, tail) {
+                if let Ok(tail) = consume(b"_", tail) {
+                    return Ok((BuiltinType::FloatN(bits as _), tail));
+                }
+            }
+        }
+
+        if let Ok((ty, tail)) = FixedPointType::parse(subs, input) {
+            return Ok((BuiltinType::FixedPoint(ty), tail));
         }
 
-        let tail = try!(consume(b"u", input));
-        let (name, tail) = try!(SourceName::parse(subs, tail));
-        Ok((BuiltinType::Extension(name), tail))
+        // Nothing else matched, so the standard builtin type's error tells us
+        // whether the input was truncated (e.g. a lone `D`) or just invalid.
+        Err(standard_err)
     }
 }
 
@@ -2196,13 +4397,169 @@ impl Demangle for BuiltinType {
     fn demangle<W>(&self,
                    ctx: &mut DemangleContext<W>,
                    stack: Option<ArgStack>)
-                   -> io::Result<()>
+                   -> DemangleResult<()>
         where W: io::Write
     {
         match *self {
+            BuiltinType::Standard(StandardBuiltinType::Nullptr) => {
+                try!(ctx.write_nullptr_type());
+                Ok(())
+            }
             BuiltinType::Standard(ref ty) => ty.demangle(ctx, stack),
             BuiltinType::Extension(ref name) => name.demangle(ctx, stack),
+            BuiltinType::ConstrainedAuto(ref name) => {
+                try!(name.demangle(ctx, stack));
+                try!(write!(ctx, " auto"));
+                Ok(())
+            }
+            BuiltinType::ConstrainedDecltypeAuto(ref name) => {
+                try!(name.demangle(ctx, stack));
+                try!(write!(ctx, " decltype(auto)"));
+                Ok(())
+            }
+            BuiltinType::FixedPoint(ref ty) => ty.demangle(ctx, stack),
+            BuiltinType::FloatN(bits) => {
+                try!(write!(ctx, "_Float{}", bits));
+                Ok(())
+            }
+        }
+    }
+}
+
+impl SemanticEq for BuiltinType {
+    fn semantic_eq(&self, ctx: &SemanticEqContext, other: &BuiltinType) -> bool {
+        match (self, other) {
+            (&BuiltinType::Standard(ref lhs), &BuiltinType::Standard(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&BuiltinType::Extension(ref lhs), &BuiltinType::Extension(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&BuiltinType::ConstrainedAuto(ref lhs), &BuiltinType::ConstrainedAuto(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&BuiltinType::ConstrainedDecltypeAuto(ref lhs),
+             &BuiltinType::ConstrainedDecltypeAuto(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&BuiltinType::FixedPoint(ref lhs), &BuiltinType::FixedPoint(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&BuiltinType::FloatN(lhs), &BuiltinType::FloatN(rhs)) => lhs == rhs,
+            _ => false,
+        }
+    }
+}
+
+impl Remangle for BuiltinType {
+    fn remangle(&self, ctx: &mut RemangleContext) -> RemangleResult {
+        match *self {
+            BuiltinType::Standard(ref ty) => ty.remangle(ctx),
+            BuiltinType::Extension(ref name) => {
+                ctx.write(b"u");
+                name.remangle(ctx)
+            }
+            BuiltinType::FixedPoint(ref ty) => ty.remangle(ctx),
+            BuiltinType::FloatN(bits) => {
+                ctx.write(b"DF");
+                ctx.write(bits.to_string().as_bytes());
+                ctx.write(b"_");
+                Ok(())
+            }
+            BuiltinType::ConstrainedAuto(_) |
+            BuiltinType::ConstrainedDecltypeAuto(_) => Err(()),
+        }
+    }
+}
+
+/// A GCC fixed-point type.
+///
+/// ```text
+/// <builtin-type> ::= DF [<number>] <builtin-type> <number> <saturation>
+/// <saturation> ::= s  # saturating
+///              ::= n  # not saturating
+/// ```
+///
+/// The first number is the count of integral bits, which only `_Accum` types
+/// have, and the second is the count of fractional bits. The builtin type is
+/// the integer type whose size the fixed-point type has, such as `s` for
+/// `short _Fract`.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct FixedPointType {
+    integral_bits: Option<usize>,
+    length: StandardBuiltinType,
+    fractional_bits: usize,
+    saturating: bool,
+}
+
+impl Parse for FixedPointType {
+    fn parse<'a, 'b>(subs: &'a mut SubstitutionTable,
+                     input: IndexStr<'b>)
+                     -> Result<(FixedPointType, IndexStr<'b>)> {
+        log_parse!("FixedPointType", input);
+
+        let tail = try!(consume(b"DF", input));
+
+        let (integral_bits, tail) = match parse_number(10, false, tail) {
+            Ok((bits, tail)) => (Some(bits as _), tail),
+            Err(_) => (None, tail),
+        };
+
+        let (length, tail) = try!(StandardBuiltinType::parse(subs, tail));
+        let (fractional_bits, tail) = try!(parse_number(10, false, tail));
+
+        let (saturating, tail) = if let Ok(tail) = consume(b"s", tail) {
+            (true, tail)
+        } else {
+            (false, try!(consume(b"n", tail)))
+        };
+
+        let ty = FixedPointType {
+            integral_bits: integral_bits,
+            length: length,
+            fractional_bits: fractional_bits as _,
+            saturating: saturating,
+        };
+        Ok((ty, tail))
+    }
+}
+
+impl Demangle for FixedPointType {
+    fn demangle<W>(&self,
+                   ctx: &mut DemangleContext<W>,
+                   stack: Option<ArgStack>)
+                   -> DemangleResult<()>
+        where W: io::Write
+    {
+        if self.saturating {
+            try!(write!(ctx, "_Sat "));
+        }
+
+        // Like libiberty, don't print `int _Accum`.
+        if self.length != StandardBuiltinType::Int {
+            try!(self.length.demangle(ctx, stack));
+            try!(write!(ctx, " "));
         }
+
+        if self.integral_bits.is_some() {
+            try!(write!(ctx, "_Accum"));
+        } else {
+            try!(write!(ctx, "_Fract"));
+        }
+        Ok(())
+    }
+}
+
+impl Remangle for FixedPointType {
+    fn remangle(&self, ctx: &mut RemangleContext) -> RemangleResult {
+        ctx.write(b"DF");
+        if let Some(bits) = self.integral_bits {
+            ctx.write(bits.to_string().as_bytes());
+        }
+        try!(self.length.remangle(ctx));
+        ctx.write(self.fractional_bits.to_string().as_bytes());
+        ctx.write(if self.saturating { b"s" } else { b"n" });
+        Ok(())
     }
 }
 
@@ -2274,15 +4631,50 @@ impl DemangleWithInner for FunctionType {
                                  inner: Option<&D>,
                                  ctx: &mut DemangleContext<W>,
                                  stack: Option<ArgStack>)
-                                 -> io::Result<()>
+                                 -> DemangleResult<()>
         where D: ?Sized + Demangle,
               W: io::Write
     {
         // TODO: transactions safety?
         // TODO: extern C?
         try!(self.bare.demangle_with_inner(inner, ctx, stack));
+        // These qualify the implicit object parameter of a member function,
+        // and are printed after the parameter list just like those of a
+        // `<nested-name>` are.
         try!(self.cv_qualifiers.demangle(ctx, stack));
-        // TODO: ref_qualifier?
+        if let Some(ref refs) = self.ref_qualifier {
+            try!(ctx.ensure_space());
+            try!(refs.demangle(ctx, stack));
+        }
+        Ok(())
+    }
+}
+
+impl SemanticEq for FunctionType {
+    fn semantic_eq(&self, ctx: &SemanticEqContext, other: &FunctionType) -> bool {
+        self.cv_qualifiers == other.cv_qualifiers &&
+        self.transaction_safe == other.transaction_safe &&
+        self.extern_c == other.extern_c &&
+        self.bare.semantic_eq(ctx, &other.bare) &&
+        self.ref_qualifier == other.ref_qualifier
+    }
+}
+
+impl Remangle for FunctionType {
+    fn remangle(&self, ctx: &mut RemangleContext) -> RemangleResult {
+        try!(self.cv_qualifiers.remangle(ctx));
+        if self.transaction_safe {
+            ctx.write(b"Dx");
+        }
+        ctx.write(b"F");
+        if self.extern_c {
+            ctx.write(b"Y");
+        }
+        try!(self.bare.remangle(ctx));
+        if let Some(ref refs) = self.ref_qualifier {
+            try!(refs.remangle(ctx));
+        }
+        ctx.write(b"E");
         Ok(())
     }
 }
@@ -2322,7 +4714,7 @@ impl DemangleWithInner for BareFunctionType {
                                  inner: Option<&D>,
                                  ctx: &mut DemangleContext<W>,
                                  stack: Option<ArgStack>)
-                                 -> io::Result<()>
+                                 -> DemangleResult<()>
         where D: ?Sized + Demangle,
               W: io::Write
     {
@@ -2340,6 +4732,20 @@ impl DemangleWithInner for BareFunctionType {
     }
 }
 
+impl SemanticEq for BareFunctionType {
+    fn semantic_eq(&self, ctx: &SemanticEqContext, other: &BareFunctionType) -> bool {
+        let BareFunctionType(ref lhs) = *self;
+        let BareFunctionType(ref rhs) = *other;
+        lhs.semantic_eq(ctx, rhs)
+    }
+}
+
+impl Remangle for BareFunctionType {
+    fn remangle(&self, ctx: &mut RemangleContext) -> RemangleResult {
+        self.0.remangle(ctx)
+    }
+}
+
 /// The `<decltype>` production.
 ///
 /// ```text
@@ -2380,10 +4786,13 @@ impl Demangle for Decltype {
     fn demangle<W>(&self,
                    ctx: &mut DemangleContext<W>,
                    stack: Option<ArgStack>)
-                   -> io::Result<()>
+                   -> DemangleResult<()>
         where W: io::Write
     {
         match *self {
+            // Whether the operand is an id-expression changes what type the
+            // `decltype` denotes, but not how it is spelled, so (like
+            // libiberty) both print the same.
             Decltype::Expression(ref expr) |
             Decltype::IdExpression(ref expr) => {
                 try!(write!(ctx, "decltype ("));
@@ -2395,6 +4804,20 @@ impl Demangle for Decltype {
     }
 }
 
+impl SemanticEq for Decltype {
+    fn semantic_eq(&self, ctx: &SemanticEqContext, other: &Decltype) -> bool {
+        match (self, other) {
+            (&Decltype::IdExpression(ref lhs), &Decltype::IdExpression(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&Decltype::Expression(ref lhs), &Decltype::Expression(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            _ => false,
+        }
+    }
+}
+
 /// The `<class-enum-type>` production.
 ///
 /// ```text
@@ -2451,7 +4874,7 @@ impl Demangle for ClassEnumType {
     fn demangle<W>(&self,
                    ctx: &mut DemangleContext<W>,
                    stack: Option<ArgStack>)
-                   -> io::Result<()>
+                   -> DemangleResult<()>
         where W: io::Write
     {
         match *self {
@@ -2472,6 +4895,49 @@ impl Demangle for ClassEnumType {
     }
 }
 
+impl SemanticEq for ClassEnumType {
+    fn semantic_eq(&self, ctx: &SemanticEqContext, other: &ClassEnumType) -> bool {
+        match (self, other) {
+            (&ClassEnumType::Named(ref lhs), &ClassEnumType::Named(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&ClassEnumType::ElaboratedStruct(ref lhs),
+             &ClassEnumType::ElaboratedStruct(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&ClassEnumType::ElaboratedUnion(ref lhs),
+             &ClassEnumType::ElaboratedUnion(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&ClassEnumType::ElaboratedEnum(ref lhs), &ClassEnumType::ElaboratedEnum(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Remangle for ClassEnumType {
+    fn remangle(&self, ctx: &mut RemangleContext) -> RemangleResult {
+        let name = match *self {
+            ClassEnumType::Named(ref name) => name,
+            ClassEnumType::ElaboratedStruct(ref name) => {
+                ctx.write(b"Ts");
+                name
+            }
+            ClassEnumType::ElaboratedUnion(ref name) => {
+                ctx.write(b"Tu");
+                name
+            }
+            ClassEnumType::ElaboratedEnum(ref name) => {
+                ctx.write(b"Te");
+                name
+            }
+        };
+        name.remangle(ctx)
+    }
+}
+
 /// The `<unnamed-type-name>` production.
 ///
 /// ```text
@@ -2479,7 +4945,8 @@ impl Demangle for ClassEnumType {
 ///                     ::= <closure-type-name>
 /// ```
 ///
-/// TODO: parse the <closure-type-name> variant
+/// The `<closure-type-name>` variant is parsed separately, as
+/// `UnqualifiedName::ClosureType`.
 #[derive(Clone, Debug, Hash, PartialEq, Eq)]
 pub struct UnnamedTypeName(Option<usize>);
 
@@ -2510,10 +4977,10 @@ impl Demangle for UnnamedTypeName {
     fn demangle<W>(&self,
                    ctx: &mut DemangleContext<W>,
                    _: Option<ArgStack>)
-                   -> io::Result<()>
+                   -> DemangleResult<()>
         where W: io::Write
     {
-        try!(write!(ctx, "{{unnamed type {}}}", self.0.map_or(0, |n| n + 1)));
+        try!(ctx.write_unnamed_type(self.0));
         Ok(())
     }
 }
@@ -2563,53 +5030,97 @@ impl Parse for ArrayType {
     }
 }
 
+impl ArrayType {
+    fn element_type(&self) -> &TypeHandle {
+        match *self {
+            ArrayType::DimensionNumber(_, ref ty) |
+            ArrayType::DimensionExpression(_, ref ty) |
+            ArrayType::NoDimension(ref ty) => ty,
+        }
+    }
+
+    fn demangle_dimension<W>(&self,
+                             ctx: &mut DemangleContext<W>,
+                             stack: Option<ArgStack>)
+                             -> DemangleResult<()>
+        where W: io::Write
+    {
+        match *self {
+            ArrayType::DimensionNumber(n, _) => {
+                try!(write!(ctx, "[{}]", n));
+            }
+            ArrayType::DimensionExpression(ref expr, _) => {
+                // The brackets already delimit the dimension, so the
+                // expression itself is never wrapped in parentheses here; only
+                // the operands of binary operators within it are.
+                try!(write!(ctx, "["));
+                try!(expr.demangle(ctx, stack));
+                try!(write!(ctx, "]"));
+            }
+            ArrayType::NoDimension(_) => {
+                try!(write!(ctx, "[]"));
+            }
+        }
+        Ok(())
+    }
+}
+
 impl DemangleWithInner for ArrayType {
     fn demangle_with_inner<D, W>(&self,
                               inner: Option<&D>,
                               ctx: &mut DemangleContext<W>,
                               stack: Option<ArgStack>)
-                              -> io::Result<()>
+                              -> DemangleResult<()>
         where D: ?Sized + Demangle,
               W: io::Write
     {
-        match *self {
-            ArrayType::DimensionNumber(n, ref ty) => {
-                try!(ty.demangle(ctx, stack));
-                if let Some(inner) = inner {
-                    try!(write!(ctx, " ("));
-                    try!(inner.demangle(ctx, stack));
-                    try!(write!(ctx, ") [{}]", n));
-                } else {
-                    try!(write!(ctx, " [{}]", n));
-                }
-            }
-            ArrayType::DimensionExpression(ref expr, ref ty) => {
-                try!(ty.demangle(ctx, stack));
-                if let Some(inner) = inner {
-                    try!(write!(ctx, " ("));
-                    try!(inner.demangle(ctx, stack));
-                    try!(write!(ctx, ") ["));
-                } else {
-                    try!(write!(ctx, " ["));
-                }
-                try!(expr.demangle(ctx, stack));
-                try!(write!(ctx, "]"));
-            }
-            ArrayType::NoDimension(ref ty) => {
-                try!(ty.demangle(ctx, stack));
-                if let Some(inner) = inner {
-                    try!(write!(ctx, " ("));
-                    try!(inner.demangle(ctx, stack));
-                    try!(write!(ctx, ") []"));
-                } else {
-                    try!(write!(ctx, " []"));
-                }
-            }
+        // A multi-dimensional array is an array of arrays, and `A10_A2_i` is
+        // `int [10][2]`: print the innermost element type, and then every
+        // dimension from the outermost inwards.
+        let subs = ctx.subs;
+        let mut dimensions = vec![self];
+        let mut element = self.element_type();
+        while let Some(&Type::Array(ref array)) = subs.get_type(element) {
+            dimensions.push(array);
+            element = array.element_type();
+        }
+
+        try!(element.demangle(ctx, stack));
+        if let Some(inner) = inner {
+            try!(write!(ctx, " ("));
+            try!(inner.demangle(ctx, stack));
+            try!(write!(ctx, ") "));
+        } else {
+            try!(write!(ctx, " "));
+        }
+        for dimension in dimensions {
+            try!(dimension.demangle_dimension(ctx, stack));
         }
         Ok(())
     }
 }
 
+impl SemanticEq for ArrayType {
+    fn semantic_eq(&self, ctx: &SemanticEqContext, other: &ArrayType) -> bool {
+        match (self, other) {
+            (&ArrayType::DimensionNumber(ref lhs_n, ref lhs_ty),
+             &ArrayType::DimensionNumber(ref rhs_n, ref rhs_ty)) => {
+                lhs_n.semantic_eq(ctx, rhs_n) &&
+                lhs_ty.semantic_eq(ctx, rhs_ty)
+            }
+            (&ArrayType::DimensionExpression(ref lhs_expr, ref lhs_ty),
+             &ArrayType::DimensionExpression(ref rhs_expr, ref rhs_ty)) => {
+                lhs_expr.semantic_eq(ctx, rhs_expr) &&
+                lhs_ty.semantic_eq(ctx, rhs_ty)
+            }
+            (&ArrayType::NoDimension(ref lhs), &ArrayType::NoDimension(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            _ => false,
+        }
+    }
+}
+
 /// The `<pointer-to-member-type>` production.
 ///
 /// ```text
@@ -2635,22 +5146,60 @@ impl Demangle for PointerToMemberType {
     fn demangle<W>(&self,
                    ctx: &mut DemangleContext<W>,
                    stack: Option<ArgStack>)
-                   -> io::Result<()>
+                   -> DemangleResult<()>
         where W: io::Write
     {
-        if let Some(&Type::Function(ref func)) = ctx.subs.get_type(&self.1) {
-            let ptm = Concat(&self.0, "::*");
-            func.demangle_with_inner(Some(&ptm), ctx, stack)
-        } else {
-            try!(self.1.demangle(ctx, stack));
-            try!(write!(ctx, " "));
-            try!(self.0.demangle(ctx, stack));
-            try!(write!(ctx, "::*"));
-            Ok(())
+        // A pointer to a member whose type is a template parameter is
+        // printed according to the argument that it is substituted with, so
+        // that `M1AT_` with `T = void () const` is `void (A::*)() const`.
+        let arg_stack = ctx.template_arg_stack(stack);
+        let member_ty = match ctx.subs.get_type(&self.1) {
+            Some(&Type::TemplateParam(ref param)) => {
+                match arg_stack.get_template_arg(param.0) {
+                    Ok(&TemplateArg::Type(ref ty)) => ty,
+                    _ => &self.1,
+                }
+            }
+            _ => &self.1,
+        };
+
+        match ctx.subs.get_type(member_ty) {
+            Some(&Type::Function(ref func)) => {
+                let ptm = Concat(&self.0, "::*");
+                func.demangle_with_inner(Some(&ptm), ctx, stack)
+            }
+            Some(&Type::Array(ref array)) => {
+                let ptm = Concat(&self.0, "::*");
+                array.demangle_with_inner(Some(&ptm), ctx, stack)
+            }
+            _ => {
+                try!(self.1.demangle(ctx, stack));
+                try!(write!(ctx, " "));
+                try!(self.0.demangle(ctx, stack));
+                try!(write!(ctx, "::*"));
+                Ok(())
+            }
         }
     }
 }
 
+impl SemanticEq for PointerToMemberType {
+    fn semantic_eq(&self, ctx: &SemanticEqContext, other: &PointerToMemberType) -> bool {
+        let PointerToMemberType(ref lhs_class_ty, ref lhs_member_ty) = *self;
+        let PointerToMemberType(ref rhs_class_ty, ref rhs_member_ty) = *other;
+        lhs_class_ty.semantic_eq(ctx, rhs_class_ty) &&
+        lhs_member_ty.semantic_eq(ctx, rhs_member_ty)
+    }
+}
+
+impl Remangle for PointerToMemberType {
+    fn remangle(&self, ctx: &mut RemangleContext) -> RemangleResult {
+        ctx.write(b"M");
+        try!(self.0.remangle(ctx));
+        self.1.remangle(ctx)
+    }
+}
+
 /// The `<template-param>` production.
 ///
 /// ```text
@@ -2680,15 +5229,44 @@ impl Demangle for TemplateParam {
     fn demangle<W>(&self,
                    ctx: &mut DemangleContext<W>,
                    stack: Option<ArgStack>)
-                   -> io::Result<()>
+                   -> DemangleResult<()>
         where W: io::Write
     {
-        let arg = try!(stack.get_template_arg(self.0)
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, e.description())));
+        if let Some(decl) = stack.get_template_param_decl(self.0) {
+            return decl.demangle_name(ctx, self.0);
+        }
+
+        if ctx.options.raw_template_params {
+            if self.0 == 0 {
+                try!(write!(ctx, "T_"));
+            } else {
+                try!(write!(ctx, "T{}_", self.0 - 1));
+            }
+            return Ok(());
+        }
+
+        let arg = try!(stack.get_template_arg(self.0));
+        if let (&TemplateArg::ArgPack(ref args), Some(index)) = (arg, ctx.pack_index) {
+            if let Some(arg) = args.get(index) {
+                return arg.demangle(ctx, stack);
+            }
+        }
         arg.demangle(ctx, stack)
     }
 }
 
+impl Remangle for TemplateParam {
+    fn remangle(&self, ctx: &mut RemangleContext) -> RemangleResult {
+        if self.0 == 0 {
+            ctx.write(b"T_");
+        } else {
+            let n = format!("T{}_", self.0 - 1);
+            ctx.write(n.as_bytes());
+        }
+        Ok(())
+    }
+}
+
 /// The `<template-template-param>` production.
 ///
 /// ```text
@@ -2719,7 +5297,7 @@ impl Parse for TemplateTemplateParamHandle {
                     // TODO: should this check if the thing at idx is a
                     // template-template-param? There could otherwise be ambiguity
                     // with <type>'s <substitution> form...
-                    return Ok((TemplateTemplateParamHandle::BackReference(idx), tail));
+                    return Ok((TemplateTemplateParamHandle::new_back_reference(subs, idx), tail));
                 }
             }
         }
@@ -2727,8 +5305,8 @@ impl Parse for TemplateTemplateParamHandle {
         let (param, tail) = try!(TemplateParam::parse(subs, input));
         let ttp = TemplateTemplateParam(param);
         let ttp = Substitutable::TemplateTemplateParam(ttp);
-        let idx = subs.insert(ttp);
-        let handle = TemplateTemplateParamHandle::BackReference(idx);
+        let idx = try!(subs.insert(ttp));
+        let handle = TemplateTemplateParamHandle::new_back_reference(subs, idx);
         Ok((handle, tail))
     }
 }
@@ -2738,13 +5316,27 @@ impl Demangle for TemplateTemplateParam {
     fn demangle<W>(&self,
                    ctx: &mut DemangleContext<W>,
                    stack: Option<ArgStack>)
-                   -> io::Result<()>
+                   -> DemangleResult<()>
         where W: io::Write
     {
         self.0.demangle(ctx, stack)
     }
 }
 
+impl SemanticEq for TemplateTemplateParam {
+    fn semantic_eq(&self, ctx: &SemanticEqContext, other: &TemplateTemplateParam) -> bool {
+        let TemplateTemplateParam(ref lhs) = *self;
+        let TemplateTemplateParam(ref rhs) = *other;
+        lhs.semantic_eq(ctx, rhs)
+    }
+}
+
+impl Remangle for TemplateTemplateParam {
+    fn remangle(&self, ctx: &mut RemangleContext) -> RemangleResult {
+        self.0.remangle(ctx)
+    }
+}
+
 /// The <function-param> production.
 ///
 /// ```text
@@ -2796,13 +5388,20 @@ impl Demangle for FunctionParam {
     fn demangle<W>(&self,
                    ctx: &mut DemangleContext<W>,
                    stack: Option<ArgStack>)
-                   -> io::Result<()>
+                   -> DemangleResult<()>
         where W: io::Write
     {
-        // TODO: this needs more finesse.
-        let ty = try!(stack.get_function_arg(self.0)
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, e.description())));
-        ty.demangle(ctx, stack)
+        // `fp_` is the first parameter, and `fp0_` the second.
+        let index = self.2.map_or(0, |n| n + 1);
+        match stack.get_function_arg(index) {
+            Ok(ty) => ty.demangle(ctx, stack),
+            // Like libiberty, fall back to the parameter's position when we
+            // have nothing better to print.
+            Err(_) => {
+                try!(write!(ctx, "{{parm#{}}}", index + 1));
+                Ok(())
+            }
+        }
     }
 }
 
@@ -2811,6 +5410,10 @@ impl Demangle for FunctionParam {
 /// ```text
 /// <template-args> ::= I <template-arg>+ E
 /// ```
+///
+/// There must be at least one `<template-arg>`, so `IE` is never valid. A
+/// template instantiated with no arguments, such as `f<>()` for a variadic
+/// `f`, is mangled with a single empty argument pack instead: `IJEE`.
 #[derive(Clone, Debug, Hash, PartialEq, Eq)]
 pub struct TemplateArgs(Vec<TemplateArg>);
 
@@ -2818,6 +5421,14 @@ impl Parse for TemplateArgs {
     fn parse<'a, 'b>(subs: &'a mut SubstitutionTable,
                      input: IndexStr<'b>)
                      -> Result<(TemplateArgs, IndexStr<'b>)> {
+        parse_with_span("TemplateArgs", subs, input, TemplateArgs::parse_without_span)
+    }
+}
+
+impl TemplateArgs {
+    fn parse_without_span<'a, 'b>(subs: &'a mut SubstitutionTable,
+                                  input: IndexStr<'b>)
+                                  -> Result<(TemplateArgs, IndexStr<'b>)> {
         log_parse!("TemplateArgs", input);
 
         let tail = try!(consume(b"I", input));
@@ -2832,18 +5443,70 @@ impl Demangle for TemplateArgs {
     fn demangle<W>(&self,
                    ctx: &mut DemangleContext<W>,
                    stack: Option<ArgStack>)
-                   -> io::Result<()>
+                   -> DemangleResult<()>
+        where W: io::Write
+    {
+        try!(ctx.markup(Markup::Start(MarkupRegion::TemplateArgs)));
+
+        if let Some(max_depth) = ctx.options.max_template_depth {
+            if ctx.template_depth >= max_depth {
+                try!(write!(ctx, "<...>"));
+                try!(ctx.markup(Markup::End(MarkupRegion::TemplateArgs)));
+                return Ok(());
+            }
+        }
+
+        ctx.template_depth += 1;
+        let ret = self.demangle_args(ctx, stack);
+        ctx.template_depth -= 1;
+        try!(ret);
+
+        try!(ctx.markup(Markup::End(MarkupRegion::TemplateArgs)));
+        Ok(())
+    }
+}
+
+impl SemanticEq for TemplateArgs {
+    fn semantic_eq(&self, ctx: &SemanticEqContext, other: &TemplateArgs) -> bool {
+        let TemplateArgs(ref lhs) = *self;
+        let TemplateArgs(ref rhs) = *other;
+        lhs.semantic_eq(ctx, rhs)
+    }
+}
+
+impl Remangle for TemplateArgs {
+    fn remangle(&self, ctx: &mut RemangleContext) -> RemangleResult {
+        ctx.write(b"I");
+        try!(self.0.remangle(ctx));
+        ctx.write(b"E");
+        Ok(())
+    }
+}
+
+impl TemplateArgs {
+    fn demangle_args<W>(&self,
+                        ctx: &mut DemangleContext<W>,
+                        stack: Option<ArgStack>)
+                        -> DemangleResult<()>
         where W: io::Write
     {
         try!(write!(ctx, "<"));
         let mut need_comma = false;
-        for arg in &self.0[..] {
+        for arg in self.0.iter().filter(|arg| !arg.is_empty_pack()) {
             if need_comma {
-                try!(write!(ctx, ", "));
+                try!(ctx.write_arg_separator());
+            }
+            if let TemplateArg::Type(ref ty) = *arg {
+                try!(ctx.demangle_region(MarkupRegion::Type, ty, stack));
+            } else {
+                try!(arg.demangle(ctx, stack));
             }
-            try!(arg.demangle(ctx, stack));
             need_comma = true;
         }
+        // Like libiberty, keep nested closing brackets from running together.
+        if let Some(b'>') = ctx.last_byte_written {
+            try!(write!(ctx, " "));
+        }
         try!(write!(ctx, ">"));
         Ok(())
     }
@@ -2866,6 +5529,7 @@ impl ArgResolver for TemplateArgs {
 ///                ::= X <expression> E      # expression
 ///                ::= <expr-primary>        # simple expressions
 ///                ::= J <template-arg>* E   # argument pack
+///                ::= I <template-arg>* E   # argument pack (old GCC)
 /// ```
 #[derive(Clone, Debug, Hash, PartialEq, Eq)]
 pub enum TemplateArg {
@@ -2888,6 +5552,10 @@ impl Parse for TemplateArg {
                      -> Result<(TemplateArg, IndexStr<'b>)> {
         log_parse!("TemplateArg", input);
 
+        if input.is_empty() {
+            return Err(error::Error::UnexpectedEnd);
+        }
+
         if let Ok(tail) = consume(b"X", input) {
             let (expr, tail) = try!(Expression::parse(subs, tail));
             let tail = try!(consume(b"E", tail));
@@ -2902,7 +5570,12 @@ impl Parse for TemplateArg {
             return Ok((TemplateArg::Type(ty), tail));
         }
 
-        let tail = try!(consume(b"J", input));
+        // Older versions of GCC mangled argument packs with `I` rather than
+        // `J`, and libiberty still accepts either.
+        let tail = match consume(b"J", input) {
+            Ok(tail) => tail,
+            Err(_) => try!(consume(b"I", input)),
+        };
         let (args, tail) = if tail.peek() == Some(b'E') {
             (vec![], tail)
         } else {
@@ -2913,11 +5586,22 @@ impl Parse for TemplateArg {
     }
 }
 
+impl TemplateArg {
+    // Empty argument packs print as nothing at all, so they mustn't be
+    // separated from their neighbors by a comma either.
+    fn is_empty_pack(&self) -> bool {
+        match *self {
+            TemplateArg::ArgPack(ref args) => args.iter().all(TemplateArg::is_empty_pack),
+            _ => false,
+        }
+    }
+}
+
 impl Demangle for TemplateArg {
     fn demangle<W>(&self,
                    ctx: &mut DemangleContext<W>,
                    stack: Option<ArgStack>)
-                   -> io::Result<()>
+                   -> DemangleResult<()>
         where W: io::Write
     {
         match *self {
@@ -2926,7 +5610,7 @@ impl Demangle for TemplateArg {
             TemplateArg::SimpleExpression(ref expr) => expr.demangle(ctx, stack),
             TemplateArg::ArgPack(ref args) => {
                 let mut need_comma = false;
-                for arg in &args[..] {
+                for arg in args.iter().filter(|arg| !arg.is_empty_pack()) {
                     if need_comma {
                         try!(write!(ctx, ", "));
                     }
@@ -2939,6 +5623,42 @@ impl Demangle for TemplateArg {
     }
 }
 
+impl SemanticEq for TemplateArg {
+    fn semantic_eq(&self, ctx: &SemanticEqContext, other: &TemplateArg) -> bool {
+        match (self, other) {
+            (&TemplateArg::Type(ref lhs), &TemplateArg::Type(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&TemplateArg::Expression(ref lhs), &TemplateArg::Expression(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&TemplateArg::SimpleExpression(ref lhs), &TemplateArg::SimpleExpression(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&TemplateArg::ArgPack(ref lhs), &TemplateArg::ArgPack(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Remangle for TemplateArg {
+    fn remangle(&self, ctx: &mut RemangleContext) -> RemangleResult {
+        match *self {
+            TemplateArg::Type(ref ty) => ty.remangle(ctx),
+            TemplateArg::SimpleExpression(ref expr) => expr.remangle(ctx),
+            TemplateArg::ArgPack(ref args) => {
+                ctx.write(b"J");
+                try!(args.remangle(ctx));
+                ctx.write(b"E");
+                Ok(())
+            }
+            TemplateArg::Expression(_) => Err(()),
+        }
+    }
+}
+
 /// The `<expression>` production.
 ///
 /// ```text
@@ -2980,6 +5700,9 @@ impl Demangle for TemplateArg {
 ///               ::= sp <expression>                              # expression..., pack expansion
 ///               ::= tw <expression>                              # throw expression
 ///               ::= tr                                           # throw with no operand (rethrow)
+///               ::= rq <requirement>+ E                          # requires { requirements }
+///               ::= rQ <bare-function-type> _ <requirement>+ E   # requires (params) { requirements }
+///               ::= u <source-name> <template-arg>* E            # vendor extended expression
 ///               ::= <unresolved-name>                            # f(p), N::f(p), ::f(p),
 ///                                                                # freestanding dependent name (e.g., T::x),
 ///                                                                # objectless nonstatic member reference
@@ -3108,6 +5831,16 @@ pub enum Expression {
     /// `throw` with no operand
     Rethrow,
 
+    /// `requires { requirements }`, a C++20 requires-expression.
+    Requires(Vec<Requirement>),
+
+    /// `requires (params) { requirements }`, a C++20 requires-expression
+    /// with a parameter list.
+    RequiresWithParams(Vec<TypeHandle>, Vec<Requirement>),
+
+    /// A vendor extended expression, `name(args)`.
+    VendorExtension(SourceName, Vec<TemplateArg>),
+
     /// `f(p)`, `N::f(p)`, `::f(p)`, freestanding dependent name (e.g., `T::x`),
     /// objectless nonstatic member reference.
     UnresolvedName(UnresolvedName),
@@ -3122,6 +5855,10 @@ impl Parse for Expression {
                      -> Result<(Expression, IndexStr<'b>)> {
         log_parse!("Expression", input);
 
+        if input.is_empty() {
+            return Err(error::Error::UnexpectedEnd);
+        }
+
         if let Ok(tail) = consume(b"pp_", input) {
             let (expr, tail) = try!(Expression::parse(subs, tail));
             let expr = Expression::PrefixInc(Box::new(expr));
@@ -3139,6 +5876,7 @@ impl Parse for Expression {
                 b"cl" => {
                     let (func, tail) = try!(Expression::parse(subs, tail));
                     let (args, tail) = try!(zero_or_more::<Expression>(subs, tail));
+                    let tail = try!(consume(b"E", tail));
                     let expr = Expression::Call(Box::new(func), args);
                     return Ok((expr, tail));
                 }
@@ -3276,13 +6014,45 @@ impl Parse for Expression {
                     let expr = Expression::Rethrow;
                     return Ok((expr, tail));
                 }
+                b"rq" => {
+                    let (reqs, tail) = try!(one_or_more::<Requirement>(subs, tail));
+                    let tail = try!(consume(b"E", tail));
+                    let expr = Expression::Requires(reqs);
+                    return Ok((expr, tail));
+                }
+                b"rQ" => {
+                    let (params, tail) = try!(one_or_more::<TypeHandle>(subs, tail));
+                    let tail = try!(consume(b"_", tail));
+                    let (reqs, tail) = try!(one_or_more::<Requirement>(subs, tail));
+                    let tail = try!(consume(b"E", tail));
+                    let expr = Expression::RequiresWithParams(params, reqs);
+                    return Ok((expr, tail));
+                }
                 b"gs" => {
-                    return can_be_global(true, subs, tail);
+                    // Either `::new`/`::delete`, or a global <unresolved-name>.
+                    match can_be_global(true, subs, tail) {
+                        Ok((expr, tail)) => return Ok((expr, tail)),
+                        Err(error) => {
+                            if let Ok((name, tail)) = UnresolvedName::parse(subs, input) {
+                                let expr = Expression::UnresolvedName(name);
+                                return Ok((expr, tail));
+                            }
+                            return Err(error);
+                        }
+                    }
                 }
                 _ => {}
             }
         }
 
+        if let Ok(tail) = consume(b"u", input) {
+            let (name, tail) = try!(SourceName::parse(subs, tail));
+            let (args, tail) = try!(zero_or_more::<TemplateArg>(subs, tail));
+            let tail = try!(consume(b"E", tail));
+            let expr = Expression::VendorExtension(name, args);
+            return Ok((expr, tail));
+        }
+
         if let Ok((expr, tail)) = can_be_global(false, subs, input) {
             return Ok((expr, tail));
         }
@@ -3417,25 +6187,57 @@ impl Demangle for Expression {
     fn demangle<W>(&self,
                    ctx: &mut DemangleContext<W>,
                    stack: Option<ArgStack>)
-                   -> io::Result<()>
+                   -> DemangleResult<()>
         where W: io::Write
     {
         // TODO: do we need to actually understand operator precedence?
         match *self {
+            Expression::Unary(ref op @ OperatorName::PostInc, ref expr) |
+            Expression::Unary(ref op @ OperatorName::PostDec, ref expr) => {
+                // Without a leading `_`, `pp` and `mm` are postfix. The prefix
+                // forms are parsed as `PrefixInc` and `PrefixDec`.
+                try!(expr.demangle(ctx, stack));
+                op.demangle(ctx, stack)
+            }
+            Expression::Unary(OperatorName::AddressOf, ref expr) => {
+                // Taking the address of a function names the function, not a
+                // call to it: print `&ns::func` without its parameter list.
+                if let Expression::Primary(
+                    ExprPrimary::External(
+                        MangledName::Encoding(
+                            Encoding::Function(ref name, _, _)))) = **expr {
+                    try!(write!(ctx, "&"));
+                    return name.demangle(ctx, stack);
+                }
+                try!(OperatorName::AddressOf.demangle(ctx, stack));
+                expr.demangle(ctx, stack)
+            }
             Expression::Unary(ref op, ref expr) => {
+                // Like libiberty, only space out operators that are words. The
+                // operand of a symbolic operator is parenthesized just like a
+                // binary operator's, so that `ng` applied to `mi` reads
+                // `-((1)-(2))` rather than `-(1)-(2)`.
                 try!(op.demangle(ctx, stack));
-                try!(write!(ctx, " "));
-                expr.demangle(ctx, stack)
+                if op.is_alphabetic() {
+                    try!(write!(ctx, " "));
+                    expr.demangle(ctx, stack)
+                } else {
+                    demangle_operand(ctx, stack, expr)
+                }
+            }
+            Expression::Binary(OperatorName::Comma, ref lhs, ref rhs) => {
+                // Like in an argument list, a comma is only ever followed by a
+                // space, never preceded by one.
+                try!(demangle_operand(ctx, stack, lhs));
+                if ctx.options.spaced_binary_operators {
+                    try!(write!(ctx, ", "));
+                } else {
+                    try!(write!(ctx, ","));
+                }
+                demangle_operand(ctx, stack, rhs)
             }
             Expression::Binary(ref op, ref lhs, ref rhs) => {
-                try!(write!(ctx, "("));
-                try!(lhs.demangle(ctx, stack));
-                try!(write!(ctx, ")"));
-                try!(op.demangle(ctx, stack));
-                try!(write!(ctx, "("));
-                try!(rhs.demangle(ctx, stack));
-                try!(write!(ctx, ")"));
-                Ok(())
+                demangle_binary_expression(ctx, stack, lhs, op, rhs)
             }
             Expression::Ternary(OperatorName::Question,
                                 ref condition,
@@ -3471,9 +6273,20 @@ impl Demangle for Expression {
                 expr.demangle(ctx, stack)
             }
             Expression::Call(ref functor_expr, ref args) => {
-                try!(write!(ctx, "("));
-                try!(functor_expr.demangle(ctx, stack));
-                try!(write!(ctx, ")("));
+                match **functor_expr {
+                    // A member access already reads naturally as a callee, as
+                    // in `obj->method(args)`, so don't parenthesize it.
+                    Expression::Member(..) |
+                    Expression::DerefMember(..) => {
+                        try!(functor_expr.demangle(ctx, stack));
+                        try!(write!(ctx, "("));
+                    }
+                    _ => {
+                        try!(write!(ctx, "("));
+                        try!(functor_expr.demangle(ctx, stack));
+                        try!(write!(ctx, ")("));
+                    }
+                }
                 let mut need_comma = false;
                 for arg in args {
                     if need_comma {
@@ -3526,74 +6339,19 @@ impl Demangle for Expression {
                 try!(write!(ctx, "}}"));
                 Ok(())
             }
-            // TODO: factor out all this duplication in the `new` variants.
             Expression::New(ref exprs, ref ty, ref init) => {
-                try!(write!(ctx, "new ("));
-                let mut need_comma = false;
-                for expr in exprs {
-                    if need_comma {
-                        try!(write!(ctx, ", "));
-                    }
-                    try!(expr.demangle(ctx, stack));
-                    need_comma = true;
-                }
-                try!(write!(ctx, ") "));
-                try!(ty.demangle(ctx, stack));
-                if let Some(ref init) = *init {
-                    try!(init.demangle(ctx, stack));
-                }
-                Ok(())
+                demangle_new(ctx, stack, "new", exprs, ty, init)
             }
             Expression::GlobalNew(ref exprs, ref ty, ref init) => {
-                try!(write!(ctx, "::new ("));
-                let mut need_comma = false;
-                for expr in exprs {
-                    if need_comma {
-                        try!(write!(ctx, ", "));
-                    }
-                    try!(expr.demangle(ctx, stack));
-                    need_comma = true;
-                }
-                try!(write!(ctx, ") "));
-                try!(ty.demangle(ctx, stack));
-                if let Some(ref init) = *init {
-                    try!(init.demangle(ctx, stack));
-                }
-                Ok(())
+                demangle_new(ctx, stack, "::new", exprs, ty, init)
             }
+            // The type being allocated is what makes it an array, so libiberty
+            // spells `new[]` the same as `new`.
             Expression::NewArray(ref exprs, ref ty, ref init) => {
-                try!(write!(ctx, "new[] ("));
-                let mut need_comma = false;
-                for expr in exprs {
-                    if need_comma {
-                        try!(write!(ctx, ", "));
-                    }
-                    try!(expr.demangle(ctx, stack));
-                    need_comma = true;
-                }
-                try!(write!(ctx, ") "));
-                try!(ty.demangle(ctx, stack));
-                if let Some(ref init) = *init {
-                    try!(init.demangle(ctx, stack));
-                }
-                Ok(())
+                demangle_new(ctx, stack, "new", exprs, ty, init)
             }
             Expression::GlobalNewArray(ref exprs, ref ty, ref init) => {
-                try!(write!(ctx, "::new[] ("));
-                let mut need_comma = false;
-                for expr in exprs {
-                    if need_comma {
-                        try!(write!(ctx, ", "));
-                    }
-                    try!(expr.demangle(ctx, stack));
-                    need_comma = true;
-                }
-                try!(write!(ctx, ") "));
-                try!(ty.demangle(ctx, stack));
-                if let Some(ref init) = *init {
-                    try!(init.demangle(ctx, stack));
-                }
-                Ok(())
+                demangle_new(ctx, stack, "::new", exprs, ty, init)
             }
             Expression::Delete(ref expr) => {
                 try!(write!(ctx, "delete "));
@@ -3645,43 +6403,43 @@ impl Demangle for Expression {
                 Ok(())
             }
             Expression::TypeidType(ref ty) => {
-                try!(write!(ctx, "typeid ("));
+                try!(write_keyword_paren(ctx, "typeid"));
                 try!(ty.demangle(ctx, stack));
                 try!(write!(ctx, ")"));
                 Ok(())
             }
             Expression::TypeidExpr(ref expr) => {
-                try!(write!(ctx, "typeid ("));
+                try!(write_keyword_paren(ctx, "typeid"));
                 try!(expr.demangle(ctx, stack));
                 try!(write!(ctx, ")"));
                 Ok(())
             }
             Expression::SizeofType(ref ty) => {
-                try!(write!(ctx, "sizeof ("));
+                try!(write_keyword_paren(ctx, "sizeof"));
                 try!(ty.demangle(ctx, stack));
                 try!(write!(ctx, ")"));
                 Ok(())
             }
             Expression::SizeofExpr(ref expr) => {
-                try!(write!(ctx, "sizeof ("));
+                try!(write_keyword_paren(ctx, "sizeof"));
                 try!(expr.demangle(ctx, stack));
                 try!(write!(ctx, ")"));
                 Ok(())
             }
             Expression::AlignofType(ref ty) => {
-                try!(write!(ctx, "alignof ("));
+                try!(write_keyword_paren(ctx, "alignof"));
                 try!(ty.demangle(ctx, stack));
                 try!(write!(ctx, ")"));
                 Ok(())
             }
             Expression::AlignofExpr(ref expr) => {
-                try!(write!(ctx, "alignof ("));
+                try!(write_keyword_paren(ctx, "alignof"));
                 try!(expr.demangle(ctx, stack));
                 try!(write!(ctx, ")"));
                 Ok(())
             }
             Expression::Noexcept(ref expr) => {
-                try!(write!(ctx, "noexcept ("));
+                try!(write_keyword_paren(ctx, "noexcept"));
                 try!(expr.demangle(ctx, stack));
                 try!(write!(ctx, ")"));
                 Ok(())
@@ -3699,11 +6457,17 @@ impl Demangle for Expression {
                 name.demangle(ctx, stack)
             }
             Expression::PointerToMember(ref e1, ref e2) => {
-                try!(e1.demangle(ctx, stack));
-                try!(write!(ctx, ".*"));
-                e2.demangle(ctx, stack)
+                demangle_binary_expression(ctx, stack, e1, ".*", e2)
             }
             Expression::SizeofTemplatePack(ref param) => {
+                // Like libiberty, print the length of the pack rather than
+                // its elements when we know what it is bound to.
+                if let Some(len) = template_pack_length(param,
+                                                        ctx.template_arg_stack(stack)) {
+                    try!(write!(ctx, "{}", len));
+                    return Ok(());
+                }
+
                 try!(write!(ctx, "sizeof...("));
                 try!(param.demangle(ctx, stack));
                 try!(write!(ctx, ")"));
@@ -3716,6 +6480,12 @@ impl Demangle for Expression {
                 Ok(())
             }
             Expression::SizeofCapturedTemplatePack(ref args) => {
+                let arg_stack = ctx.template_arg_stack(stack);
+                if let Some(len) = captured_pack_length(ctx.subs, args, arg_stack) {
+                    try!(write!(ctx, "{}", len));
+                    return Ok(());
+                }
+
                 try!(write!(ctx, "sizeof...("));
                 let mut need_comma = false;
                 for arg in args {
@@ -3741,44 +6511,565 @@ impl Demangle for Expression {
                 try!(write!(ctx, "throw"));
                 Ok(())
             }
+            Expression::Requires(ref reqs) => {
+                try!(write!(ctx, "requires "));
+                demangle_requirements(ctx, stack, reqs)
+            }
+            Expression::RequiresWithParams(ref params, ref reqs) => {
+                try!(write!(ctx, "requires ("));
+                let mut need_comma = false;
+                for param in params {
+                    if need_comma {
+                        try!(ctx.write_arg_separator());
+                    }
+                    try!(param.demangle(ctx, stack));
+                    need_comma = true;
+                }
+                try!(write!(ctx, ") "));
+                demangle_requirements(ctx, stack, reqs)
+            }
+            Expression::VendorExtension(ref name, ref args) => {
+                try!(name.demangle(ctx, stack));
+                try!(write!(ctx, "("));
+                let mut need_comma = false;
+                for arg in args.iter().filter(|arg| !arg.is_empty_pack()) {
+                    if need_comma {
+                        try!(ctx.write_arg_separator());
+                    }
+                    try!(arg.demangle(ctx, stack));
+                    need_comma = true;
+                }
+                try!(write!(ctx, ")"));
+                Ok(())
+            }
             Expression::UnresolvedName(ref name) => name.demangle(ctx, stack),
             Expression::Primary(ref expr) => expr.demangle(ctx, stack),
         }
     }
 }
 
-/// The `<unresolved-name>` production.
-///
-/// ```text
-/// <unresolved-name> ::= [gs] <base-unresolved-name>
-///                          #
-///                   ::= sr <unresolved-type> <base-unresolved-name>
-///                          #
-///                   ::= srN <unresolved-type> <unresolved-qualifier-level>+ E <base-unresolved-name>
-///                          #
-///                   ::= [gs] sr <unresolved-qualifier-level>+ E <base-unresolved-name>
-///                          # A::x, N::y, A<T>::z; "gs" means leading "::"
-/// ```
-#[derive(Clone, Debug, Hash, PartialEq, Eq)]
-pub enum UnresolvedName {
-    /// `x`
-    Name(BaseUnresolvedName),
-
-    /// `::x`
-    Global(BaseUnresolvedName),
-
-    /// `T::x`  or `decltype(p)::x` or `T::N::x` or `decltype(p)::N::x`
-    Nested1(UnresolvedTypeHandle, Vec<UnresolvedQualifierLevel>, BaseUnresolvedName),
-
-    /// `A::x` or `N::y` or `A<T>::z`
-    Nested2(Vec<UnresolvedQualifierLevel>, BaseUnresolvedName),
-
-    /// `::A::x` or `::N::y` or `::A<T>::z`
-    GlobalNested2(Vec<UnresolvedQualifierLevel>, BaseUnresolvedName),
-}
-
-impl Parse for UnresolvedName {
-    fn parse<'a, 'b>(subs: &'a mut SubstitutionTable,
+impl SemanticEq for Expression {
+    fn semantic_eq(&self, ctx: &SemanticEqContext, other: &Expression) -> bool {
+        match (self, other) {
+            (&Expression::Unary(ref lhs_op, ref lhs_expr),
+             &Expression::Unary(ref rhs_op, ref rhs_expr)) => {
+                lhs_op.semantic_eq(ctx, rhs_op) &&
+                lhs_expr.semantic_eq(ctx, rhs_expr)
+            }
+            (&Expression::Binary(ref lhs_op, ref lhs_lhs, ref lhs_rhs),
+             &Expression::Binary(ref rhs_op, ref rhs_lhs, ref rhs_rhs)) => {
+                lhs_op.semantic_eq(ctx, rhs_op) &&
+                lhs_lhs.semantic_eq(ctx, rhs_lhs) &&
+                lhs_rhs.semantic_eq(ctx, rhs_rhs)
+            }
+            (&Expression::Ternary(ref lhs_op, ref lhs_e1, ref lhs_e2, ref lhs_e3),
+             &Expression::Ternary(ref rhs_op, ref rhs_e1, ref rhs_e2, ref rhs_e3)) => {
+                lhs_op.semantic_eq(ctx, rhs_op) &&
+                lhs_e1.semantic_eq(ctx, rhs_e1) &&
+                lhs_e2.semantic_eq(ctx, rhs_e2) &&
+                lhs_e3.semantic_eq(ctx, rhs_e3)
+            }
+            (&Expression::PrefixInc(ref lhs), &Expression::PrefixInc(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&Expression::PrefixDec(ref lhs), &Expression::PrefixDec(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&Expression::Call(ref lhs_callee, ref lhs_args),
+             &Expression::Call(ref rhs_callee, ref rhs_args)) => {
+                lhs_callee.semantic_eq(ctx, rhs_callee) &&
+                lhs_args.semantic_eq(ctx, rhs_args)
+            }
+            (&Expression::ConversionOne(ref lhs_ty, ref lhs_expr),
+             &Expression::ConversionOne(ref rhs_ty, ref rhs_expr)) => {
+                lhs_ty.semantic_eq(ctx, rhs_ty) &&
+                lhs_expr.semantic_eq(ctx, rhs_expr)
+            }
+            (&Expression::ConversionMany(ref lhs_ty, ref lhs_exprs),
+             &Expression::ConversionMany(ref rhs_ty, ref rhs_exprs)) => {
+                lhs_ty.semantic_eq(ctx, rhs_ty) &&
+                lhs_exprs.semantic_eq(ctx, rhs_exprs)
+            }
+            (&Expression::ConversionBraced(ref lhs_ty, ref lhs_exprs),
+             &Expression::ConversionBraced(ref rhs_ty, ref rhs_exprs)) => {
+                lhs_ty.semantic_eq(ctx, rhs_ty) &&
+                lhs_exprs.semantic_eq(ctx, rhs_exprs)
+            }
+            (&Expression::BracedInitList(ref lhs), &Expression::BracedInitList(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&Expression::New(ref lhs_exprs, ref lhs_ty, ref lhs_init),
+             &Expression::New(ref rhs_exprs, ref rhs_ty, ref rhs_init)) => {
+                lhs_exprs.semantic_eq(ctx, rhs_exprs) &&
+                lhs_ty.semantic_eq(ctx, rhs_ty) &&
+                lhs_init.semantic_eq(ctx, rhs_init)
+            }
+            (&Expression::GlobalNew(ref lhs_exprs, ref lhs_ty, ref lhs_init),
+             &Expression::GlobalNew(ref rhs_exprs, ref rhs_ty, ref rhs_init)) => {
+                lhs_exprs.semantic_eq(ctx, rhs_exprs) &&
+                lhs_ty.semantic_eq(ctx, rhs_ty) &&
+                lhs_init.semantic_eq(ctx, rhs_init)
+            }
+            (&Expression::NewArray(ref lhs_exprs, ref lhs_ty, ref lhs_init),
+             &Expression::NewArray(ref rhs_exprs, ref rhs_ty, ref rhs_init)) => {
+                lhs_exprs.semantic_eq(ctx, rhs_exprs) &&
+                lhs_ty.semantic_eq(ctx, rhs_ty) &&
+                lhs_init.semantic_eq(ctx, rhs_init)
+            }
+            (&Expression::GlobalNewArray(ref lhs_exprs, ref lhs_ty, ref lhs_init),
+             &Expression::GlobalNewArray(ref rhs_exprs, ref rhs_ty, ref rhs_init)) => {
+                lhs_exprs.semantic_eq(ctx, rhs_exprs) &&
+                lhs_ty.semantic_eq(ctx, rhs_ty) &&
+                lhs_init.semantic_eq(ctx, rhs_init)
+            }
+            (&Expression::Delete(ref lhs), &Expression::Delete(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&Expression::GlobalDelete(ref lhs), &Expression::GlobalDelete(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&Expression::DeleteArray(ref lhs), &Expression::DeleteArray(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&Expression::GlobalDeleteArray(ref lhs), &Expression::GlobalDeleteArray(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&Expression::DynamicCast(ref lhs_ty, ref lhs_expr),
+             &Expression::DynamicCast(ref rhs_ty, ref rhs_expr)) => {
+                lhs_ty.semantic_eq(ctx, rhs_ty) &&
+                lhs_expr.semantic_eq(ctx, rhs_expr)
+            }
+            (&Expression::StaticCast(ref lhs_ty, ref lhs_expr),
+             &Expression::StaticCast(ref rhs_ty, ref rhs_expr)) => {
+                lhs_ty.semantic_eq(ctx, rhs_ty) &&
+                lhs_expr.semantic_eq(ctx, rhs_expr)
+            }
+            (&Expression::ConstCast(ref lhs_ty, ref lhs_expr),
+             &Expression::ConstCast(ref rhs_ty, ref rhs_expr)) => {
+                lhs_ty.semantic_eq(ctx, rhs_ty) &&
+                lhs_expr.semantic_eq(ctx, rhs_expr)
+            }
+            (&Expression::ReinterpretCast(ref lhs_ty, ref lhs_expr),
+             &Expression::ReinterpretCast(ref rhs_ty, ref rhs_expr)) => {
+                lhs_ty.semantic_eq(ctx, rhs_ty) &&
+                lhs_expr.semantic_eq(ctx, rhs_expr)
+            }
+            (&Expression::TypeidType(ref lhs), &Expression::TypeidType(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&Expression::TypeidExpr(ref lhs), &Expression::TypeidExpr(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&Expression::SizeofType(ref lhs), &Expression::SizeofType(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&Expression::SizeofExpr(ref lhs), &Expression::SizeofExpr(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&Expression::AlignofType(ref lhs), &Expression::AlignofType(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&Expression::AlignofExpr(ref lhs), &Expression::AlignofExpr(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&Expression::Noexcept(ref lhs), &Expression::Noexcept(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&Expression::TemplateParam(ref lhs), &Expression::TemplateParam(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&Expression::FunctionParam(ref lhs), &Expression::FunctionParam(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&Expression::Member(ref lhs_expr, ref lhs_name),
+             &Expression::Member(ref rhs_expr, ref rhs_name)) => {
+                lhs_expr.semantic_eq(ctx, rhs_expr) &&
+                lhs_name.semantic_eq(ctx, rhs_name)
+            }
+            (&Expression::DerefMember(ref lhs_expr, ref lhs_name),
+             &Expression::DerefMember(ref rhs_expr, ref rhs_name)) => {
+                lhs_expr.semantic_eq(ctx, rhs_expr) &&
+                lhs_name.semantic_eq(ctx, rhs_name)
+            }
+            (&Expression::PointerToMember(ref lhs_e1, ref lhs_e2),
+             &Expression::PointerToMember(ref rhs_e1, ref rhs_e2)) => {
+                lhs_e1.semantic_eq(ctx, rhs_e1) &&
+                lhs_e2.semantic_eq(ctx, rhs_e2)
+            }
+            (&Expression::SizeofTemplatePack(ref lhs),
+             &Expression::SizeofTemplatePack(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&Expression::SizeofFunctionPack(ref lhs),
+             &Expression::SizeofFunctionPack(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&Expression::SizeofCapturedTemplatePack(ref lhs),
+             &Expression::SizeofCapturedTemplatePack(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&Expression::PackExpansion(ref lhs), &Expression::PackExpansion(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&Expression::Throw(ref lhs), &Expression::Throw(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&Expression::Rethrow, &Expression::Rethrow) => true,
+            (&Expression::Requires(ref lhs), &Expression::Requires(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&Expression::RequiresWithParams(ref lhs_params, ref lhs_reqs),
+             &Expression::RequiresWithParams(ref rhs_params, ref rhs_reqs)) => {
+                lhs_params.semantic_eq(ctx, rhs_params) && lhs_reqs.semantic_eq(ctx, rhs_reqs)
+            }
+            (&Expression::VendorExtension(ref lhs_name, ref lhs_args),
+             &Expression::VendorExtension(ref rhs_name, ref rhs_args)) => {
+                lhs_name.semantic_eq(ctx, rhs_name) && lhs_args.semantic_eq(ctx, rhs_args)
+            }
+            (&Expression::UnresolvedName(ref lhs), &Expression::UnresolvedName(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&Expression::Primary(ref lhs), &Expression::Primary(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// A single requirement in the body of a requires-expression.
+///
+/// ```text
+/// <requirement> ::= X <expression> [N] [R <type-constraint>]
+///               ::= T <type>
+///               ::= Q <constraint-expression>
+/// ```
+///
+/// A simple requirement is the `X` form with neither the `N` (noexcept) flag
+/// nor a return type constraint; otherwise it is a compound requirement.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub enum Requirement {
+    /// `expr;` or `{ expr } noexcept -> constraint;`
+    Expression(Expression, bool, Option<Name>),
+
+    /// `typename type;`
+    Type(TypeHandle),
+
+    /// `requires expr;`
+    Nested(Expression),
+}
+
+impl Parse for Requirement {
+    fn parse<'a, 'b>(subs: &'a mut SubstitutionTable,
+                     input: IndexStr<'b>)
+                     -> Result<(Requirement, IndexStr<'b>)> {
+        log_parse!("Requirement", input);
+
+        if let Ok(tail) = consume(b"X", input) {
+            let (expr, tail) = try!(Expression::parse(subs, tail));
+            let (noexcept, tail) = match consume(b"N", tail) {
+                Ok(tail) => (true, tail),
+                Err(_) => (false, tail),
+            };
+            let (constraint, tail) = match consume(b"R", tail) {
+                Ok(tail) => {
+                    let (name, tail) = try!(Name::parse(subs, tail));
+                    (Some(name), tail)
+                }
+                Err(_) => (None, tail),
+            };
+            return Ok((Requirement::Expression(expr, noexcept, constraint), tail));
+        }
+
+        if let Ok(tail) = consume(b"T", input) {
+            let (ty, tail) = try!(TypeHandle::parse(subs, tail));
+            return Ok((Requirement::Type(ty), tail));
+        }
+
+        let tail = try!(consume(b"Q", input));
+        let (expr, tail) = try!(Expression::parse(subs, tail));
+        Ok((Requirement::Nested(expr), tail))
+    }
+}
+
+impl Demangle for Requirement {
+    fn demangle<W>(&self,
+                   ctx: &mut DemangleContext<W>,
+                   stack: Option<ArgStack>)
+                   -> DemangleResult<()>
+        where W: io::Write
+    {
+        match *self {
+            Requirement::Expression(ref expr, false, None) => {
+                try!(expr.demangle(ctx, stack));
+            }
+            Requirement::Expression(ref expr, noexcept, ref constraint) => {
+                try!(write!(ctx, "{{ "));
+                try!(expr.demangle(ctx, stack));
+                try!(write!(ctx, " }}"));
+                if noexcept {
+                    try!(write!(ctx, " noexcept"));
+                }
+                if let Some(ref constraint) = *constraint {
+                    try!(write!(ctx, " -> "));
+                    try!(constraint.demangle(ctx, stack));
+                }
+            }
+            Requirement::Type(ref ty) => {
+                try!(write!(ctx, "typename "));
+                try!(ty.demangle(ctx, stack));
+            }
+            Requirement::Nested(ref expr) => {
+                try!(write!(ctx, "requires "));
+                try!(expr.demangle(ctx, stack));
+            }
+        }
+        try!(write!(ctx, ";"));
+        Ok(())
+    }
+}
+
+impl SemanticEq for Requirement {
+    fn semantic_eq(&self, ctx: &SemanticEqContext, other: &Requirement) -> bool {
+        match (self, other) {
+            (&Requirement::Expression(ref lhs_expr, lhs_noexcept, ref lhs_constraint),
+             &Requirement::Expression(ref rhs_expr, rhs_noexcept, ref rhs_constraint)) => {
+                lhs_noexcept == rhs_noexcept && lhs_expr.semantic_eq(ctx, rhs_expr) &&
+                lhs_constraint.semantic_eq(ctx, rhs_constraint)
+            }
+            (&Requirement::Type(ref lhs), &Requirement::Type(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&Requirement::Nested(ref lhs), &Requirement::Nested(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            _ => false,
+        }
+    }
+}
+
+// Write the braced body of a requires-expression, `{ req; req; }`.
+fn demangle_requirements<W>(ctx: &mut DemangleContext<W>,
+                            stack: Option<ArgStack>,
+                            reqs: &[Requirement])
+                            -> DemangleResult<()>
+    where W: io::Write
+{
+    try!(write!(ctx, "{{"));
+    for req in reqs {
+        try!(write!(ctx, " "));
+        try!(req.demangle(ctx, stack));
+    }
+    try!(write!(ctx, " }}"));
+    Ok(())
+}
+
+// Write a keyword operator such as `sizeof` and the opening parenthesis of
+// its operand.
+fn write_keyword_paren<W>(ctx: &mut DemangleContext<W>, keyword: &str) -> DemangleResult<()>
+    where W: io::Write
+{
+    if ctx.options.compact_keyword_operators {
+        try!(write!(ctx, "{}(", keyword));
+    } else {
+        try!(write!(ctx, "{} (", keyword));
+    }
+    Ok(())
+}
+
+// Demangle a binary operator expression, including the pointer-to-member
+// operators `.*` and `->*`. Like libiberty, operands that are names or
+// function parameters are printed bare, and every other operand is
+// parenthesized so that the operators' relative precedence never matters.
+fn demangle_binary_expression<W, D>(ctx: &mut DemangleContext<W>,
+                                    stack: Option<ArgStack>,
+                                    lhs: &Expression,
+                                    op: &D,
+                                    rhs: &Expression)
+                                    -> DemangleResult<()>
+    where W: io::Write,
+          D: ?Sized + Demangle
+{
+    try!(demangle_operand(ctx, stack, lhs));
+    if ctx.options.spaced_binary_operators {
+        try!(write!(ctx, " "));
+        try!(op.demangle(ctx, stack));
+        try!(write!(ctx, " "));
+    } else {
+        try!(op.demangle(ctx, stack));
+    }
+    demangle_operand(ctx, stack, rhs)
+}
+
+fn demangle_operand<W>(ctx: &mut DemangleContext<W>,
+                       stack: Option<ArgStack>,
+                       operand: &Expression)
+                       -> DemangleResult<()>
+    where W: io::Write
+{
+    match *operand {
+        Expression::UnresolvedName(_) |
+        Expression::FunctionParam(_) => operand.demangle(ctx, stack),
+        _ => {
+            try!(write!(ctx, "("));
+            try!(operand.demangle(ctx, stack));
+            try!(write!(ctx, ")"));
+            Ok(())
+        }
+    }
+}
+
+// Demangle the various `new` expressions. The placement arguments are only
+// printed when there are any, so that `nw_T_E` is `new int` and not `new ()
+// int`.
+fn demangle_new<W>(ctx: &mut DemangleContext<W>,
+                   stack: Option<ArgStack>,
+                   keyword: &str,
+                   exprs: &[Expression],
+                   ty: &TypeHandle,
+                   init: &Option<Initializer>)
+                   -> DemangleResult<()>
+    where W: io::Write
+{
+    try!(write!(ctx, "{} ", keyword));
+    if !exprs.is_empty() {
+        try!(write!(ctx, "("));
+        let mut need_comma = false;
+        for expr in exprs {
+            if need_comma {
+                try!(write!(ctx, ", "));
+            }
+            try!(expr.demangle(ctx, stack));
+            need_comma = true;
+        }
+        try!(write!(ctx, ") "));
+    }
+    try!(ty.demangle(ctx, stack));
+    if let Some(ref init) = *init {
+        try!(init.demangle(ctx, stack));
+    }
+    Ok(())
+}
+
+/// The number of arguments in the pack that `param` refers to, or `None` if it
+/// refers to anything else, or can't be resolved.
+fn template_pack_length(param: &TemplateParam, stack: Option<ArgStack>) -> Option<usize> {
+    match stack.get_template_arg(param.0) {
+        Ok(&TemplateArg::ArgPack(ref args)) => Some(args.len()),
+        _ => None,
+    }
+}
+
+/// The number of elements that a `Dp` pack expansion of `pattern` expands
+/// to: the length of the first argument pack referred to by a template
+/// parameter within it. `None` if there is no such parameter, or it can't be
+/// resolved.
+fn pack_expansion_length(subs: &SubstitutionTable,
+                         pattern: &TypeHandle,
+                         stack: Option<ArgStack>)
+                         -> Option<usize> {
+    let args_length = |args: &TemplateArgs| {
+        args.0
+            .iter()
+            .filter_map(|arg| match *arg {
+                TemplateArg::Type(ref ty) => pack_expansion_length(subs, ty, stack),
+                _ => None,
+            })
+            .next()
+    };
+
+    match subs.get_type(pattern) {
+        Some(&Type::TemplateParam(ref param)) => template_pack_length(param, stack),
+        Some(&Type::Qualified(_, ref ty)) |
+        Some(&Type::PointerTo(ref ty)) |
+        Some(&Type::LvalueRef(ref ty)) |
+        Some(&Type::RvalueRef(ref ty)) |
+        Some(&Type::Complex(ref ty)) |
+        Some(&Type::Imaginary(ref ty)) => pack_expansion_length(subs, ty, stack),
+        Some(&Type::Function(ref func)) => {
+            func.bare
+                .0
+                .iter()
+                .filter_map(|ty| pack_expansion_length(subs, ty, stack))
+                .next()
+        }
+        Some(&Type::ClassEnum(ClassEnumType::Named(ref name))) => {
+            name.get_template_args(subs).and_then(args_length)
+        }
+        Some(&Type::TemplateTemplate(_, ref args)) => args_length(args),
+        _ => None,
+    }
+}
+
+/// The number of arguments in the `sP` captured pack `args`, once any pack
+/// expansions within it are expanded, or `None` if some pack expansion can't
+/// be resolved.
+fn captured_pack_length(subs: &SubstitutionTable,
+                        args: &[TemplateArg],
+                        stack: Option<ArgStack>)
+                        -> Option<usize> {
+    let mut len = 0;
+    for arg in args {
+        len += match *arg {
+            TemplateArg::ArgPack(ref args) => args.len(),
+            TemplateArg::Type(ref ty) => {
+                match subs.get_type(ty) {
+                    Some(&Type::PackExpansion(ref pack)) => {
+                        match subs.get_type(pack) {
+                            Some(&Type::TemplateParam(ref param)) => {
+                                match template_pack_length(param, stack) {
+                                    Some(n) => n,
+                                    None => return None,
+                                }
+                            }
+                            _ => return None,
+                        }
+                    }
+                    _ => 1,
+                }
+            }
+            TemplateArg::Expression(_) |
+            TemplateArg::SimpleExpression(_) => 1,
+        };
+    }
+    Some(len)
+}
+
+/// The `<unresolved-name>` production.
+///
+/// ```text
+/// <unresolved-name> ::= [gs] <base-unresolved-name>
+///                          #
+///                   ::= sr <unresolved-type> <base-unresolved-name>
+///                          #
+///                   ::= srN <unresolved-type> <unresolved-qualifier-level>+ E <base-unresolved-name>
+///                          #
+///                   ::= [gs] sr <unresolved-qualifier-level>+ E <base-unresolved-name>
+///                          # A::x, N::y, A<T>::z; "gs" means leading "::"
+/// ```
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub enum UnresolvedName {
+    /// `x`
+    Name(BaseUnresolvedName),
+
+    /// `::x`
+    Global(BaseUnresolvedName),
+
+    /// `T::x`  or `decltype(p)::x` or `T::N::x` or `decltype(p)::N::x`
+    ///
+    /// When parsed from `srN`, there is always at least one qualifier level;
+    /// only the plain `sr` form has none.
+    Nested1(UnresolvedTypeHandle, Vec<UnresolvedQualifierLevel>, BaseUnresolvedName),
+
+    /// `A::x` or `N::y` or `A<T>::z`
+    Nested2(Vec<UnresolvedQualifierLevel>, BaseUnresolvedName),
+
+    /// `::A::x` or `::N::y` or `::A<T>::z`
+    GlobalNested2(Vec<UnresolvedQualifierLevel>, BaseUnresolvedName),
+}
+
+impl Parse for UnresolvedName {
+    fn parse<'a, 'b>(subs: &'a mut SubstitutionTable,
                      input: IndexStr<'b>)
                      -> Result<(UnresolvedName, IndexStr<'b>)> {
         log_parse!("UnresolvedName", input);
@@ -3805,6 +7096,11 @@ impl Parse for UnresolvedName {
         if tail.peek() == Some(b'N') {
             let tail = consume(b"N", tail).unwrap();
             let (ty, tail) = try!(UnresolvedTypeHandle::parse(subs, tail));
+            // The grammar requires at least one <unresolved-qualifier-level>
+            // after `srN <unresolved-type>`, so `srN T_ E` is malformed.
+            if tail.peek() == Some(b'E') {
+                return Err(error::Error::UnexpectedText);
+            }
             let (levels, tail) = try!(one_or_more::<UnresolvedQualifierLevel>(subs,
                                                                               tail));
             let tail = try!(consume(b"E", tail));
@@ -3828,7 +7124,7 @@ impl Demangle for UnresolvedName {
     fn demangle<W>(&self,
                    ctx: &mut DemangleContext<W>,
                    stack: Option<ArgStack>)
-                   -> io::Result<()>
+                   -> DemangleResult<()>
         where W: io::Write
     {
         match *self {
@@ -3843,12 +7139,13 @@ impl Demangle for UnresolvedName {
                     try!(write!(ctx, "::"));
                     try!(lvl.demangle(ctx, stack));
                 }
+                try!(write!(ctx, "::"));
                 name.demangle(ctx, stack)
             }
             UnresolvedName::Nested2(ref levels, ref name) => {
                 for lvl in &levels[..] {
-                    try!(write!(ctx, "::"));
                     try!(lvl.demangle(ctx, stack));
+                    try!(write!(ctx, "::"));
                 }
                 name.demangle(ctx, stack)
             }
@@ -3856,8 +7153,8 @@ impl Demangle for UnresolvedName {
             UnresolvedName::GlobalNested2(ref levels, ref name) => {
                 try!(write!(ctx, "::"));
                 for lvl in &levels[..] {
-                    try!(write!(ctx, "::"));
                     try!(lvl.demangle(ctx, stack));
+                    try!(write!(ctx, "::"));
                 }
                 name.demangle(ctx, stack)
             }
@@ -3865,6 +7162,36 @@ impl Demangle for UnresolvedName {
     }
 }
 
+impl SemanticEq for UnresolvedName {
+    fn semantic_eq(&self, ctx: &SemanticEqContext, other: &UnresolvedName) -> bool {
+        match (self, other) {
+            (&UnresolvedName::Name(ref lhs), &UnresolvedName::Name(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&UnresolvedName::Global(ref lhs), &UnresolvedName::Global(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&UnresolvedName::Nested1(ref lhs_ty, ref lhs_levels, ref lhs_name),
+             &UnresolvedName::Nested1(ref rhs_ty, ref rhs_levels, ref rhs_name)) => {
+                lhs_ty.semantic_eq(ctx, rhs_ty) &&
+                lhs_levels.semantic_eq(ctx, rhs_levels) &&
+                lhs_name.semantic_eq(ctx, rhs_name)
+            }
+            (&UnresolvedName::Nested2(ref lhs_levels, ref lhs_name),
+             &UnresolvedName::Nested2(ref rhs_levels, ref rhs_name)) => {
+                lhs_levels.semantic_eq(ctx, rhs_levels) &&
+                lhs_name.semantic_eq(ctx, rhs_name)
+            }
+            (&UnresolvedName::GlobalNested2(ref lhs_levels, ref lhs_name),
+             &UnresolvedName::GlobalNested2(ref rhs_levels, ref rhs_name)) => {
+                lhs_levels.semantic_eq(ctx, rhs_levels) &&
+                lhs_name.semantic_eq(ctx, rhs_name)
+            }
+            _ => false,
+        }
+    }
+}
+
 /// The `<unresolved-type>` production.
 ///
 /// ```text
@@ -3901,16 +7228,16 @@ impl Parse for UnresolvedTypeHandle {
             };
             let ty = UnresolvedType::Template(param, args);
             let ty = Substitutable::UnresolvedType(ty);
-            let idx = subs.insert(ty);
-            let handle = UnresolvedTypeHandle::BackReference(idx);
+            let idx = try!(subs.insert(ty));
+            let handle = UnresolvedTypeHandle::new_back_reference(subs, idx);
             return Ok((handle, tail));
         }
 
         if let Ok((decltype, tail)) = Decltype::parse(subs, input) {
             let ty = UnresolvedType::Decltype(decltype);
             let ty = Substitutable::UnresolvedType(ty);
-            let idx = subs.insert(ty);
-            let handle = UnresolvedTypeHandle::BackReference(idx);
+            let idx = try!(subs.insert(ty));
+            let handle = UnresolvedTypeHandle::new_back_reference(subs, idx);
             return Ok((handle, tail));
         }
 
@@ -3922,7 +7249,7 @@ impl Parse for UnresolvedTypeHandle {
             Substitution::BackReference(idx) => {
                 // TODO: should this check that the back reference actually
                 // points to an `<unresolved-type>`?
-                Ok((UnresolvedTypeHandle::BackReference(idx), tail))
+                Ok((UnresolvedTypeHandle::new_back_reference(subs, idx), tail))
             }
         }
     }
@@ -3932,18 +7259,18 @@ impl Demangle for UnresolvedType {
     fn demangle<W>(&self,
                    ctx: &mut DemangleContext<W>,
                    stack: Option<ArgStack>)
-                   -> io::Result<()>
+                   -> DemangleResult<()>
         where W: io::Write
     {
         match *self {
             UnresolvedType::Decltype(ref dt) => dt.demangle(ctx, stack),
             UnresolvedType::Template(ref param, ref args) => {
+                // Both the template template param and its args refer to the
+                // enclosing template's args, not to these args, so don't push
+                // them onto the stack.
+                try!(param.demangle(ctx, stack));
                 if let Some(ref args) = *args {
-                    let stack = stack.push(args);
-                    try!(param.demangle(ctx, stack));
                     try!(args.demangle(ctx, stack));
-                } else {
-                    try!(param.demangle(ctx, stack));
                 }
                 Ok(())
             }
@@ -3951,6 +7278,22 @@ impl Demangle for UnresolvedType {
     }
 }
 
+impl SemanticEq for UnresolvedType {
+    fn semantic_eq(&self, ctx: &SemanticEqContext, other: &UnresolvedType) -> bool {
+        match (self, other) {
+            (&UnresolvedType::Template(ref lhs_param, ref lhs_args),
+             &UnresolvedType::Template(ref rhs_param, ref rhs_args)) => {
+                lhs_param.semantic_eq(ctx, rhs_param) &&
+                lhs_args.semantic_eq(ctx, rhs_args)
+            }
+            (&UnresolvedType::Decltype(ref lhs), &UnresolvedType::Decltype(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            _ => false,
+        }
+    }
+}
+
 /// The `<unresolved-qualifier-level>` production.
 ///
 /// ```text
@@ -3975,13 +7318,21 @@ impl Demangle for UnresolvedQualifierLevel {
     fn demangle<W>(&self,
                    ctx: &mut DemangleContext<W>,
                    stack: Option<ArgStack>)
-                   -> io::Result<()>
+                   -> DemangleResult<()>
         where W: io::Write
     {
         self.0.demangle(ctx, stack)
     }
 }
 
+impl SemanticEq for UnresolvedQualifierLevel {
+    fn semantic_eq(&self, ctx: &SemanticEqContext, other: &UnresolvedQualifierLevel) -> bool {
+        let UnresolvedQualifierLevel(ref lhs) = *self;
+        let UnresolvedQualifierLevel(ref rhs) = *other;
+        lhs.semantic_eq(ctx, rhs)
+    }
+}
+
 /// The `<simple-id>` production.
 ///
 /// ```text
@@ -4010,7 +7361,7 @@ impl Demangle for SimpleId {
     fn demangle<W>(&self,
                    ctx: &mut DemangleContext<W>,
                    stack: Option<ArgStack>)
-                   -> io::Result<()>
+                   -> DemangleResult<()>
         where W: io::Write
     {
         try!(self.0.demangle(ctx, stack));
@@ -4021,6 +7372,15 @@ impl Demangle for SimpleId {
     }
 }
 
+impl SemanticEq for SimpleId {
+    fn semantic_eq(&self, ctx: &SemanticEqContext, other: &SimpleId) -> bool {
+        let SimpleId(ref lhs_name, ref lhs_args) = *self;
+        let SimpleId(ref rhs_name, ref rhs_args) = *other;
+        lhs_name.semantic_eq(ctx, rhs_name) &&
+        lhs_args.semantic_eq(ctx, rhs_args)
+    }
+}
+
 /// The `<base-unresolved-name>` production.
 ///
 /// ```text
@@ -4073,13 +7433,18 @@ impl Demangle for BaseUnresolvedName {
     fn demangle<W>(&self,
                    ctx: &mut DemangleContext<W>,
                    stack: Option<ArgStack>)
-                   -> io::Result<()>
+                   -> DemangleResult<()>
         where W: io::Write
     {
         match *self {
             BaseUnresolvedName::Name(ref name) => name.demangle(ctx, stack),
             BaseUnresolvedName::Destructor(ref dtor) => dtor.demangle(ctx, stack),
             BaseUnresolvedName::Operator(ref op, ref args) => {
+                if op.is_alphabetic() {
+                    try!(write!(ctx, "operator "));
+                } else {
+                    try!(write!(ctx, "operator"));
+                }
                 try!(op.demangle(ctx, stack));
                 if let Some(ref args) = *args {
                     try!(args.demangle(ctx, stack));
@@ -4090,6 +7455,26 @@ impl Demangle for BaseUnresolvedName {
     }
 }
 
+impl SemanticEq for BaseUnresolvedName {
+    fn semantic_eq(&self, ctx: &SemanticEqContext, other: &BaseUnresolvedName) -> bool {
+        match (self, other) {
+            (&BaseUnresolvedName::Name(ref lhs), &BaseUnresolvedName::Name(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&BaseUnresolvedName::Operator(ref lhs_op, ref lhs_args),
+             &BaseUnresolvedName::Operator(ref rhs_op, ref rhs_args)) => {
+                lhs_op.semantic_eq(ctx, rhs_op) &&
+                lhs_args.semantic_eq(ctx, rhs_args)
+            }
+            (&BaseUnresolvedName::Destructor(ref lhs),
+             &BaseUnresolvedName::Destructor(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            _ => false,
+        }
+    }
+}
+
 /// The `<destructor-name>` production.
 ///
 /// ```text
@@ -4124,7 +7509,7 @@ impl Demangle for DestructorName {
     fn demangle<W>(&self,
                    ctx: &mut DemangleContext<W>,
                    stack: Option<ArgStack>)
-                   -> io::Result<()>
+                   -> DemangleResult<()>
         where W: io::Write
     {
         try!(write!(ctx, "~"));
@@ -4135,6 +7520,20 @@ impl Demangle for DestructorName {
     }
 }
 
+impl SemanticEq for DestructorName {
+    fn semantic_eq(&self, ctx: &SemanticEqContext, other: &DestructorName) -> bool {
+        match (self, other) {
+            (&DestructorName::Unresolved(ref lhs), &DestructorName::Unresolved(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&DestructorName::Name(ref lhs), &DestructorName::Name(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            _ => false,
+        }
+    }
+}
+
 /// The `<expr-primary>` production.
 ///
 /// ```text
@@ -4163,22 +7562,26 @@ impl Parse for ExprPrimary {
 
         let tail = try!(consume(b"L", input));
 
-        if let Ok((ty, tail)) = TypeHandle::parse(subs, tail) {
-            let start = tail.index();
-            let num_bytes_in_literal = tail.as_ref()
-                .iter()
-                .take_while(|&&c| c != b'E')
-                .count();
-            let tail = tail.range_from(num_bytes_in_literal..);
-            let end = tail.index();
+        // No type can begin with `_`, so dispatch on the mangled name's prefix
+        // up front. Otherwise, a failed attempt at parsing a type could leave
+        // stray entries in the substitutions table.
+        if tail.as_ref().starts_with(b"_Z") {
+            let (name, tail) = try!(MangledName::parse(subs, tail));
             let tail = try!(consume(b"E", tail));
-            let expr = ExprPrimary::Literal(ty, start, end);
+            let expr = ExprPrimary::External(name);
             return Ok((expr, tail));
         }
 
-        let (name, tail) = try!(MangledName::parse(subs, tail));
+        let (ty, tail) = try!(TypeHandle::parse(subs, tail));
+        let start = tail.index();
+        let num_bytes_in_literal = tail.as_ref()
+            .iter()
+            .take_while(|&&c| c != b'E')
+            .count();
+        let tail = tail.range_from(num_bytes_in_literal..);
+        let end = tail.index();
         let tail = try!(consume(b"E", tail));
-        let expr = ExprPrimary::External(name);
+        let expr = ExprPrimary::Literal(ty, start, end);
         Ok((expr, tail))
     }
 }
@@ -4187,7 +7590,7 @@ impl Demangle for ExprPrimary {
     fn demangle<W>(&self,
                    ctx: &mut DemangleContext<W>,
                    stack: Option<ArgStack>)
-                   -> io::Result<()>
+                   -> DemangleResult<()>
         where W: io::Write
     {
         match *self {
@@ -4204,6 +7607,14 @@ impl Demangle for ExprPrimary {
                 if start == end {
                     type_handle.demangle(ctx, stack)
                 } else {
+                    // Negative numbers are mangled with a leading `n` rather
+                    // than `-`.
+                    let start = if ctx.input[start] == b'n' {
+                        try!(write!(ctx, "-"));
+                        start + 1
+                    } else {
+                        start
+                    };
                     try!(write!(ctx,
                                 "{}",
                                 String::from_utf8_lossy(&ctx.input[start..end])));
@@ -4214,13 +7625,45 @@ impl Demangle for ExprPrimary {
     }
 }
 
-/// The `<initializer>` production.
-///
-/// ```text
-/// <initializer> ::= pi <expression>* E # parenthesized initialization
-/// ```
-#[derive(Clone, Debug, Hash, PartialEq, Eq)]
-pub struct Initializer(Vec<Expression>);
+impl SemanticEq for ExprPrimary {
+    fn semantic_eq(&self, ctx: &SemanticEqContext, other: &ExprPrimary) -> bool {
+        match (self, other) {
+            (&ExprPrimary::Literal(ref lhs_ty, lhs_start, lhs_end),
+             &ExprPrimary::Literal(ref rhs_ty, rhs_start, rhs_end)) => {
+                lhs_ty.semantic_eq(ctx, rhs_ty) &&
+                ctx.lhs_input[lhs_start..lhs_end] == ctx.rhs_input[rhs_start..rhs_end]
+            }
+            (&ExprPrimary::External(ref lhs), &ExprPrimary::External(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Remangle for ExprPrimary {
+    fn remangle(&self, ctx: &mut RemangleContext) -> RemangleResult {
+        ctx.write(b"L");
+        match *self {
+            ExprPrimary::Literal(ref ty, start, end) => {
+                try!(ty.remangle(ctx));
+                let input = ctx.input;
+                ctx.write(&input[start..end]);
+            }
+            ExprPrimary::External(ref name) => try!(name.remangle(ctx)),
+        }
+        ctx.write(b"E");
+        Ok(())
+    }
+}
+
+/// The `<initializer>` production.
+///
+/// ```text
+/// <initializer> ::= pi <expression>* E # parenthesized initialization
+/// ```
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct Initializer(Vec<Expression>);
 
 impl Parse for Initializer {
     fn parse<'a, 'b>(subs: &'a mut SubstitutionTable,
@@ -4239,7 +7682,7 @@ impl Demangle for Initializer {
     fn demangle<W>(&self,
                    ctx: &mut DemangleContext<W>,
                    stack: Option<ArgStack>)
-                   -> io::Result<()>
+                   -> DemangleResult<()>
         where W: io::Write
     {
         try!(write!(ctx, "("));
@@ -4256,6 +7699,14 @@ impl Demangle for Initializer {
     }
 }
 
+impl SemanticEq for Initializer {
+    fn semantic_eq(&self, ctx: &SemanticEqContext, other: &Initializer) -> bool {
+        let Initializer(ref lhs) = *self;
+        let Initializer(ref rhs) = *other;
+        lhs.semantic_eq(ctx, rhs)
+    }
+}
+
 /// The `<local-name>` production.
 ///
 /// ```text
@@ -4273,6 +7724,17 @@ pub enum LocalName {
     Default(Box<Encoding>, Option<usize>, Box<Name>),
 }
 
+impl LocalName {
+    /// Get the encoding of the function that this local entity is declared
+    /// within.
+    pub fn enclosing_encoding(&self) -> &Encoding {
+        match *self {
+            LocalName::Relative(ref encoding, _, _) |
+            LocalName::Default(ref encoding, _, _) => encoding,
+        }
+    }
+}
+
 impl Parse for LocalName {
     fn parse<'a, 'b>(subs: &'a mut SubstitutionTable,
                      input: IndexStr<'b>)
@@ -4316,26 +7778,80 @@ impl Parse for LocalName {
     }
 }
 
-impl Demangle for LocalName {
-    fn demangle<W>(&self,
-                   ctx: &mut DemangleContext<W>,
-                   stack: Option<ArgStack>)
-                   -> io::Result<()>
-        where W: io::Write
+impl DemangleWithInner for LocalName {
+    fn demangle_with_inner<D, W>(&self,
+                                 inner: Option<&D>,
+                                 ctx: &mut DemangleContext<W>,
+                                 stack: Option<ArgStack>)
+                                 -> DemangleResult<()>
+        where D: ?Sized + Demangle,
+              W: io::Write
     {
+        // The inner item, such as a local member function's parameter list,
+        // has to go before the qualifiers of a `<nested-name>`, just as it
+        // would if the function weren't local.
+        fn demangle_name<D, W>(name: &Name,
+                               inner: Option<&D>,
+                               ctx: &mut DemangleContext<W>,
+                               stack: Option<ArgStack>)
+                               -> DemangleResult<()>
+            where D: ?Sized + Demangle,
+                  W: io::Write
+        {
+            if let Name::Nested(ref name) = *name {
+                return name.demangle_with_inner(inner, ctx, stack);
+            }
+            try!(name.demangle(ctx, stack));
+            if let Some(inner) = inner {
+                try!(inner.demangle(ctx, stack));
+            }
+            Ok(())
+        }
+
         match *self {
             LocalName::Relative(ref encoding, Some(ref name), _) => {
                 try!(encoding.demangle(ctx, stack));
                 try!(write!(ctx, "::"));
-                name.demangle(ctx, stack)
+                demangle_name(name, inner, ctx, stack)
             }
             LocalName::Relative(ref encoding, None, _) => {
                 // No name means that this is the symbol for a string literal.
                 try!(encoding.demangle(ctx, stack));
                 try!(write!(ctx, "::string literal"));
+                if let Some(inner) = inner {
+                    try!(inner.demangle(ctx, stack));
+                }
                 Ok(())
             }
-            LocalName::Default(ref encoding, _, _) => encoding.demangle(ctx, stack),
+            LocalName::Default(ref encoding, param, ref name) => {
+                // Like libiberty, number the default arguments from one, where
+                // `d_` is the last parameter, `d0_` the second to last, etc.
+                try!(encoding.demangle(ctx, stack));
+                try!(write!(ctx,
+                            "::{{default arg#{}}}::",
+                            param.map_or(1, |param| param + 2)));
+                demangle_name(name, inner, ctx, stack)
+            }
+        }
+    }
+}
+
+impl SemanticEq for LocalName {
+    fn semantic_eq(&self, ctx: &SemanticEqContext, other: &LocalName) -> bool {
+        match (self, other) {
+            (&LocalName::Relative(ref lhs_encoding, ref lhs_name, ref lhs_discriminator),
+             &LocalName::Relative(ref rhs_encoding, ref rhs_name, ref rhs_discriminator)) => {
+                lhs_encoding.semantic_eq(ctx, rhs_encoding) &&
+                lhs_name.semantic_eq(ctx, rhs_name) &&
+                lhs_discriminator.semantic_eq(ctx, rhs_discriminator)
+            }
+            (&LocalName::Default(ref lhs_encoding, ref lhs_param, ref lhs_name),
+             &LocalName::Default(ref rhs_encoding, ref rhs_param, ref rhs_name)) => {
+                lhs_encoding.semantic_eq(ctx, rhs_encoding) &&
+                lhs_param.semantic_eq(ctx, rhs_param) &&
+                lhs_name.semantic_eq(ctx, rhs_name)
+            }
+            _ => false,
         }
     }
 }
@@ -4352,6 +7868,47 @@ impl GetTemplateArgs for LocalName {
     }
 }
 
+impl GetLeafName for LocalName {
+    fn get_leaf_name<'a>(&'a self,
+                         subs: &'a SubstitutionTable)
+                         -> Option<&'a UnqualifiedName> {
+        match *self {
+            LocalName::Relative(_, None, _) => None,
+            LocalName::Relative(_, Some(ref name), _) |
+            LocalName::Default(_, _, ref name) => name.get_leaf_name(subs),
+        }
+    }
+}
+
+impl LocalName {
+    // The scope of a local entity is the whole name of its enclosing function,
+    // followed by the scope of the entity within that function.
+    fn match_enclosing_scope<'s, 't>(&self,
+                                     subs: &SubstitutionTable,
+                                     input: &[u8],
+                                     scope: &'s [&'t [u8]])
+                                     -> Option<&'s [&'t [u8]]> {
+        let scope = match *self.enclosing_encoding() {
+            Encoding::Function(ref name, _, _) => {
+                match name.match_scope(subs, input, scope) {
+                    Some(rest) => rest,
+                    None => return None,
+                }
+            }
+            Encoding::Data(_) |
+            Encoding::Special(_) => return None,
+        };
+
+        match *self {
+            LocalName::Relative(_, None, _) => Some(scope),
+            LocalName::Relative(_, Some(ref name), _) |
+            LocalName::Default(_, _, ref name) => {
+                name.match_enclosing_scope(subs, input, scope)
+            }
+        }
+    }
+}
+
 /// The `<discriminator>` production.
 ///
 /// ```text
@@ -4403,10 +7960,14 @@ impl Parse for Discriminator {
 /// The `<closure-type-name>` production.
 ///
 /// ```text
-/// <closure-type-name> ::= Ul <lambda-sig> E [ <nonnegative number> ] _
+/// <closure-type-name> ::= Ul <template-param-decl>* <lambda-sig> E [ <nonnegative number> ] _
 /// ```
+///
+/// A generic lambda with an explicit template parameter list, like
+/// `[]<typename T>(T) {}`, declares its template parameters before its
+/// signature, and the `<template-param>`s in its signature refer to them.
 #[derive(Clone, Debug, Hash, PartialEq, Eq)]
-pub struct ClosureTypeName(LambdaSig, Option<usize>);
+pub struct ClosureTypeName(Vec<TemplateParamDecl>, LambdaSig, Option<usize>);
 
 impl Parse for ClosureTypeName {
     fn parse<'a, 'b>(subs: &'a mut SubstitutionTable,
@@ -4415,6 +7976,8 @@ impl Parse for ClosureTypeName {
         log_parse!("ClosureTypeName", input);
 
         let tail = try!(consume(b"Ul", input));
+        let mut counts = TemplateParamDeclCounts::default();
+        let (params, tail) = try!(TemplateParamDecl::parse_list(subs, tail, &mut counts));
         let (sig, tail) = try!(LambdaSig::parse(subs, tail));
         let tail = try!(consume(b"E", tail));
         let (num, tail) = if let Ok((num, tail)) = parse_number(10, false, tail) {
@@ -4423,7 +7986,7 @@ impl Parse for ClosureTypeName {
             (None, tail)
         };
         let tail = try!(consume(b"_", tail));
-        Ok((ClosureTypeName(sig, num), tail))
+        Ok((ClosureTypeName(params, sig, num), tail))
     }
 }
 
@@ -4431,16 +7994,236 @@ impl Demangle for ClosureTypeName {
     fn demangle<W>(&self,
                    ctx: &mut DemangleContext<W>,
                    stack: Option<ArgStack>)
-                   -> io::Result<()>
+                   -> DemangleResult<()>
         where W: io::Write
     {
-        try!(write!(ctx, "{{lambda("));
-        try!(self.0.demangle(ctx, stack));
-        try!(write!(ctx, ")#{}}}", self.1.map_or(0, |n| n + 1)));
+        try!(ctx.write_lambda_prefix(self.2));
+        if !self.0.is_empty() {
+            try!(TemplateParamDecl::demangle_list(&self.0, ctx, stack));
+        }
+        try!(write!(ctx, "("));
+        try!(self.1.demangle(ctx, stack.push(self)));
+        try!(write!(ctx, ")"));
+        try!(ctx.write_lambda_suffix(self.2));
+        Ok(())
+    }
+}
+
+impl SemanticEq for ClosureTypeName {
+    fn semantic_eq(&self, ctx: &SemanticEqContext, other: &ClosureTypeName) -> bool {
+        let ClosureTypeName(ref lhs_params, ref lhs_sig, ref lhs_n) = *self;
+        let ClosureTypeName(ref rhs_params, ref rhs_sig, ref rhs_n) = *other;
+        lhs_params.semantic_eq(ctx, rhs_params) &&
+        lhs_sig.semantic_eq(ctx, rhs_sig) &&
+        lhs_n.semantic_eq(ctx, rhs_n)
+    }
+}
+
+impl ArgResolver for ClosureTypeName {
+    fn get_template_arg(&self, _: usize) -> Result<&TemplateArg> {
+        Err(error::Error::BadTemplateArgReference)
+    }
+
+    fn get_function_arg(&self, _: usize) -> Result<&Type> {
+        Err(error::Error::BadFunctionArgReference)
+    }
+
+    fn get_template_param_decl(&self, idx: usize) -> Option<&TemplateParamDecl> {
+        self.0.get(idx)
+    }
+}
+
+/// The `<template-param-decl>` production.
+///
+/// ```text
+/// <template-param-decl> ::= Ty                          # type parameter
+///                       ::= Tn <type>                   # non-type parameter
+///                       ::= Tt <template-param-decl>* E # template parameter
+///                       ::= Tp <template-param-decl>    # parameter pack
+/// ```
+///
+/// Declared parameters have no names in the mangling, so we invent them: `$T`
+/// for types, `$N` for non-types, and `$TT` for templates, followed by a
+/// number. Like libiberty, the `GnuV3` flavor numbers parameters by their
+/// position in the list, as in `$T0, $N1, $T2`, while like LLVM, the `Llvm`
+/// flavor counts each kind separately, as in `$T, $N, $T0`. Each variant holds
+/// its parameter's index among those of its kind.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub enum TemplateParamDecl {
+    /// A type parameter.
+    Type(usize),
+
+    /// A non-type parameter of the given type.
+    NonType(usize, TypeHandle),
+
+    /// A template parameter with the given template parameters.
+    Template(usize, Vec<TemplateParamDecl>),
+
+    /// A parameter pack.
+    Pack(Box<TemplateParamDecl>),
+}
+
+// The number of template parameters of each kind declared so far, used to
+// invent their names.
+#[derive(Default)]
+struct TemplateParamDeclCounts {
+    types: usize,
+    non_types: usize,
+    templates: usize,
+}
+
+fn next_template_param_index(count: &mut usize) -> usize {
+    let idx = *count;
+    *count += 1;
+    idx
+}
+
+impl TemplateParamDecl {
+    fn parse_list<'a, 'b>(subs: &'a mut SubstitutionTable,
+                          mut input: IndexStr<'b>,
+                          counts: &mut TemplateParamDeclCounts)
+                          -> Result<(Vec<TemplateParamDecl>, IndexStr<'b>)> {
+        let mut params = vec![];
+        while TemplateParamDecl::starts_here(input) {
+            let (param, tail) = try!(TemplateParamDecl::parse_numbered(subs, input, counts));
+            params.push(param);
+            input = tail;
+        }
+        Ok((params, input))
+    }
+
+    // `T` also starts `<template-param>`s and elaborated type specifiers, so
+    // look at the second byte too.
+    fn starts_here(input: IndexStr) -> bool {
+        match input.try_split_at(2) {
+            Some((head, _)) => {
+                match head.as_ref() {
+                    b"Ty" | b"Tn" | b"Tt" | b"Tp" => true,
+                    _ => false,
+                }
+            }
+            None => false,
+        }
+    }
+
+    fn parse_numbered<'a, 'b>(subs: &'a mut SubstitutionTable,
+                              input: IndexStr<'b>,
+                              counts: &mut TemplateParamDeclCounts)
+                              -> Result<(TemplateParamDecl, IndexStr<'b>)> {
+        log_parse!("TemplateParamDecl", input);
+
+        if let Ok(tail) = consume(b"Ty", input) {
+            let idx = next_template_param_index(&mut counts.types);
+            return Ok((TemplateParamDecl::Type(idx), tail));
+        }
+
+        if let Ok(tail) = consume(b"Tn", input) {
+            let idx = next_template_param_index(&mut counts.non_types);
+            let (ty, tail) = try!(TypeHandle::parse(subs, tail));
+            return Ok((TemplateParamDecl::NonType(idx, ty), tail));
+        }
+
+        if let Ok(tail) = consume(b"Tt", input) {
+            let idx = next_template_param_index(&mut counts.templates);
+            let (params, tail) = try!(TemplateParamDecl::parse_list(subs, tail, counts));
+            let tail = try!(consume(b"E", tail));
+            return Ok((TemplateParamDecl::Template(idx, params), tail));
+        }
+
+        let tail = try!(consume(b"Tp", input));
+        let (param, tail) = try!(TemplateParamDecl::parse_numbered(subs, tail, counts));
+        Ok((TemplateParamDecl::Pack(Box::new(param)), tail))
+    }
+
+    fn demangle_list<W>(params: &[TemplateParamDecl],
+                        ctx: &mut DemangleContext<W>,
+                        stack: Option<ArgStack>)
+                        -> DemangleResult<()>
+        where W: io::Write
+    {
+        try!(write!(ctx, "<"));
+        let mut need_comma = false;
+        for (position, param) in params.iter().enumerate() {
+            if need_comma {
+                try!(write!(ctx, ", "));
+            }
+            try!(param.demangle_decl(ctx, stack, position, false));
+            need_comma = true;
+        }
+        try!(write!(ctx, ">"));
+        Ok(())
+    }
+
+    fn demangle_decl<W>(&self,
+                        ctx: &mut DemangleContext<W>,
+                        stack: Option<ArgStack>,
+                        position: usize,
+                        is_pack: bool)
+                        -> DemangleResult<()>
+        where W: io::Write
+    {
+        match *self {
+            TemplateParamDecl::Type(_) => try!(write!(ctx, "typename ")),
+            TemplateParamDecl::NonType(_, ref ty) => {
+                try!(ty.demangle(ctx, stack));
+                try!(write!(ctx, " "));
+            }
+            TemplateParamDecl::Template(_, ref params) => {
+                try!(write!(ctx, "template"));
+                try!(TemplateParamDecl::demangle_list(params, ctx, stack));
+                try!(write!(ctx, " typename "));
+            }
+            TemplateParamDecl::Pack(ref param) => {
+                return param.demangle_decl(ctx, stack, position, true);
+            }
+        }
+
+        if is_pack {
+            try!(write!(ctx, "..."));
+        }
+        self.demangle_name(ctx, position)
+    }
+
+    fn demangle_name<W>(&self,
+                        ctx: &mut DemangleContext<W>,
+                        position: usize)
+                        -> DemangleResult<()>
+        where W: io::Write
+    {
+        let (kind, idx) = match *self {
+            TemplateParamDecl::Type(idx) => ("T", idx),
+            TemplateParamDecl::NonType(idx, _) => ("N", idx),
+            TemplateParamDecl::Template(idx, _) => ("TT", idx),
+            TemplateParamDecl::Pack(ref param) => {
+                return param.demangle_name(ctx, position);
+            }
+        };
+
+        try!(ctx.write_template_param_decl_name(kind, idx, position));
         Ok(())
     }
 }
 
+impl SemanticEq for TemplateParamDecl {
+    fn semantic_eq(&self, ctx: &SemanticEqContext, other: &TemplateParamDecl) -> bool {
+        match (self, other) {
+            (&TemplateParamDecl::Type(lhs), &TemplateParamDecl::Type(rhs)) => lhs == rhs,
+            (&TemplateParamDecl::NonType(lhs_idx, ref lhs_ty),
+             &TemplateParamDecl::NonType(rhs_idx, ref rhs_ty)) => {
+                lhs_idx == rhs_idx && lhs_ty.semantic_eq(ctx, rhs_ty)
+            }
+            (&TemplateParamDecl::Template(lhs_idx, ref lhs_params),
+             &TemplateParamDecl::Template(rhs_idx, ref rhs_params)) => {
+                lhs_idx == rhs_idx && lhs_params.semantic_eq(ctx, rhs_params)
+            }
+            (&TemplateParamDecl::Pack(ref lhs), &TemplateParamDecl::Pack(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            _ => false,
+        }
+    }
+}
+
 /// The `<lambda-sig>` production.
 ///
 /// ```text
@@ -4468,7 +8251,7 @@ impl Demangle for LambdaSig {
     fn demangle<W>(&self,
                    ctx: &mut DemangleContext<W>,
                    stack: Option<ArgStack>)
-                   -> io::Result<()>
+                   -> DemangleResult<()>
         where W: io::Write
     {
         let mut need_comma = false;
@@ -4483,6 +8266,14 @@ impl Demangle for LambdaSig {
     }
 }
 
+impl SemanticEq for LambdaSig {
+    fn semantic_eq(&self, ctx: &SemanticEqContext, other: &LambdaSig) -> bool {
+        let LambdaSig(ref lhs) = *self;
+        let LambdaSig(ref rhs) = *other;
+        lhs.semantic_eq(ctx, rhs)
+    }
+}
+
 /// The `<data-member-prefix>` production.
 ///
 /// ```text
@@ -4514,13 +8305,21 @@ impl Demangle for DataMemberPrefix {
     fn demangle<W>(&self,
                    ctx: &mut DemangleContext<W>,
                    stack: Option<ArgStack>)
-                   -> io::Result<()>
+                   -> DemangleResult<()>
         where W: io::Write
     {
         self.0.demangle(ctx, stack)
     }
 }
 
+impl SemanticEq for DataMemberPrefix {
+    fn semantic_eq(&self, ctx: &SemanticEqContext, other: &DataMemberPrefix) -> bool {
+        let DataMemberPrefix(ref lhs) = *self;
+        let DataMemberPrefix(ref rhs) = *other;
+        lhs.semantic_eq(ctx, rhs)
+    }
+}
+
 /// The `<substitution>` form: a back-reference to some component we've already
 /// parsed.
 ///
@@ -4566,11 +8365,12 @@ impl Parse for Substitution {
             (0, tail)
         };
 
+        let tail = try!(consume(b"_", tail));
+
         if !subs.contains(idx) {
             return Err(error::Error::BadBackReference);
         }
 
-        let tail = try!(consume(b"_", tail));
         log!("Found a reference to @ {}", idx);
         Ok((Substitution::BackReference(idx), tail))
     }
@@ -4586,9 +8386,43 @@ define_vocabulary! {
         StdAllocator (b"Sa", "std::allocator"),
         StdString1   (b"Sb", "std::basic_string"),
         StdString2   (b"Ss", "std::string"),
-        StdIstream   (b"Si", "std::basic_istream<char, std::char_traits<char> >"),
+        StdIstream   (b"Si", "std::istream"),
         StdOstream   (b"So", "std::ostream"),
-        StdIostream  (b"Sd", "std::basic_iostream<char, std::char_traits<char> >")
+        StdIostream  (b"Sd", "std::iostream")
+    }
+}
+
+impl WellKnownComponent {
+    // The identifier that this component names within `std`, or `None` for
+    // `std` itself.
+    fn std_member(&self) -> Option<&'static [u8]> {
+        match *self {
+            WellKnownComponent::Std => None,
+            WellKnownComponent::StdAllocator => Some(b"allocator"),
+            WellKnownComponent::StdString1 => Some(b"basic_string"),
+            WellKnownComponent::StdString2 => Some(b"string"),
+            WellKnownComponent::StdIstream => Some(b"basic_istream"),
+            WellKnownComponent::StdOstream => Some(b"ostream"),
+            WellKnownComponent::StdIostream => Some(b"basic_iostream"),
+        }
+    }
+
+    fn match_scope<'s, 't>(&self, scope: &'s [&'t [u8]]) -> Option<&'s [&'t [u8]]> {
+        match_scope_component(scope, Some(b"std")).and_then(|rest| {
+            match self.std_member() {
+                Some(member) => match_scope_component(rest, Some(member)),
+                None => Some(rest),
+            }
+        })
+    }
+
+    fn match_enclosing_scope<'s, 't>(&self,
+                                     scope: &'s [&'t [u8]])
+                                     -> Option<&'s [&'t [u8]]> {
+        match self.std_member() {
+            Some(_) => match_scope_component(scope, Some(b"std")),
+            None => Some(scope),
+        }
     }
 }
 
@@ -4656,6 +8490,9 @@ pub enum SpecialName {
 
     /// A temporary used in the initialization of a static storage and promoted
     /// to a static lifetime.
+    ///
+    /// Temporaries are numbered from zero in mangling order: `GR <name> _` is
+    /// the first, and `GR <name> <seq-id> _` is the `seq-id + 1`th.
     GuardTemporary(Name, usize),
 }
 
@@ -4728,51 +8565,39 @@ impl Demangle for SpecialName {
     fn demangle<W>(&self,
                    ctx: &mut DemangleContext<W>,
                    stack: Option<ArgStack>)
-                   -> io::Result<()>
+                   -> DemangleResult<()>
         where W: io::Write
     {
         match *self {
             SpecialName::VirtualTable(ref ty) => {
-                try!(write!(ctx, "{{vtable("));
-                try!(ty.demangle(ctx, stack));
-                try!(write!(ctx, ")}}"));
-                Ok(())
+                try!(write!(ctx, "vtable for "));
+                ty.demangle(ctx, stack)
             }
             SpecialName::Vtt(ref ty) => {
-                try!(write!(ctx, "{{vtt("));
-                try!(ty.demangle(ctx, stack));
-                try!(write!(ctx, ")}}"));
-                Ok(())
+                try!(write!(ctx, "VTT for "));
+                ty.demangle(ctx, stack)
             }
             SpecialName::Typeinfo(ref ty) => {
                 try!(write!(ctx, "typeinfo for "));
                 ty.demangle(ctx, stack)
             }
             SpecialName::TypeinfoName(ref ty) => {
-                try!(write!(ctx, "{{typeinfo name("));
-                try!(ty.demangle(ctx, stack));
-                try!(write!(ctx, ")}}"));
-                Ok(())
+                try!(write!(ctx, "typeinfo name for "));
+                ty.demangle(ctx, stack)
             }
-            SpecialName::VirtualOverrideThunk(ref offset, ref encoding) => {
-                try!(write!(ctx, "{{virtual override thunk("));
-                try!(offset.demangle(ctx, stack));
-                try!(write!(ctx, ", "));
-                try!(encoding.demangle(ctx, stack));
-                try!(write!(ctx, ")}}"));
-                Ok(())
+            // Like libiberty, only say what kind of thunk this is, rather than
+            // printing its raw offsets.
+            SpecialName::VirtualOverrideThunk(CallOffset::NonVirtual(_), ref encoding) => {
+                try!(write!(ctx, "non-virtual thunk to "));
+                encoding.demangle(ctx, stack)
             }
-            SpecialName::VirtualOverrideThunkCovariant(ref this_offset,
-                                                       ref result_offset,
-                                                       ref encoding) => {
-                try!(write!(ctx, "{{virtual override thunk("));
-                try!(this_offset.demangle(ctx, stack));
-                try!(write!(ctx, ", "));
-                try!(result_offset.demangle(ctx, stack));
-                try!(write!(ctx, ", "));
-                try!(encoding.demangle(ctx, stack));
-                try!(write!(ctx, ")}}"));
-                Ok(())
+            SpecialName::VirtualOverrideThunk(CallOffset::Virtual(_), ref encoding) => {
+                try!(write!(ctx, "virtual thunk to "));
+                encoding.demangle(ctx, stack)
+            }
+            SpecialName::VirtualOverrideThunkCovariant(_, _, ref encoding) => {
+                try!(write!(ctx, "covariant return thunk to "));
+                encoding.demangle(ctx, stack)
             }
             SpecialName::Guard(ref name) => {
                 try!(write!(ctx, "{{static initialization guard("));
@@ -4781,11 +8606,50 @@ impl Demangle for SpecialName {
                 Ok(())
             }
             SpecialName::GuardTemporary(ref name, n) => {
-                try!(write!(ctx, "{{static initialization guard temporary("));
-                try!(name.demangle(ctx, stack));
-                try!(write!(ctx, ", {})}}", n));
-                Ok(())
+                try!(write!(ctx, "reference temporary #{} for ", n));
+                name.demangle(ctx, stack)
+            }
+        }
+    }
+}
+
+impl SemanticEq for SpecialName {
+    fn semantic_eq(&self, ctx: &SemanticEqContext, other: &SpecialName) -> bool {
+        match (self, other) {
+            (&SpecialName::VirtualTable(ref lhs), &SpecialName::VirtualTable(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&SpecialName::Vtt(ref lhs), &SpecialName::Vtt(ref rhs)) => lhs.semantic_eq(ctx, rhs),
+            (&SpecialName::Typeinfo(ref lhs), &SpecialName::Typeinfo(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&SpecialName::TypeinfoName(ref lhs), &SpecialName::TypeinfoName(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
             }
+            (&SpecialName::VirtualOverrideThunk(ref lhs_offset, ref lhs_encoding),
+             &SpecialName::VirtualOverrideThunk(ref rhs_offset, ref rhs_encoding)) => {
+                lhs_offset.semantic_eq(ctx, rhs_offset) &&
+                lhs_encoding.semantic_eq(ctx, rhs_encoding)
+            }
+            (&SpecialName::VirtualOverrideThunkCovariant(ref lhs_this_offset,
+                                                         ref lhs_result_offset,
+                                                         ref lhs_encoding),
+             &SpecialName::VirtualOverrideThunkCovariant(ref rhs_this_offset,
+                                                         ref rhs_result_offset,
+                                                         ref rhs_encoding)) => {
+                lhs_this_offset.semantic_eq(ctx, rhs_this_offset) &&
+                lhs_result_offset.semantic_eq(ctx, rhs_result_offset) &&
+                lhs_encoding.semantic_eq(ctx, rhs_encoding)
+            }
+            (&SpecialName::Guard(ref lhs), &SpecialName::Guard(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&SpecialName::GuardTemporary(ref lhs_name, ref lhs_n),
+             &SpecialName::GuardTemporary(ref rhs_name, ref rhs_n)) => {
+                lhs_name.semantic_eq(ctx, rhs_name) &&
+                lhs_n.semantic_eq(ctx, rhs_n)
+            }
+            _ => false,
         }
     }
 }
@@ -4811,11 +8675,14 @@ fn one_or_more<'a, 'b, P>(subs: &'a mut SubstitutionTable,
     let (first, mut tail) = try!(P::parse(subs, input));
     let mut results = vec![first];
     loop {
-        if let Ok((parsed, tail_tail)) = P::parse(subs, tail) {
-            results.push(parsed);
-            tail = tail_tail;
-        } else {
-            return Ok((results, tail));
+        match P::parse(subs, tail) {
+            // Stop if a production that can match the empty string matched
+            // it, rather than matching it forever.
+            Ok((parsed, tail_tail)) if tail_tail.len() < tail.len() => {
+                results.push(parsed);
+                tail = tail_tail;
+            }
+            _ => return Ok((results, tail)),
         }
     }
 }
@@ -4828,11 +8695,13 @@ fn zero_or_more<'a, 'b, P>(subs: &'a mut SubstitutionTable,
     let mut tail = input;
     let mut results = vec![];
     loop {
-        if let Ok((parsed, tail_tail)) = P::parse(subs, tail) {
-            results.push(parsed);
-            tail = tail_tail;
-        } else {
-            return Ok((results, tail));
+        match P::parse(subs, tail) {
+            // See `one_or_more`.
+            Ok((parsed, tail_tail)) if tail_tail.len() < tail.len() => {
+                results.push(parsed);
+                tail = tail_tail;
+            }
+            _ => return Ok((results, tail)),
         }
     }
 }
@@ -4895,25 +8764,29 @@ fn parse_number(base: u32,
 
 #[cfg(test)]
 mod tests {
-    use error::Error;
+    use error::{DemangleError, Error};
     use index_str::IndexStr;
+    use std::borrow::Cow;
     use std::fmt::Debug;
     use std::iter::FromIterator;
     use subs::{Substitutable, SubstitutionTable};
-    use super::{ArrayType, BareFunctionType, BaseUnresolvedName, BuiltinType,
-                CallOffset, ClassEnumType, ClosureTypeName, CtorDtorName, CvQualifiers,
-                DataMemberPrefix, Decltype, Demangle, DemangleContext, DestructorName,
-                Discriminator, Encoding, ExprPrimary, Expression, FunctionParam,
-                FunctionType, Identifier, Initializer, LambdaSig, LocalName,
-                MangledName, Name, NestedName, Number, NvOffset, OperatorName, Parse,
-                PointerToMemberType, Prefix, PrefixHandle, RefQualifier, SeqId,
-                SimpleId, SourceName, SpecialName, StandardBuiltinType, Substitution,
-                TemplateArg, TemplateArgs, TemplateParam, TemplateTemplateParam,
+    use {BorrowedSymbol, ComplexSpelling, DemangleOptions, DemangleStats, Demangler,
+         Flavor, Markup, MarkupRegion, ParseOptions, Symbol, demangle_or_borrow};
+    use super::{ArrayType, BareFunctionType, BaseUnresolvedName, BuiltinType, CallOffset,
+                ClassEnumType, ClosureTypeName, CtorDtorKind, CtorDtorName, CvQualifiers,
+                DPrefix, DataMemberPrefix, Decltype, Demangle, DemangleContext,
+                DestructorName, Discriminator, Encoding, ExprPrimary, Expression,
+                FixedPointType, FunctionParam, FunctionType, GlobalCtorDtor,
+                GlobalCtorDtorKey, Identifier, Initializer, LambdaSig, LocalName,
+                MangledName, Name, NestedName, NonSubstitution, Number, NvOffset,
+                OperatorName, Parse, PointerToMemberType, Prefix, PrefixHandle,
+                RefQualifier, Requirement, SeqId, SimpleId, SourceName, SpecialName,
+                StandardBuiltinType, Substitution, TemplateArg, TemplateArgs,
+                TemplateParam, TemplateParamDecl, TemplateTemplateParam,
                 TemplateTemplateParamHandle, Type, TypeHandle, UnnamedTypeName,
-                UnqualifiedName, UnresolvedName, UnresolvedQualifierLevel,
-                UnresolvedType, UnresolvedTypeHandle, UnscopedName,
-                UnscopedTemplateName, UnscopedTemplateNameHandle, VOffset,
-                WellKnownComponent};
+                UnqualifiedName, UnresolvedName, UnresolvedQualifierLevel, UnresolvedType,
+                UnresolvedTypeHandle, UnscopedName, UnscopedTemplateName,
+                UnscopedTemplateNameHandle, VOffset, WellKnownComponent, peek_d_prefix};
 
     fn assert_parse_ok<P, S1, S2, I1, I2>(production: &'static str,
                                           subs: S1,
@@ -4958,7 +8831,9 @@ mod tests {
                            tail,
                            String::from_utf8_lossy(expected_tail));
                 }
-                if subs != expected_subs {
+                // Only the numbered substitutions can be back referenced, so
+                // those are what we compare.
+                if subs[..] != expected_subs[..] {
                     panic!("Parsing {:?} as {} produced a substitutions table of\n\n\
                             {:#?}\n\n\
                             but we expected\n\n\
@@ -5107,16 +8982,87 @@ mod tests {
                                         })))))),
                     b"..."
                 }
+                // A top-level type need not consume all of the input here;
+                // `Symbol` is what rejects the trailing `3foo`.
+                b"i3foo" => {
+                    MangledName::Type(
+                        TypeHandle::Builtin(
+                            BuiltinType::Standard(StandardBuiltinType::Int))),
+                    b"3foo"
+                }
             }
             Err => {
                 b"_Y" => Error::UnexpectedText,
-                b"_Z" => Error::UnexpectedText,
+                b"_Z" => Error::UnexpectedEnd,
+                b"__Z" => Error::UnexpectedEnd,
+                b"___Z3fooi" => Error::UnexpectedText,
+                b"_ZN" => Error::UnexpectedEnd,
+                b"_ZN3foo" => Error::UnexpectedEnd,
+                b"_Z3fo" => Error::UnexpectedEnd,
+                b"_ZTV" => Error::UnexpectedEnd,
+                b"_Z1fI" => Error::UnexpectedEnd,
+                b"_ZZ3foovE" => Error::UnexpectedEnd,
+                b"_ZS" => Error::UnexpectedEnd,
+                b"_Z1fP" => Error::UnexpectedEnd,
+                b"_Z1fR" => Error::UnexpectedEnd,
+                b"_Z1fA" => Error::UnexpectedEnd,
+                b"_Z1fM" => Error::UnexpectedEnd,
+                b"_Z1fPF" => Error::UnexpectedEnd,
+                b"_Z1fL" => Error::UnexpectedEnd,
+                b"_Zx" => Error::UnexpectedText,
                 b"_" => Error::UnexpectedEnd,
                 b"" => Error::UnexpectedEnd,
             }
         });
     }
 
+    #[test]
+    fn parse_global_ctor_dtor() {
+        assert_parse!(GlobalCtorDtor {
+            Ok => {
+                b"_GLOBAL__sub_I_main.cpp ..." => {
+                    GlobalCtorDtor::Ctor(
+                        GlobalCtorDtorKey::FileName(Identifier {
+                            start: 15,
+                            end: 23,
+                        })),
+                    b" ..."
+                }
+                b"_GLOBAL_.D_foo" => {
+                    GlobalCtorDtor::Dtor(
+                        GlobalCtorDtorKey::FileName(Identifier {
+                            start: 11,
+                            end: 14,
+                        })),
+                    b""
+                }
+                b"_GLOBAL__I__Z3foo..." => {
+                    GlobalCtorDtor::Ctor(
+                        GlobalCtorDtorKey::MangledName(Box::new(
+                            MangledName::Encoding(
+                                Encoding::Data(
+                                    Name::Unscoped(
+                                        UnscopedName::Unqualified(
+                                            UnqualifiedName::Source(
+                                                SourceName(Identifier {
+                                                    start: 14,
+                                                    end: 17,
+                                                }))))))))),
+                    b"..."
+                }
+            }
+            Err => {
+                b"_GLOBAL_" => Error::UnexpectedEnd,
+                b"_GLOBAL__sub_" => Error::UnexpectedEnd,
+                b"_GLOBAL__I_" => Error::UnexpectedEnd,
+                b"_GLOBAL_xI_foo" => Error::UnexpectedText,
+                b"_GLOBAL__X_foo" => Error::UnexpectedText,
+                b"_GLOBAL__I_ foo" => Error::UnexpectedText,
+                b"_GLOBAL" => Error::UnexpectedEnd,
+            }
+        });
+    }
+
     #[test]
     fn parse_encoding() {
         assert_parse!(Encoding {
@@ -5133,7 +9079,8 @@ mod tests {
                                         })))),
                             BareFunctionType(vec![
                                 TypeHandle::Builtin(BuiltinType::Standard(StandardBuiltinType::Int))
-                            ])),
+                            ]),
+                            None),
                         b"...",
                         []
                     }
@@ -5149,10 +9096,31 @@ mod tests {
                         b"...",
                         []
                     }
-                    b"GV3abc..." => {
-                        Encoding::Special(
-                            SpecialName::Guard(
-                                Name::Unscoped(
+                    b"3fooIiE..." => {
+                        Encoding::Data(
+                            Name::UnscopedTemplate(
+                                UnscopedTemplateNameHandle::BackReference(0),
+                                TemplateArgs(vec![
+                                    TemplateArg::Type(
+                                        TypeHandle::Builtin(
+                                            BuiltinType::Standard(StandardBuiltinType::Int)))
+                                ]))),
+                        b"...",
+                        [
+                            Substitutable::UnscopedTemplateName(
+                                UnscopedTemplateName(
+                                    UnscopedName::Unqualified(
+                                        UnqualifiedName::Source(
+                                            SourceName(Identifier {
+                                                start: 1,
+                                                end: 4,
+                                            }))))),
+                        ]
+                    }
+                    b"GV3abc..." => {
+                        Encoding::Special(
+                            SpecialName::Guard(
+                                Name::Unscoped(
                                     UnscopedName::Unqualified(
                                         UnqualifiedName::Source(
                                             SourceName(Identifier {
@@ -5242,6 +9210,35 @@ mod tests {
                         b"...",
                         []
                     }
+                    b"StL3abc..." => {
+                        Name::Std(UnqualifiedName::LocalSourceName(
+                            SourceName(Identifier {
+                                start: 4,
+                                end: 7,
+                            }),
+                            None)),
+                        b"...",
+                        []
+                    }
+                    b"St3abcIcE..." => {
+                        Name::UnscopedTemplate(
+                            UnscopedTemplateNameHandle::BackReference(2),
+                            TemplateArgs(vec![
+                                TemplateArg::Type(
+                                    TypeHandle::Builtin(
+                                        BuiltinType::Standard(StandardBuiltinType::Char)))
+                            ])),
+                        b"...",
+                        [
+                            Substitutable::UnscopedTemplateName(
+                                UnscopedTemplateName(
+                                    UnscopedName::Std(
+                                        UnqualifiedName::Source(SourceName(Identifier {
+                                            start: 3,
+                                            end: 6,
+                                        }))))),
+                        ]
+                    }
                 }
                 Err => {
                     b"zzz" => Error::UnexpectedText,
@@ -5307,18 +9304,9 @@ mod tests {
                                 const_: true,
                             },
                             Some(RefQualifier::RValueRef),
-                            PrefixHandle::BackReference(1)),
+                            PrefixHandle::NonSubstitution(NonSubstitution(0))),
                         b"...",
-                        [
-                            Substitutable::Prefix(
-                                Prefix::Nested(
-                                    PrefixHandle::BackReference(0),
-                                    UnqualifiedName::Source(
-                                        SourceName(Identifier {
-                                            start: 6,
-                                            end: 9,
-                                        }))))
-                        ]
+                        []
                     }
                     b"NOS_3abcE..." => {
                         NestedName(
@@ -5328,18 +9316,9 @@ mod tests {
                                 const_: false,
                             },
                             Some(RefQualifier::RValueRef),
-                            PrefixHandle::BackReference(1)),
+                            PrefixHandle::NonSubstitution(NonSubstitution(0))),
                         b"...",
-                        [
-                            Substitutable::Prefix(
-                                Prefix::Nested(
-                                    PrefixHandle::BackReference(0),
-                                    UnqualifiedName::Source(
-                                        SourceName(Identifier {
-                                            start: 5,
-                                            end: 8,
-                                        }))))
-                        ]
+                        []
                     }
                     b"NS_3abcE..." => {
                         NestedName(
@@ -5349,18 +9328,33 @@ mod tests {
                                 const_: false,
                             },
                             None,
-                            PrefixHandle::BackReference(1)),
+                            PrefixHandle::NonSubstitution(NonSubstitution(0))),
                         b"...",
-                        [
-                            Substitutable::Prefix(
-                                Prefix::Nested(
-                                    PrefixHandle::BackReference(0),
-                                    UnqualifiedName::Source(
-                                        SourceName(Identifier {
-                                            start: 4,
-                                            end: 7,
-                                        }))))
-                        ]
+                        []
+                    }
+                    b"NS_C4E..." => {
+                        NestedName(
+                            CvQualifiers {
+                                restrict: false,
+                                volatile: false,
+                                const_: false,
+                            },
+                            None,
+                            PrefixHandle::NonSubstitution(NonSubstitution(0))),
+                        b"...",
+                        []
+                    }
+                    b"NS_D4E..." => {
+                        NestedName(
+                            CvQualifiers {
+                                restrict: false,
+                                volatile: false,
+                                const_: false,
+                            },
+                            None,
+                            PrefixHandle::NonSubstitution(NonSubstitution(0))),
+                        b"...",
+                        []
                     }
                     b"NKOS_3abcIJEEE..." => {
                         NestedName(
@@ -5370,7 +9364,7 @@ mod tests {
                                 const_: true,
                             },
                             Some(RefQualifier::RValueRef),
-                            PrefixHandle::BackReference(2)),
+                            PrefixHandle::NonSubstitution(NonSubstitution(0))),
                         b"...",
                         [
                             Substitutable::Prefix(
@@ -5381,10 +9375,6 @@ mod tests {
                                             start: 6,
                                             end: 9,
                                         })))),
-                            Substitutable::Prefix(
-                                Prefix::Template(
-                                    PrefixHandle::BackReference(1),
-                                    TemplateArgs(vec![TemplateArg::ArgPack(vec![])]))),
                         ]
                     }
                     b"NOS_3abcIJEEE..." => {
@@ -5395,7 +9385,7 @@ mod tests {
                                 const_: false,
                             },
                             Some(RefQualifier::RValueRef),
-                            PrefixHandle::BackReference(2)),
+                            PrefixHandle::NonSubstitution(NonSubstitution(0))),
                         b"...",
                         [
                             Substitutable::Prefix(
@@ -5406,10 +9396,6 @@ mod tests {
                                             start: 5,
                                             end: 8,
                                         })))),
-                            Substitutable::Prefix(
-                                Prefix::Template(
-                                    PrefixHandle::BackReference(1),
-                                    TemplateArgs(vec![TemplateArg::ArgPack(vec![])]))),
                         ]
                     }
                     b"NS_3abcIJEEE..." => {
@@ -5420,7 +9406,7 @@ mod tests {
                                 const_: false,
                             },
                             None,
-                            PrefixHandle::BackReference(2)),
+                            PrefixHandle::NonSubstitution(NonSubstitution(0))),
                         b"...",
                         [
                             Substitutable::Prefix(
@@ -5431,17 +9417,34 @@ mod tests {
                                             start: 4,
                                             end: 7,
                                         })))),
-                            Substitutable::Prefix(
-                                Prefix::Template(
-                                    PrefixHandle::BackReference(1),
-                                    TemplateArgs(vec![TemplateArg::ArgPack(vec![])]))),
                         ]
                     }
+                    b"NSt3abcE..." => {
+                        NestedName(
+                            CvQualifiers::default(),
+                            None,
+                            PrefixHandle::NonSubstitution(NonSubstitution(0))),
+                        b"...",
+                        []
+                    }
+                    b"NSaIiEE..." => {
+                        NestedName(
+                            CvQualifiers::default(),
+                            None,
+                            PrefixHandle::NonSubstitution(NonSubstitution(0))),
+                        b"...",
+                        []
+                    }
                 }
                 Err => {
                     // Ends with a prefix that is not a name or template.
                     b"NS_E..." => Error::UnexpectedText,
                     b"NS_DttrEE..." => Error::UnexpectedText,
+                    b"NStE..." => Error::UnexpectedText,
+                    b"NSsE..." => Error::UnexpectedText,
+
+                    // `std` is a namespace, not a template.
+                    b"NStIiEE..." => Error::UnexpectedText,
 
                     b"zzz" => Error::UnexpectedText,
                     b"Nzzz" => Error::UnexpectedText,
@@ -5453,7 +9456,8 @@ mod tests {
                     b"N" => Error::UnexpectedEnd,
                     b"NK" => Error::UnexpectedEnd,
                     b"NKO" => Error::UnexpectedEnd,
-                    b"NKO3abc" => Error::UnexpectedText,
+                    b"NKO3abc" => Error::UnexpectedEnd,
+                    b"NKO3abcE" => Error::UnexpectedText,
                     b"NKO3abc3abc" => Error::UnexpectedEnd,
                 }
             }
@@ -5571,9 +9575,10 @@ mod tests {
                         b"...",
                         []
                     }
-                    // The trailing E and <nested-name> case...
+                    // The trailing E and <nested-name> case. The last
+                    // component is not a substitution candidate.
                     b"3abc3defE..." => {
-                        PrefixHandle::BackReference(2),
+                        PrefixHandle::NonSubstitution(NonSubstitution(0)),
                         b"E...",
                         [
                             Substitutable::Prefix(
@@ -5583,14 +9588,6 @@ mod tests {
                                             start: 1,
                                             end: 4,
                                         })))),
-                            Substitutable::Prefix(
-                                Prefix::Nested(
-                                    PrefixHandle::BackReference(1),
-                                    UnqualifiedName::Source(
-                                        SourceName(Identifier {
-                                            start: 5,
-                                            end: 8,
-                                        })))),
                         ]
                     }
                 }
@@ -5788,6 +9785,12 @@ mod tests {
                     }
                 }
                 Err => {
+                    // CvQualifiers match the empty string, which must not
+                    // recurse back into TypeHandle forever.
+                    b"Ez" => Error::UnexpectedText,
+                    b"E" => Error::UnexpectedText,
+                    b"rVK" => Error::UnexpectedEnd,
+                    b"rVKEz" => Error::UnexpectedText,
                     b"P" => Error::UnexpectedEnd,
                     b"R" => Error::UnexpectedEnd,
                     b"O" => Error::UnexpectedEnd,
@@ -5795,6 +9798,10 @@ mod tests {
                     b"G" => Error::UnexpectedEnd,
                     b"Dp" => Error::UnexpectedEnd,
                     b"D" => Error::UnexpectedEnd,
+                    b"Dt" => Error::UnexpectedEnd,
+                    b"DxF" => Error::UnexpectedEnd,
+                    b"D1..." => Error::UnexpectedText,
+                    b"Dz..." => Error::UnexpectedText,
                     b"P" => Error::UnexpectedEnd,
                     b"" => Error::UnexpectedEnd,
                 }
@@ -5802,6 +9809,21 @@ mod tests {
         });
     }
 
+    #[test]
+    fn repetition_stops_on_empty_matches() {
+        let mut subs = SubstitutionTable::new();
+        let (quals, tail) = super::zero_or_more::<CvQualifiers>(&mut subs,
+                                                                IndexStr::new(b"KVKi"))
+            .expect("should parse");
+        assert_eq!(quals.len(), 2);
+        assert_eq!(tail, IndexStr::new(b"KVKi").range_from(3..));
+
+        let (quals, tail) = super::one_or_more::<CvQualifiers>(&mut subs, IndexStr::new(b"i"))
+            .expect("should parse");
+        assert_eq!(quals, vec![CvQualifiers::default()]);
+        assert_eq!(tail, IndexStr::new(b"i"));
+    }
+
     #[test]
     fn parse_function_type() {
         assert_parse!(FunctionType {
@@ -6034,6 +10056,13 @@ mod tests {
                         b"...",
                         []
                     }
+                    b"AT__S_..." => {
+                        ArrayType::DimensionExpression(
+                            Expression::TemplateParam(TemplateParam(0)),
+                            TypeHandle::BackReference(0)),
+                        b"...",
+                        []
+                    }
                     b"A_S_..." => {
                         ArrayType::NoDimension(TypeHandle::BackReference(0)),
                         b"...",
@@ -6136,9 +10165,29 @@ mod tests {
                         b"...",
                         []
                     }
+                    b"IJEE..." => {
+                        TemplateArgs(vec![TemplateArg::ArgPack(vec![])]),
+                        b"...",
+                        []
+                    }
+                    b"IS_XplT_LS_1EEE..." => {
+                        TemplateArgs(vec![
+                            TemplateArg::Type(TypeHandle::BackReference(0)),
+                            TemplateArg::Expression(Expression::Binary(
+                                OperatorName::Add,
+                                Box::new(Expression::TemplateParam(TemplateParam(0))),
+                                Box::new(Expression::Primary(
+                                    ExprPrimary::Literal(TypeHandle::BackReference(0),
+                                                         11,
+                                                         12))))),
+                        ]),
+                        b"...",
+                        []
+                    }
                 }
                 Err => {
                     b"zzz" => Error::UnexpectedText,
+                    // An empty list of template args is an empty pack, `IJEE`.
                     b"IE" => Error::UnexpectedText,
                     b"IS_" => Error::UnexpectedEnd,
                     b"I" => Error::UnexpectedEnd,
@@ -6171,11 +10220,46 @@ mod tests {
                         b"...",
                         []
                     }
+                    b"XdtT_1xE..." => {
+                        TemplateArg::Expression(Expression::Member(
+                            Box::new(Expression::TemplateParam(TemplateParam(0))),
+                            UnresolvedName::Name(
+                                BaseUnresolvedName::Name(
+                                    SimpleId(
+                                        SourceName(Identifier {
+                                            start: 6,
+                                            end: 7,
+                                        }),
+                                        None))))),
+                        b"...",
+                        []
+                    }
+                    b"XptT_1xE..." => {
+                        TemplateArg::Expression(Expression::DerefMember(
+                            Box::new(Expression::TemplateParam(TemplateParam(0))),
+                            UnresolvedName::Name(
+                                BaseUnresolvedName::Name(
+                                    SimpleId(
+                                        SourceName(Identifier {
+                                            start: 6,
+                                            end: 7,
+                                        }),
+                                        None))))),
+                        b"...",
+                        []
+                    }
                     b"JE..." => {
                         TemplateArg::ArgPack(vec![]),
                         b"...",
                         []
                     }
+                    b"IS_E..." => {
+                        TemplateArg::ArgPack(vec![
+                            TemplateArg::Type(TypeHandle::BackReference(0)),
+                        ]),
+                        b"...",
+                        []
+                    }
                     b"JS_XtrELS_EJEE..." => {
                         TemplateArg::ArgPack(vec![
                             TemplateArg::Type(TypeHandle::BackReference(0)),
@@ -6196,6 +10280,7 @@ mod tests {
                     b"JS_" => Error::UnexpectedEnd,
                     b"X" => Error::UnexpectedEnd,
                     b"J" => Error::UnexpectedEnd,
+                    b"IS_" => Error::UnexpectedEnd,
                     b"" => Error::UnexpectedEnd,
                 }
             }
@@ -6276,7 +10361,7 @@ mod tests {
                         b"...",
                         []
                     }
-                    b"clLS_1E..." => {
+                    b"clLS_1EE..." => {
                         Expression::Call(
                             Box::new(Expression::Primary(
                                 ExprPrimary::Literal(
@@ -6317,6 +10402,16 @@ mod tests {
                         b"...",
                         []
                     }
+                    b"cvS__E..." => {
+                        Expression::ConversionMany(TypeHandle::BackReference(0), vec![]),
+                        b"...",
+                        []
+                    }
+                    b"tlS_E..." => {
+                        Expression::ConversionBraced(TypeHandle::BackReference(0), vec![]),
+                        b"...",
+                        []
+                    }
                     b"tlS_LS_1ELS_1EE..." => {
                         Expression::ConversionBraced(
                             TypeHandle::BackReference(0),
@@ -6457,6 +10552,82 @@ mod tests {
                         b"...",
                         []
                     }
+                    b"gsnw_S_E..." => {
+                        Expression::GlobalNew(vec![], TypeHandle::BackReference(0), None),
+                        b"...",
+                        []
+                    }
+                    b"gsnw_S_piLS_1EE..." => {
+                        Expression::GlobalNew(
+                            vec![],
+                            TypeHandle::BackReference(0),
+                            Some(Initializer(vec![
+                                Expression::Primary(
+                                    ExprPrimary::Literal(
+                                        TypeHandle::BackReference(0),
+                                        12,
+                                        13))
+                            ]))),
+                        b"...",
+                        []
+                    }
+                    b"nw_S_E..." => {
+                        Expression::New(vec![], TypeHandle::BackReference(0), None),
+                        b"...",
+                        []
+                    }
+                    b"nw_S_piLS_1EE..." => {
+                        Expression::New(
+                            vec![],
+                            TypeHandle::BackReference(0),
+                            Some(Initializer(vec![
+                                Expression::Primary(
+                                    ExprPrimary::Literal(
+                                        TypeHandle::BackReference(0),
+                                        10,
+                                        11))
+                            ]))),
+                        b"...",
+                        []
+                    }
+                    b"gsna_S_E..." => {
+                        Expression::GlobalNewArray(vec![], TypeHandle::BackReference(0), None),
+                        b"...",
+                        []
+                    }
+                    b"gsna_S_piLS_1EE..." => {
+                        Expression::GlobalNewArray(
+                            vec![],
+                            TypeHandle::BackReference(0),
+                            Some(Initializer(vec![
+                                Expression::Primary(
+                                    ExprPrimary::Literal(
+                                        TypeHandle::BackReference(0),
+                                        12,
+                                        13))
+                            ]))),
+                        b"...",
+                        []
+                    }
+                    b"na_S_E..." => {
+                        Expression::NewArray(vec![], TypeHandle::BackReference(0), None),
+                        b"...",
+                        []
+                    }
+                    b"na_S_piLS_1EE..." => {
+                        Expression::NewArray(
+                            vec![],
+                            TypeHandle::BackReference(0),
+                            Some(Initializer(vec![
+                                Expression::Primary(
+                                    ExprPrimary::Literal(
+                                        TypeHandle::BackReference(0),
+                                        10,
+                                        11))
+                            ]))),
+                        b"...",
+                        []
+                    }
                     b"gsdlLS_1E..." => {
                         Expression::GlobalDelete(
                             Box::new(Expression::Primary(
@@ -6607,6 +10778,19 @@ mod tests {
                         b"...",
                         []
                     }
+                    b"gs3abc..." => {
+                        Expression::UnresolvedName(
+                            UnresolvedName::Global(
+                                BaseUnresolvedName::Name(
+                                    SimpleId(
+                                        SourceName(Identifier {
+                                            start: 3,
+                                            end: 6,
+                                        }),
+                                        None)))),
+                        b"...",
+                        []
+                    }
                     b"dtT_3abc..." => {
                         Expression::Member(
                             Box::new(Expression::TemplateParam(TemplateParam(0))),
@@ -6661,6 +10845,17 @@ mod tests {
                         b"...",
                         []
                     }
+                    b"sPIiEE..." => {
+                        Expression::SizeofCapturedTemplatePack(vec![
+                            TemplateArg::ArgPack(vec![
+                                TemplateArg::Type(
+                                    TypeHandle::Builtin(
+                                        BuiltinType::Standard(StandardBuiltinType::Int)))
+                            ])
+                        ]),
+                        b"...",
+                        []
+                    }
                     b"spT_..." => {
                         Expression::PackExpansion(
                             Box::new(Expression::TemplateParam(TemplateParam(0)))),
@@ -6677,36 +10872,114 @@ mod tests {
                         b"...",
                         []
                     }
-                    b"3abc..." => {
-                        Expression::UnresolvedName(
-                            UnresolvedName::Name(
-                                BaseUnresolvedName::Name(
-                                    SimpleId(
-                                        SourceName(Identifier {
-                                            start: 1,
-                                            end: 4,
-                                        }),
-                                        None)))),
+                    b"rqXT_TT_E..." => {
+                        Expression::Requires(vec![
+                            Requirement::Expression(
+                                Expression::TemplateParam(TemplateParam(0)),
+                                false,
+                                None),
+                            Requirement::Type(TypeHandle::BackReference(1)),
+                        ]),
                         b"...",
-                        []
+                        [
+                            Substitutable::Type(Type::TemplateParam(TemplateParam(0))),
+                        ]
                     }
-                    b"L_Z3abcE..." => {
-                        Expression::Primary(
-                            ExprPrimary::External(
-                                MangledName::Encoding(
-                                    Encoding::Data(
-                                        Name::Unscoped(
-                                            UnscopedName::Unqualified(
-                                                UnqualifiedName::Source(
-                                                    SourceName(Identifier {
-                                                        start: 4,
-                                                        end: 7,
-                                                    })))))))),
+                    b"rqXT_NR1CQtrE..." => {
+                        Expression::Requires(vec![
+                            Requirement::Expression(
+                                Expression::TemplateParam(TemplateParam(0)),
+                                true,
+                                Some(Name::Unscoped(
+                                    UnscopedName::Unqualified(
+                                        UnqualifiedName::Source(
+                                            SourceName(Identifier {
+                                                start: 8,
+                                                end: 9,
+                                            })))))),
+                            Requirement::Nested(Expression::Rethrow),
+                        ]),
                         b"...",
                         []
                     }
-                }
+                    b"rQT__XtrE..." => {
+                        Expression::RequiresWithParams(
+                            vec![TypeHandle::BackReference(1)],
+                            vec![Requirement::Expression(Expression::Rethrow, false, None)]),
+                        b"...",
+                        [
+                            Substitutable::Type(Type::TemplateParam(TemplateParam(0))),
+                        ]
+                    }
+                    b"clT_trtwT_E..." => {
+                        Expression::Call(
+                            Box::new(Expression::TemplateParam(TemplateParam(0))),
+                            vec![
+                                Expression::Rethrow,
+                                Expression::Throw(
+                                    Box::new(Expression::TemplateParam(TemplateParam(0)))),
+                            ]),
+                        b"...",
+                        []
+                    }
+                    b"u3fooiT_E..." => {
+                        Expression::VendorExtension(
+                            SourceName(Identifier {
+                                start: 2,
+                                end: 5,
+                            }),
+                            vec![
+                                TemplateArg::Type(
+                                    TypeHandle::Builtin(
+                                        BuiltinType::Standard(StandardBuiltinType::Int))),
+                                TemplateArg::Type(TypeHandle::BackReference(1)),
+                            ]),
+                        b"...",
+                        [
+                            Substitutable::Type(Type::TemplateParam(TemplateParam(0))),
+                        ]
+                    }
+                    b"cltwT_trE..." => {
+                        Expression::Call(
+                            Box::new(Expression::Throw(
+                                Box::new(Expression::TemplateParam(TemplateParam(0))))),
+                            vec![Expression::Rethrow]),
+                        b"...",
+                        []
+                    }
+                    b"3abc..." => {
+                        Expression::UnresolvedName(
+                            UnresolvedName::Name(
+                                BaseUnresolvedName::Name(
+                                    SimpleId(
+                                        SourceName(Identifier {
+                                            start: 1,
+                                            end: 4,
+                                        }),
+                                        None)))),
+                        b"...",
+                        []
+                    }
+                    b"L_Z3abcE..." => {
+                        Expression::Primary(
+                            ExprPrimary::External(
+                                MangledName::Encoding(
+                                    Encoding::Data(
+                                        Name::Unscoped(
+                                            UnscopedName::Unqualified(
+                                                UnqualifiedName::Source(
+                                                    SourceName(Identifier {
+                                                        start: 4,
+                                                        end: 7,
+                                                    })))))))),
+                        b"...",
+                        []
+                    }
+                }
                 Err => {
+                    b"rqE..." => Error::UnexpectedText,
+                    b"rqXtr" => Error::UnexpectedEnd,
+                    b"rQXtrE..." => Error::UnexpectedText,
                 }
             }
         });
@@ -6728,6 +11001,16 @@ mod tests {
                         b"...",
                         []
                     }
+                    b"gsonplIiE..." => {
+                        UnresolvedName::Global(BaseUnresolvedName::Operator(
+                            OperatorName::Add,
+                            Some(TemplateArgs(vec![
+                                TemplateArg::Type(TypeHandle::Builtin(
+                                    BuiltinType::Standard(StandardBuiltinType::Int))),
+                            ])))),
+                        b"...",
+                        []
+                    }
                     b"3abc..." => {
                         UnresolvedName::Name(BaseUnresolvedName::Name(SimpleId(SourceName(Identifier {
                             start: 1,
@@ -6806,6 +11089,8 @@ mod tests {
                     b"srN3abczzzz" => Error::UnexpectedText,
                     b"srN3abcE" => Error::UnexpectedText,
                     b"srN3abc" => Error::UnexpectedText,
+                    b"srNT_E3abc" => Error::UnexpectedText,
+                    b"srNT_" => Error::UnexpectedEnd,
                     b"srN" => Error::UnexpectedEnd,
                     b"sr" => Error::UnexpectedEnd,
                     b"gssr" => Error::UnexpectedEnd,
@@ -7041,9 +11326,31 @@ mod tests {
                         b"...",
                         []
                     }
+                    b"L_Z3fooiE..." => {
+                        ExprPrimary::External(
+                            MangledName::Encoding(
+                                Encoding::Function(
+                                    Name::Unscoped(
+                                        UnscopedName::Unqualified(
+                                            UnqualifiedName::Source(
+                                                SourceName(Identifier {
+                                                    start: 4,
+                                                    end: 7,
+                                                })))),
+                                    BareFunctionType(vec![
+                                        TypeHandle::Builtin(
+                                            BuiltinType::Standard(StandardBuiltinType::Int))
+                                    ]),
+                                    None))),
+                        b"...",
+                        []
+                    }
                 }
                 Err => {
                     b"zzz" => Error::UnexpectedText,
+                    b"L_Z3fooi" => Error::UnexpectedEnd,
+                    b"L_Z3fooi..." => Error::UnexpectedText,
+                    b"L_Z" => Error::UnexpectedEnd,
                     b"LS_zzz" => Error::UnexpectedEnd,
                     b"LS_12345" => Error::UnexpectedEnd,
                     b"LS_" => Error::UnexpectedEnd,
@@ -7211,15 +11518,44 @@ mod tests {
         assert_parse!(ClosureTypeName {
             Ok => {
                 b"UlvE_..." => {
-                    ClosureTypeName(LambdaSig(vec![]), None),
+                    ClosureTypeName(vec![], LambdaSig(vec![]), None),
                     b"..."
                 }
                 b"UlvE36_..." => {
-                    ClosureTypeName(LambdaSig(vec![]), Some(36)),
+                    ClosureTypeName(vec![], LambdaSig(vec![]), Some(36)),
+                    b"..."
+                }
+                b"UlTyiE_..." => {
+                    ClosureTypeName(vec![TemplateParamDecl::Type(0)],
+                                    LambdaSig(vec![
+                                        TypeHandle::Builtin(
+                                            BuiltinType::Standard(StandardBuiltinType::Int)),
+                                    ]),
+                                    None),
+                    b"..."
+                }
+                b"UlTyTniTpTyTtTyEvE0_..." => {
+                    ClosureTypeName(vec![
+                                        TemplateParamDecl::Type(0),
+                                        TemplateParamDecl::NonType(
+                                            0,
+                                            TypeHandle::Builtin(
+                                                BuiltinType::Standard(StandardBuiltinType::Int))),
+                                        TemplateParamDecl::Pack(
+                                            Box::new(TemplateParamDecl::Type(1))),
+                                        TemplateParamDecl::Template(
+                                            0,
+                                            vec![TemplateParamDecl::Type(2)]),
+                                    ],
+                                    LambdaSig(vec![]),
+                                    Some(0)),
                     b"..."
                 }
             }
             Err => {
+                b"UlTtTyvE_" => Error::UnexpectedText,
+                b"UlTnvE_" => Error::UnexpectedText,
+                b"UlTy" => Error::UnexpectedEnd,
                 b"UlvE36zzz" => Error::UnexpectedText,
                 b"UlvEzzz" => Error::UnexpectedText,
                 b"Ulvzzz" => Error::UnexpectedText,
@@ -7367,6 +11703,59 @@ mod tests {
                                         }))))))),
                     b"..."
                 }
+                b"Th8_3abc..." => {
+                    SpecialName::VirtualOverrideThunk(
+                        CallOffset::NonVirtual(NvOffset(8)),
+                        Box::new(Encoding::Data(
+                            Name::Unscoped(
+                                UnscopedName::Unqualified(
+                                    UnqualifiedName::Source(
+                                        SourceName(Identifier {
+                                            start: 5,
+                                            end: 8,
+                                        }))))))),
+                    b"..."
+                }
+                b"Thn8_3abc..." => {
+                    SpecialName::VirtualOverrideThunk(
+                        CallOffset::NonVirtual(NvOffset(-8)),
+                        Box::new(Encoding::Data(
+                            Name::Unscoped(
+                                UnscopedName::Unqualified(
+                                    UnqualifiedName::Source(
+                                        SourceName(Identifier {
+                                            start: 6,
+                                            end: 9,
+                                        }))))))),
+                    b"..."
+                }
+                b"Tv0_n8_3abc..." => {
+                    SpecialName::VirtualOverrideThunk(
+                        CallOffset::Virtual(VOffset(0, -8)),
+                        Box::new(Encoding::Data(
+                            Name::Unscoped(
+                                UnscopedName::Unqualified(
+                                    UnqualifiedName::Source(
+                                        SourceName(Identifier {
+                                            start: 8,
+                                            end: 11,
+                                        }))))))),
+                    b"..."
+                }
+                b"Tch0_h16_3abc..." => {
+                    SpecialName::VirtualOverrideThunkCovariant(
+                        CallOffset::NonVirtual(NvOffset(0)),
+                        CallOffset::NonVirtual(NvOffset(16)),
+                        Box::new(Encoding::Data(
+                            Name::Unscoped(
+                                UnscopedName::Unqualified(
+                                    UnqualifiedName::Source(
+                                        SourceName(Identifier {
+                                            start: 10,
+                                            end: 13,
+                                        }))))))),
+                    b"..."
+                }
                 b"Tcv42_36_v42_36_3abc..." => {
                     SpecialName::VirtualOverrideThunkCovariant(
                         CallOffset::Virtual(VOffset(42, 36)),
@@ -7422,6 +11811,9 @@ mod tests {
                 b"GZ" => Error::UnexpectedText,
                 b"GR3abcz" => Error::UnexpectedText,
                 b"GR3abc0z" => Error::UnexpectedText,
+                b"Th8z" => Error::UnexpectedText,
+                b"Tv0_n8z" => Error::UnexpectedText,
+                b"Tch0_" => Error::UnexpectedEnd,
                 b"T" => Error::UnexpectedEnd,
                 b"G" => Error::UnexpectedEnd,
                 b"" => Error::UnexpectedEnd,
@@ -7537,6 +11929,87 @@ mod tests {
         });
     }
 
+    #[test]
+    fn parse_data_member_prefix_within_prefix() {
+        // A <data-member-prefix> inside a <prefix> is parsed by
+        // `DataMemberPrefix::parse` itself, so both paths agree.
+        let input = IndexStr::new(b"3abc3defM...");
+
+        let mut subs = SubstitutionTable::new();
+        let (standalone, standalone_tail) =
+            DataMemberPrefix::parse(&mut subs, input.range_from(4..)).unwrap();
+
+        let mut subs = SubstitutionTable::new();
+        let (handle, prefix_tail) = PrefixHandle::parse(&mut subs, input).unwrap();
+        match subs.get_prefix(&handle) {
+            Some(&Prefix::DataMember(_, ref inline)) => assert_eq!(*inline, standalone),
+            otherwise => panic!("expected a data member prefix, found {:?}", otherwise),
+        }
+        assert!(prefix_tail == standalone_tail);
+
+        // Without the `M`, it's just another nested name.
+        let mut subs = SubstitutionTable::new();
+        let (handle, _) = PrefixHandle::parse(&mut subs, IndexStr::new(b"3abc3def...")).unwrap();
+        match subs.get_prefix(&handle) {
+            Some(&Prefix::Nested(..)) => {}
+            otherwise => panic!("expected a nested prefix, found {:?}", otherwise),
+        }
+    }
+
+    #[test]
+    fn parse_prefix_stopping_points() {
+        fn stop(input: &[u8]) -> Vec<u8> {
+            let mut subs = SubstitutionTable::new();
+            let (_, tail) = PrefixHandle::parse(&mut subs, IndexStr::new(input)).unwrap();
+            tail.as_ref().to_vec()
+        }
+
+        // The `E` of the enclosing <nested-name> ends the prefix.
+        assert_eq!(stop(b"3foo3barE"), b"E");
+        assert_eq!(stop(b"3fooIiEE"), b"E");
+
+        // So does any other byte that cannot continue it, leaving the caller
+        // to reject what is left.
+        assert_eq!(stop(b"3fooZ3bar"), b"Z3bar");
+        assert_eq!(stop(b"3fooIiEIiE"), b"IiE");
+        assert_eq!(stop(b"3foo"), b"");
+
+        // Without any components parsed there is no prefix at all.
+        let mut subs = SubstitutionTable::new();
+        assert_eq!(PrefixHandle::parse(&mut subs, IndexStr::new(b"Z3foo")),
+                   Err(Error::UnexpectedText));
+
+        // Within a <nested-name>, an early stop surfaces as an error rather
+        // than as a silently truncated name.
+        let mut subs = SubstitutionTable::new();
+        assert_eq!(NestedName::parse(&mut subs, IndexStr::new(b"N3fooZ3barE")),
+                   Err(Error::UnexpectedText));
+        let mut subs = SubstitutionTable::new();
+        assert_eq!(NestedName::parse(&mut subs, IndexStr::new(b"N1AIiEIiEE")),
+                   Err(Error::UnexpectedText));
+    }
+
+    #[test]
+    fn parse_nested_name_last_component_is_not_a_substitution() {
+        let input = b"N1a1bIiEE...";
+        let mut subs = SubstitutionTable::new();
+        let (name, _) = NestedName::parse(&mut subs, IndexStr::new(input)).unwrap();
+
+        // `a` and the template `a::b` are substitution candidates, but the
+        // whole `a::b<int>` is not.
+        assert_eq!(subs.len(), 2);
+        assert_eq!(name.2, PrefixHandle::NonSubstitution(NonSubstitution(0)));
+        match subs.get_prefix(&name.2) {
+            Some(&Prefix::Template(PrefixHandle::BackReference(1), _)) => {}
+            otherwise => panic!("expected a template prefix, found {:?}", otherwise),
+        }
+
+        // So later back references skip over it.
+        assert_demangle_symbol(b"_ZN1a1bEN1c1dES0_", "a::b(c::d, c)");
+        assert_demangle_symbol(b"_ZN1a1bEN1c1dES1_", "a::b(c::d, c::d)");
+        assert_demangle_symbol(b"_ZN1AIiE1fEPS0_", "A<int>::f(A<int>*)");
+    }
+
     #[test]
     fn parse_ref_qualifier() {
         assert_parse!(RefQualifier {
@@ -7600,6 +12073,23 @@ mod tests {
         });
     }
 
+    #[test]
+    fn standard_builtin_type_vocabulary() {
+        let vocabulary = StandardBuiltinType::vocabulary();
+        assert_eq!(vocabulary.len(), 31);
+        assert_eq!(vocabulary[0], (StandardBuiltinType::Void, &b"v"[..], "void"));
+        assert!(vocabulary.contains(&(StandardBuiltinType::Nullptr, &b"Dn"[..], "std::nullptr_t")));
+
+        // Every entry parses back to its own variant.
+        for &(ref ty, mangled, _) in vocabulary {
+            let mut subs = SubstitutionTable::new();
+            let (parsed, tail) = StandardBuiltinType::parse(&mut subs, IndexStr::new(mangled))
+                .unwrap();
+            assert_eq!(parsed, *ty);
+            assert!(tail.is_empty());
+        }
+    }
+
     #[test]
     fn parse_builtin_type() {
         assert_parse!(BuiltinType {
@@ -7619,8 +12109,92 @@ mod tests {
                     })),
                     b"..."
                 }
+                b"Dk1C..." => {
+                    BuiltinType::ConstrainedAuto(
+                        Name::Unscoped(
+                            UnscopedName::Unqualified(
+                                UnqualifiedName::Source(
+                                    SourceName(Identifier {
+                                        start: 3,
+                                        end: 4,
+                                    }))))),
+                    b"..."
+                }
+                b"DK1C..." => {
+                    BuiltinType::ConstrainedDecltypeAuto(
+                        Name::Unscoped(
+                            UnscopedName::Unqualified(
+                                UnqualifiedName::Source(
+                                    SourceName(Identifier {
+                                        start: 3,
+                                        end: 4,
+                                    }))))),
+                    b"..."
+                }
+                b"DF16i15n..." => {
+                    BuiltinType::FixedPoint(FixedPointType {
+                        integral_bits: Some(16),
+                        length: StandardBuiltinType::Int,
+                        fractional_bits: 15,
+                        saturating: false,
+                    }),
+                    b"..."
+                }
+                b"DFs7s..." => {
+                    BuiltinType::FixedPoint(FixedPointType {
+                        integral_bits: None,
+                        length: StandardBuiltinType::Short,
+                        fractional_bits: 7,
+                        saturating: true,
+                    }),
+                    b"..."
+                }
+                b"DF16_..." => {
+                    BuiltinType::FloatN(16),
+                    b"..."
+                }
+            }
+            Err => {
+                b"." => Error::UnexpectedText,
+                b"DFs7" => Error::UnexpectedText,
+                b"DFs7x" => Error::UnexpectedText,
+                b"Dk" => Error::UnexpectedEnd,
+                b"D" => Error::UnexpectedEnd,
+                b"Dz" => Error::UnexpectedText,
+                b"" => Error::UnexpectedEnd,
+            }
+        });
+    }
+
+    #[test]
+    fn parse_standard_builtin_type() {
+        assert_parse!(StandardBuiltinType {
+            Ok => {
+                b"i..." => {
+                    StandardBuiltinType::Int,
+                    b"..."
+                }
+                b"Dn..." => {
+                    StandardBuiltinType::Nullptr,
+                    b"..."
+                }
+                b"Dh..." => {
+                    StandardBuiltinType::Half,
+                    b"..."
+                }
+                b"DF16b..." => {
+                    StandardBuiltinType::BFloat16,
+                    b"..."
+                }
             }
             Err => {
+                b"DF16" => Error::UnexpectedEnd,
+                // `_FloatN` takes a number, so `BuiltinType` parses it.
+                b"DF16_" => Error::UnexpectedText,
+                // `D` is the only first byte shared by multi-byte codes.
+                b"D" => Error::UnexpectedEnd,
+                b"Dz" => Error::UnexpectedText,
+                b"Dzi" => Error::UnexpectedText,
                 b"." => Error::UnexpectedText,
                 b"" => Error::UnexpectedEnd,
             }
@@ -7701,10 +12275,31 @@ mod tests {
                     UnqualifiedName::UnnamedType(UnnamedTypeName(Some(5))),
                     b"..."
                 }
+                b"L3foo..." => {
+                    UnqualifiedName::LocalSourceName(
+                        SourceName(Identifier {
+                            start: 2,
+                            end: 5,
+                        }),
+                        None),
+                    b"..."
+                }
+                b"L3foo_0..." => {
+                    UnqualifiedName::LocalSourceName(
+                        SourceName(Identifier {
+                            start: 2,
+                            end: 5,
+                        }),
+                        Some(Discriminator(0))),
+                    b"..."
+                }
             }
             Err => {
                 b"zzz" => Error::UnexpectedText,
                 b"C" => Error::UnexpectedEnd,
+                b"L" => Error::UnexpectedEnd,
+                b"L3fo" => Error::UnexpectedEnd,
+                b"Lzzz" => Error::UnexpectedText,
                 b"" => Error::UnexpectedEnd,
             }
         });
@@ -7745,13 +12340,21 @@ mod tests {
                     Identifier { start: 0, end: 4 },
                     b""
                 }
-                b"_Az1..." => {
+                b"_Az1 ..." => {
                     Identifier { start: 0, end: 4 },
-                    b"..."
+                    b" ..."
+                }
+                b"$_0.constprop.0 ..." => {
+                    Identifier { start: 0, end: 15 },
+                    b" ..."
+                }
+                b"caf\xc3\xa9 ..." => {
+                    Identifier { start: 0, end: 5 },
+                    b" ..."
                 }
             }
             Err => {
-                b"..." => Error::UnexpectedText,
+                b" ..." => Error::UnexpectedText,
                 b"" => Error::UnexpectedEnd,
             }
         });
@@ -7769,6 +12372,14 @@ mod tests {
                     SourceName(Identifier { start: 2, end: 12 }),
                     b"klm"
                 }
+                b"3$_0i" => {
+                    SourceName(Identifier { start: 1, end: 4 }),
+                    b"i"
+                }
+                b"5a.b$ci" => {
+                    SourceName(Identifier { start: 1, end: 6 }),
+                    b"i"
+                }
             }
             Err => {
                 b"0abc" => Error::UnexpectedText,
@@ -7934,6 +12545,14 @@ mod tests {
                     CtorDtorName::CompleteConstructor,
                     b"01"
                 }
+                b"C4" => {
+                    CtorDtorName::MaybeInChargeConstructor,
+                    b""
+                }
+                b"D4Ev" => {
+                    CtorDtorName::MaybeInChargeDestructor,
+                    b"Ev"
+                }
             }
             Err => {
                 b"gayagaya" => Error::UnexpectedText,
@@ -7958,7 +12577,34 @@ mod tests {
             }
             Err => {
                 b"bu-buuuu" => Error::UnexpectedText,
+                b"a" => Error::UnexpectedEnd,
+                b"az" => Error::UnexpectedText,
+                b"c" => Error::UnexpectedEnd,
+                b"cz" => Error::UnexpectedText,
+                b"d" => Error::UnexpectedEnd,
+                b"dz" => Error::UnexpectedText,
+                b"e" => Error::UnexpectedEnd,
+                b"ez" => Error::UnexpectedText,
+                b"g" => Error::UnexpectedEnd,
+                b"gz" => Error::UnexpectedText,
+                b"i" => Error::UnexpectedEnd,
+                b"iz" => Error::UnexpectedText,
+                b"l" => Error::UnexpectedEnd,
+                b"lz" => Error::UnexpectedText,
+                b"m" => Error::UnexpectedEnd,
+                b"mz" => Error::UnexpectedText,
+                b"n" => Error::UnexpectedEnd,
+                b"nz" => Error::UnexpectedText,
+                b"o" => Error::UnexpectedEnd,
+                b"oz" => Error::UnexpectedText,
+                b"p" => Error::UnexpectedEnd,
+                b"pz" => Error::UnexpectedText,
                 b"q" => Error::UnexpectedEnd,
+                b"qz" => Error::UnexpectedText,
+                b"r" => Error::UnexpectedEnd,
+                b"rz" => Error::UnexpectedText,
+                b"s" => Error::UnexpectedText,
+                b"v" => Error::UnexpectedText,
                 b"" => Error::UnexpectedEnd,
             }
         });
@@ -8007,4 +12653,1558 @@ but found   "{}"."#,
     fn demangle_well_known_component() {
         assert_demangle("Sa", [], WellKnownComponent::StdAllocator, "std::allocator");
     }
+
+    fn assert_demangle_symbol(mangled: &[u8], expected: &str) {
+        let sym = Symbol::new(mangled).expect("should parse mangled symbol");
+        assert_eq!(format!("{}", sym), expected);
+    }
+
+    fn demangle_with(mangled: &[u8], options: DemangleOptions) -> String {
+        Symbol::new(mangled)
+            .expect("should parse mangled symbol")
+            .demangle_with_options(options)
+            .expect("should demangle")
+    }
+
+    #[test]
+    fn demangle_context_with_options() {
+        let input = b"_Z1fI1AI1BIiEEEvSiT_";
+        let mut subs = SubstitutionTable::new();
+        let (parsed, tail) = MangledName::parse(&mut subs, IndexStr::from(&input[..]))
+            .expect("should parse mangled symbol");
+        assert!(tail.is_empty());
+
+        let mut buf: Vec<u8> = vec![];
+        {
+            let mut ctx = DemangleContext::new(&subs, input, &mut buf);
+            parsed.demangle(&mut ctx, None).unwrap();
+        }
+        assert_eq!(String::from_utf8(buf).unwrap(),
+                   "void f<A<B<int> > >(std::istream, A<B<int> >)");
+
+        let options = DemangleOptions {
+            max_template_depth: Some(1),
+            flavor: Flavor::Llvm,
+            ..Default::default()
+        };
+        let mut buf: Vec<u8> = vec![];
+        {
+            let mut ctx = DemangleContext::new_with_options(&subs, input, &mut buf, options);
+            parsed.demangle(&mut ctx, None).unwrap();
+        }
+        assert_eq!(String::from_utf8(buf).unwrap(),
+                   "void f<A<...> >(std::istream, A<B<...> >)");
+    }
+
+    #[test]
+    fn new_back_reference() {
+        // Each insertion is numbered after every entry before it.
+        let mut subs = SubstitutionTable::new();
+        for expected in 0..3 {
+            let idx = subs.insert(Substitutable::Type(Type::PointerTo(TypeHandle::Builtin(
+                    BuiltinType::Standard(StandardBuiltinType::Int)))))
+                .unwrap();
+            assert_eq!(idx, expected);
+            assert_eq!(TypeHandle::new_back_reference(&subs, idx),
+                       TypeHandle::BackReference(expected));
+        }
+
+        // So parsing `space::foo(space::Bar*, space::Bar*)` numbers `space`
+        // as `S_`, `space::Bar` as `S0_` and `space::Bar*` as `S1_`, and each
+        // back reference refers to an entry that was parsed before it.
+        let mut subs = SubstitutionTable::new();
+        let (_, tail) = MangledName::parse(&mut subs,
+                                           IndexStr::new(b"_ZN5space3fooEPNS_3BarES1_"))
+            .unwrap();
+        assert!(tail.is_empty());
+        assert_eq!(subs.len(), 3);
+        match subs[1] {
+            Substitutable::Type(Type::ClassEnum(ClassEnumType::Named(Name::Nested(
+                NestedName(_, _, PrefixHandle::NonSubstitution(_)))))) => {}
+            ref otherwise => panic!("unexpected S0_: {:?}", otherwise),
+        }
+        assert_eq!(subs[2],
+                   Substitutable::Type(Type::PointerTo(TypeHandle::BackReference(1))));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "out of bounds of the substitution table")]
+    fn new_back_reference_out_of_bounds() {
+        let subs = SubstitutionTable::new();
+        PrefixHandle::new_back_reference(&subs, 0);
+    }
+
+    #[test]
+    fn subs_typed_accessors() {
+        let name = UnscopedTemplateName(
+            UnscopedName::Unqualified(UnqualifiedName::Operator(OperatorName::New)));
+        let prefix = Prefix::Unqualified(UnqualifiedName::Operator(OperatorName::New));
+        let ttp = TemplateTemplateParam(TemplateParam(0));
+        let unresolved = UnresolvedType::Template(TemplateParam(1), None);
+        let ty = Type::PointerTo(TypeHandle::Builtin(
+            BuiltinType::Standard(StandardBuiltinType::Int)));
+
+        let subs: SubstitutionTable = vec![
+            Substitutable::UnscopedTemplateName(name.clone()),
+            Substitutable::Prefix(prefix.clone()),
+            Substitutable::TemplateTemplateParam(ttp.clone()),
+            Substitutable::UnresolvedType(unresolved.clone()),
+            Substitutable::Type(ty.clone()),
+        ].into_iter().collect();
+
+        assert_eq!(subs.get_unscoped_template_name(
+                       &UnscopedTemplateNameHandle::BackReference(0)),
+                   Some(&name));
+        assert_eq!(subs.get_prefix(&PrefixHandle::BackReference(1)), Some(&prefix));
+        assert_eq!(subs.get_template_template_param(
+                       &TemplateTemplateParamHandle::BackReference(2)),
+                   Some(&ttp));
+        assert_eq!(subs.get_unresolved_type(&UnresolvedTypeHandle::BackReference(3)),
+                   Some(&unresolved));
+        assert_eq!(subs.get_type(&TypeHandle::BackReference(4)), Some(&ty));
+
+        // Entries of the wrong kind.
+        assert_eq!(subs.get_unscoped_template_name(
+                       &UnscopedTemplateNameHandle::BackReference(1)),
+                   None);
+        assert_eq!(subs.get_prefix(&PrefixHandle::BackReference(0)), None);
+        assert_eq!(subs.get_template_template_param(
+                       &TemplateTemplateParamHandle::BackReference(3)),
+                   None);
+        assert_eq!(subs.get_unresolved_type(&UnresolvedTypeHandle::BackReference(2)),
+                   None);
+
+        // Out of bounds.
+        assert_eq!(subs.get_prefix(&PrefixHandle::BackReference(5)), None);
+
+        // Handles that do not refer into the table.
+        assert_eq!(subs.get_prefix(&PrefixHandle::WellKnown(WellKnownComponent::Std)),
+                   None);
+    }
+
+    #[test]
+    fn demangle_operator_name_spacing() {
+        assert_demangle_symbol(b"_ZN3foonwEj", "foo::operator new(unsigned int)");
+        assert_demangle_symbol(b"_ZN3foonaEj", "foo::operator new[](unsigned int)");
+        assert_demangle_symbol(b"_ZN3foodlEPv", "foo::operator delete(void*)");
+        assert_demangle_symbol(b"_ZN3foodaEPv", "foo::operator delete[](void*)");
+        assert_demangle_symbol(b"_ZN3fooplERKS_", "foo::operator+(foo const&)");
+    }
+
+    #[test]
+    fn demangle_std_names_without_duplicate_std() {
+        assert_demangle_symbol(b"_ZStL19piecewise_construct", "std::piecewise_construct");
+        assert_demangle_symbol(b"_ZSt3fooIiEvv", "void std::foo<int>()");
+        assert_demangle_symbol(b"_ZSt3fooISt3barEvS0_",
+                               "void std::foo<std::bar>(std::bar)");
+        assert_demangle_symbol(b"_ZSt4swapISt3barEvRS0_S1_",
+                               "void std::swap<std::bar>(std::bar&, std::bar&)");
+        assert_demangle_symbol(b"_ZNSt3foo3barIS_EEvv",
+                               "void std::foo::bar<std::foo>()");
+        assert_demangle_symbol(b"_ZNSt6vectorIiE4sizeEv", "std::vector<int>::size()");
+    }
+
+    #[test]
+    fn demangle_array_dimension_expression() {
+        assert_demangle_symbol(b"_Z1fPAtr_i", "f(int (*) [throw])");
+        assert_demangle_symbol(b"_Z1fILi3EEvPAT__i", "void f<3>(int (*) [3])");
+        assert_demangle_symbol(b"_Z1fILi3EEvAT__i", "void f<3>(int [3])");
+        assert_demangle_symbol(b"_Z1fIiEvRAstT__i", "void f<int>(int (&) [sizeof (int)])");
+        assert_demangle_symbol(b"_Z1fILi3EEvPAplT_Li1E_i", "void f<3>(int (*) [(3)+(1)])");
+        assert_demangle_symbol(b"_Z1fPALin1E_i", "f(int (*) [-1])");
+    }
+
+    #[test]
+    fn demangle_negative_literal() {
+        assert_demangle_symbol(b"_Z1fILin1EEvv", "void f<-1>()");
+    }
+
+    #[test]
+    fn demangle_new_expressions() {
+        assert_demangle_symbol(b"_Z1fIiEvP1AIXsznw_T_EEE",
+                               "void f<int>(A<sizeof (new int)>*)");
+        assert_demangle_symbol(b"_Z1fIiEvP1AIXszgsnw_T_piEEE",
+                               "void f<int>(A<sizeof (::new int())>*)");
+        assert_demangle_symbol(b"_Z1fIiEvP1AIXszna_T_piLi1EEEE",
+                               "void f<int>(A<sizeof (new int(1))>*)");
+        assert_demangle_symbol(b"_Z1fIiEvP1AIXszgsna_T_EEE",
+                               "void f<int>(A<sizeof (::new int)>*)");
+        assert_demangle_symbol(b"_Z1fIiEvP1AIXsznwLi1ELi2E_T_piLi3EEEE",
+                               "void f<int>(A<sizeof (new (1, 2) int(3))>*)");
+        assert_demangle_symbol(b"_Z1fIiEvP1AIXszgsnaLi1E_T_EEE",
+                               "void f<int>(A<sizeof (::new (1) int)>*)");
+
+        // Placement args and the initializer each get their own parentheses,
+        // with the initializer's directly after the type, like libiberty.
+        assert_demangle_symbol(b"_Z1fIiEvDTnwL_Z1pE_T_EE",
+                               "void f<int>(decltype (new (p) int))");
+        assert_demangle_symbol(b"_Z1fIiEvDTnwL_Z1pE_T_piLi1ELi2EEE",
+                               "void f<int>(decltype (new (p) int(1, 2)))");
+        assert_demangle_symbol(b"_Z1fIiEvDTnwL_Z1pEL_Z1qE_T_piL_Z1aEL_Z1bEEE",
+                               "void f<int>(decltype (new (p, q) int(a, b)))");
+    }
+
+    #[test]
+    fn demangle_complex_spelling() {
+        let c = DemangleOptions {
+            complex_spelling: ComplexSpelling::C,
+            ..Default::default()
+        };
+        let gnu = DemangleOptions {
+            complex_spelling: ComplexSpelling::Gnu,
+            ..Default::default()
+        };
+
+        assert_demangle_symbol(b"_Z1fCf", "f(float _Complex)");
+        assert_demangle_symbol(b"_Z1fGf", "f(float _Imaginary)");
+        assert_eq!(demangle_with(b"_Z1fCf", c.clone()), "f(float _Complex)");
+        assert_eq!(demangle_with(b"_Z1fGf", c.clone()), "f(float _Imaginary)");
+        assert_eq!(demangle_with(b"_Z1fCf", gnu.clone()), "f(float __complex__)");
+        assert_eq!(demangle_with(b"_Z1fGf", gnu.clone()), "f(float __imaginary__)");
+        assert_eq!(demangle_with(b"_Z1fPCd", gnu.clone()), "f(double __complex__*)");
+    }
+
+    #[test]
+    fn demangle_conversions() {
+        assert_demangle_symbol(b"_Z1fIiEvP1AIXcvT_Li1EEE", "void f<int>(A<int(1)>*)");
+        assert_demangle_symbol(b"_Z1fIiEvP1AIXcvT__Li1ELi2EEEE",
+                               "void f<int>(A<int(1, 2)>*)");
+        assert_demangle_symbol(b"_Z1fIiEvP1AIXcvT__EEE", "void f<int>(A<int()>*)");
+
+        // The target type is resolved against the innermost template args.
+        assert_demangle_symbol(b"_Z1fIicEvP1AIXcvT0_Li1EEE",
+                               "void f<int, char>(A<char(1)>*)");
+        assert_demangle_symbol(b"_Z1fIPiEv1AIXcvT_Li0EEE", "void f<int*>(A<int*(0)>)");
+        assert_demangle_symbol(b"_ZN1BIlE1fIiEEvP1AIXcvT_Li1EEE",
+                               "void B<long>::f<int>(A<int(1)>*)");
+        assert_demangle_symbol(b"_Z1fIiEvP1AIXtlT_Li1EEEE", "void f<int>(A<int{1}>*)");
+        assert_demangle_symbol(b"_Z1fIiEvP1AIXtlT_Li1ELi2EEEE",
+                               "void f<int>(A<int{1, 2}>*)");
+        assert_demangle_symbol(b"_Z1fIiEvP1AIXtlT_EEE", "void f<int>(A<int{}>*)");
+    }
+
+    #[test]
+    fn demangle_template_arg_expression_referencing_sibling() {
+        assert_demangle_symbol(b"_Z1fIiXplT_Li1EEEvv", "void f<int, (int)+(1)>()");
+        assert_demangle_symbol(b"_Z1fIiXplT_Li1EEEvT_", "void f<int, (int)+(1)>(int)");
+        assert_demangle_symbol(b"_Z1fIiXstT_EEvv", "void f<int, sizeof (int)>()");
+        // The referenced sibling may come after the expression.
+        assert_demangle_symbol(b"_Z1fIXstT0_EiEvv", "void f<sizeof (int), int>()");
+        assert_demangle_symbol(b"_Z1fIiLi2EXplT0_Li1EEEvv", "void f<int, 2, (2)+(1)>()");
+    }
+
+    #[test]
+    fn demangle_type_param_resolving_to_non_type_arg() {
+        // Like libiberty, substitute the argument verbatim where a type was
+        // expected.
+        assert_demangle_symbol(b"_Z1fILi1EEvT_", "void f<1>(1)");
+        assert_demangle_symbol(b"_Z1fILi1EEvPT_", "void f<1>(1*)");
+        assert_demangle_symbol(b"_Z1fIXplLi1ELi2EEEvRKT_",
+                               "void f<(1)+(2)>((1)+(2) const&)");
+        assert_demangle_symbol(b"_Z1fIL_Z1xEEvT_", "void f<x>(x)");
+        assert_demangle_symbol(b"_Z1fILi1EEvPFT_vE", "void f<1>(1 (*)())");
+    }
+
+    #[test]
+    fn demangle_special_names() {
+        assert_demangle_symbol(b"_ZTV3Foo", "vtable for Foo");
+        assert_demangle_symbol(b"_ZTT3Foo", "VTT for Foo");
+        assert_demangle_symbol(b"_ZTI3Foo", "typeinfo for Foo");
+        assert_demangle_symbol(b"_ZTS3Foo", "typeinfo name for Foo");
+        assert_demangle_symbol(b"_ZTIN5space3FooIiEE", "typeinfo for space::Foo<int>");
+
+        // Function and array types read as they would in a declaration
+        // without a declarator.
+        assert_demangle_symbol(b"_ZTIFvvE", "typeinfo for void ()");
+        assert_demangle_symbol(b"_ZTSFivE", "typeinfo name for int ()");
+        assert_demangle_symbol(b"_ZTIPFviE", "typeinfo for void (*)(int)");
+        assert_demangle_symbol(b"_ZTIA10_i", "typeinfo for int [10]");
+        assert_demangle_symbol(b"_ZTIA10_A2_i", "typeinfo for int [10][2]");
+        assert_demangle_symbol(b"_ZTIPA10_A2_i", "typeinfo for int (*) [10][2]");
+    }
+
+    #[test]
+    fn demangle_global_ctor_dtor() {
+        assert_demangle_symbol(b"_GLOBAL__sub_I_main", "global constructors keyed to main");
+        assert_demangle_symbol(b"_GLOBAL__sub_D_foo.cpp",
+                               "global destructors keyed to foo.cpp");
+        assert_demangle_symbol(b"_GLOBAL__I__Z2fnv", "global constructors keyed to fn()");
+        assert_demangle_symbol(b"_GLOBAL_$D_main", "global destructors keyed to main");
+        assert_demangle_symbol(b"_GLOBAL__sub_I__ZN5space3fooEv",
+                               "global constructors keyed to space::foo()");
+    }
+
+    #[test]
+    fn demangle_internal_linkage() {
+        assert_demangle_symbol(b"_ZL3foov", "foo()");
+        assert_demangle_symbol(b"_ZL3foo_0", "foo");
+        assert_demangle_symbol(b"_ZN1AL3fooEv", "A::foo()");
+        assert_demangle_symbol(b"_ZZL3foovE1x", "foo()::x");
+        assert_demangle_symbol(b"_ZL3barIiEvT_", "void bar<int>(int)");
+    }
+
+    #[test]
+    fn demangle_pointer_to_data_member() {
+        assert_demangle_symbol(b"_Z1fM3Fooi", "f(int Foo::*)");
+        assert_demangle_symbol(b"_Z1fM3FooKi", "f(int const Foo::*)");
+        assert_demangle_symbol(b"_Z1fM3FooIiEi", "f(int Foo<int>::*)");
+        assert_demangle_symbol(b"_Z1fPM3Fooi", "f(int Foo::**)");
+    }
+
+    #[test]
+    fn demangle_pointer_to_array_member() {
+        assert_demangle_symbol(b"_Z1fM3FooA10_i", "f(int (Foo::*) [10])");
+        assert_demangle_symbol(b"_Z1fM3FooA_i", "f(int (Foo::*) [])");
+    }
+
+    #[test]
+    fn demangle_identifiers_with_dollar_and_dot() {
+        assert_demangle_symbol(b"_ZZ4mainEN3$_0clEv", "main::$_0::operator()()");
+        assert_demangle_symbol(b"_Z14foo.constprop1i", "foo.constprop1(int)");
+    }
+
+    #[test]
+    fn demangle_sizeof_packs() {
+        // When the pack is bound, print its length, like libiberty does.
+        assert_demangle_symbol(b"_Z1fIJidEEv1AIXsZT_EE", "void f<int, double>(A<2>)");
+        assert_demangle_symbol(b"_Z1fIJEEv1AIXsZT_EE", "void f<>(A<0>)");
+        assert_demangle_symbol(b"_Z1fIJidEEv1AIXsPIiEEEE", "void f<int, double>(A<1>)");
+        assert_demangle_symbol(b"_Z1fIJidEEv1AIXsPiDpT_EEE", "void f<int, double>(A<3>)");
+
+        // Otherwise, print `sizeof...` with the pack's contents.
+        assert_demangle_symbol(b"_Z1fIiEv1AIXsZT_EE", "void f<int>(A<sizeof...(int)>)");
+    }
+
+    #[test]
+    fn demangle_return_type() {
+        fn ret(mangled: &[u8]) -> Option<String> {
+            Symbol::new(mangled)
+                .expect("should parse mangled symbol")
+                .demangle_return_type(Default::default())
+                .expect("should demangle return type")
+        }
+
+        assert_eq!(ret(b"_Z1fIiEvT_"), Some("void".into()));
+        assert_eq!(ret(b"_Z1fIiET_S0_"), Some("int".into()));
+        assert_eq!(ret(b"_ZN5space3fooIiEEPKcT_"), Some("char const*".into()));
+
+        assert_eq!(ret(b"_Z3fooi"), None);
+        assert_eq!(ret(b"_ZN5space3fooEibc"), None);
+        assert_eq!(ret(b"_ZN3FooIiE3barEv"), None);
+        assert_eq!(ret(b"_ZN3FooC1IiEET_"), None);
+        assert_eq!(ret(b"_ZTV3Foo"), None);
+    }
+
+    #[test]
+    fn demangle_template_args() {
+        fn args(mangled: &[u8]) -> Option<String> {
+            Symbol::new(mangled)
+                .expect("should parse mangled symbol")
+                .demangle_template_args(Default::default())
+                .expect("should demangle template args")
+        }
+
+        assert_eq!(args(b"_Z3fooIicEvv"), Some("<int, char>".into()));
+        assert_eq!(args(b"_ZN5space3fooIiEEPKcT_"), Some("<int>".into()));
+        assert_eq!(args(b"_ZN3FooIiE3barIcEEvv"), Some("<char>".into()));
+        assert_eq!(args(b"_Z1xIiE"), Some("<int>".into()));
+
+        assert_eq!(args(b"_Z3fooi"), None);
+        assert_eq!(args(b"_ZN3FooIiE3barEv"), None);
+        assert_eq!(args(b"_ZTV3FooIiE"), None);
+
+        let options = DemangleOptions {
+            max_template_depth: Some(1),
+            ..Default::default()
+        };
+        let sym = Symbol::new(&b"_Z1fI1AI1BIiEEEvv"[..])
+            .expect("should parse mangled symbol");
+        assert_eq!(sym.demangle_template_args(options).unwrap(),
+                   Some("<A<...> >".into()));
+    }
+
+    #[test]
+    fn demangle_enclosing_function() {
+        fn enclosing(mangled: &[u8]) -> Option<String> {
+            Symbol::new(mangled)
+                .expect("should parse mangled symbol")
+                .demangle_enclosing_function(Default::default())
+                .expect("should demangle enclosing function")
+        }
+
+        assert_eq!(enclosing(b"_ZZ4mainE5count"), Some("main".into()));
+        assert_eq!(enclosing(b"_ZZ4mainvE5count"), Some("main()".into()));
+        assert_eq!(enclosing(b"_ZZN5space3fooEibcE3bar"),
+                   Some("space::foo(int, bool, char)".into()));
+        assert_eq!(enclosing(b"_ZZ1fIiEvvE1x"), Some("void f<int>()".into()));
+        assert_eq!(enclosing(b"_ZZZ1fvE1gvE1x"), Some("f()::g()".into()));
+        assert_eq!(enclosing(b"_ZZ1fvEN1A1gEv"), Some("f()".into()));
+
+        assert_eq!(enclosing(b"_Z4mainv"), None);
+        assert_eq!(enclosing(b"_ZTV3Foo"), None);
+    }
+
+    #[test]
+    fn local_name_enclosing_encoding() {
+        let mut subs = SubstitutionTable::new();
+        let input = IndexStr::from(&b"Z4mainvE5count"[..]);
+        let (local, _) = LocalName::parse(&mut subs, input).expect("should parse local name");
+        assert_eq!(*local.enclosing_encoding(),
+                   Encoding::Function(
+                       Name::Unscoped(
+                           UnscopedName::Unqualified(
+                               UnqualifiedName::Source(
+                                   SourceName(Identifier {
+                                       start: 2,
+                                       end: 6,
+                                   })))),
+                       BareFunctionType(vec![
+                           TypeHandle::Builtin(BuiltinType::Standard(StandardBuiltinType::Void))
+                       ]),
+                       None));
+    }
+
+    #[test]
+    fn demangle_template_constructor_has_no_return_type() {
+        // The first type in the bare function type is the constructor's
+        // parameter, not its return type.
+        assert_demangle_symbol(b"_ZN3FooC1IiEET_",
+                               "Foo::complete object constructor<int>(int)");
+    }
+
+    #[test]
+    fn demangle_default_arg_local_names() {
+        assert_demangle_symbol(b"_ZZ1fiEd_3foo", "f(int)::{default arg#1}::foo");
+        assert_demangle_symbol(b"_ZZ1fiiEd0_3foo", "f(int, int)::{default arg#2}::foo");
+        assert_demangle_symbol(b"_ZZN1S1fEiiEd1_N1A1gEv",
+                               "S::f(int, int)::{default arg#3}::A::g()");
+    }
+
+    #[test]
+    fn semantic_eq() {
+        fn semantic_eq(lhs: &[u8], rhs: &[u8]) -> bool {
+            let lhs = Symbol::new(lhs).expect("should parse mangled symbol");
+            let rhs = Symbol::new(rhs).expect("should parse mangled symbol");
+            let eq = lhs.semantic_eq(&rhs);
+            assert_eq!(eq, rhs.semantic_eq(&lhs));
+            eq
+        }
+
+        // The same type, with and without substitutions.
+        assert!(semantic_eq(b"_Z1fP3FooS0_", b"_Z1fP3FooP3Foo"));
+        assert!(semantic_eq(b"_ZN5space3fooEPNS_3BarE", b"_ZN5space3fooEPN5space3BarE"));
+        assert!(semantic_eq(b"_Z1fIiEvT_", b"_Z1fIiEvT_"));
+
+        // The same identifiers at different offsets into the input.
+        assert!(semantic_eq(b"_Z3fooi", b"__Z3fooi"));
+        assert!(semantic_eq(b"_Z1fIiEv1AIXLi42EEE", b"__Z1fIiEv1AIXLi42EEE"));
+
+        assert!(!semantic_eq(b"_Z3fooi", b"_Z3fool"));
+        assert!(!semantic_eq(b"_Z3fooi", b"_Z3bari"));
+        assert!(!semantic_eq(b"_Z1fP3FooS0_", b"_Z1fP3FooS_"));
+        assert!(!semantic_eq(b"_Z1fIiEv1AIXLi42EEE", b"_Z1fIiEv1AIXLi43EEE"));
+        assert!(!semantic_eq(b"_ZN5space3fooEv", b"_ZN5space3fooEi"));
+    }
+
+    #[test]
+    fn remangle() {
+        fn remangle(mangled: &[u8]) -> Option<Vec<u8>> {
+            let sym = Symbol::new(mangled).expect("should parse mangled symbol");
+            let remangled = sym.remangle();
+
+            // Remangling is idempotent: parsing what we wrote and remangling
+            // that gives the same bytes again.
+            if let Some(ref remangled) = remangled {
+                let reparsed = Symbol::new(&remangled[..])
+                    .expect("should parse remangled symbol");
+                assert_eq!(reparsed.remangle().as_ref(), Some(remangled));
+                assert!(sym.semantic_eq(&reparsed));
+            }
+
+            remangled
+        }
+
+        let round_trips: &[&[u8]] = &[b"_Z3foov",
+                                      b"_Z3fooicPKc",
+                                      b"_ZN5space3fooEibc",
+                                      b"_ZN5space3fooEPNS_3BarES1_",
+                                      b"_Z1fP3FooS0_",
+                                      b"_ZNK3Foo3barERKS_",
+                                      b"_ZN3FooC1Ev",
+                                      b"_ZN3FooD0Ev",
+                                      b"_ZN3FooplERKS_",
+                                      b"_ZNKSs4sizeEv",
+                                      b"_ZNSt6vectorIiSaIiEE9push_backERKi",
+                                      b"_Z1fIiEvT_",
+                                      b"_Z1fIiJcdEEvT_DpT0_",
+                                      b"_ZN1AIiE1fIcEEvT_",
+                                      b"_Z1fIiEPKcT_",
+                                      b"_Z1fIiEv1AILi42EE",
+                                      b"_Z1fPFivEPFPvS1_E",
+                                      b"_Z1fM3FooFviE",
+                                      b"_ZSt4moveIRiEONSt16remove_referenceIT_E4typeEOS2_",
+                                      b"_Z3fooIL_Z1gvEEvv",
+                                      b"_Z1fu5__m64",
+                                      b"_Z1fDF16i15nDFs7s"];
+        for &mangled in round_trips {
+            assert_eq!(remangle(mangled).as_ref().map(|m| &m[..]),
+                       Some(mangled),
+                       "{} should remangle to itself",
+                       String::from_utf8_lossy(mangled));
+        }
+
+        // Only the prefix changes when normalizing.
+        assert_eq!(remangle(b"__Z3fooi"), Some(b"_Z3fooi".to_vec()));
+
+        // Unsupported constructs.
+        assert_eq!(remangle(b"_ZTV3Foo"), None);
+        assert_eq!(remangle(b"_ZZ4mainE5count"), None);
+        assert_eq!(remangle(b"_Z1fRA_i"), None);
+        assert_eq!(remangle(b"_Z1fIiEv1AIXplLi1ELi2EEE"), None);
+    }
+
+    #[test]
+    fn demangle_increment_and_decrement() {
+        assert_demangle_symbol(b"_Z1fIiEv1AIXppLi1EEE", "void f<int>(A<1++>)");
+        assert_demangle_symbol(b"_Z1fIiEv1AIXmmLi1EEE", "void f<int>(A<1-->)");
+        assert_demangle_symbol(b"_Z1fIiEv1AIXpp_Li1EEE", "void f<int>(A<++1>)");
+        assert_demangle_symbol(b"_Z1fIiEv1AIXmm_Li1EEE", "void f<int>(A<--1>)");
+    }
+
+    #[test]
+    fn demangle_or_borrow_cow() {
+        fn is_borrowed(cow: &Cow<str>) -> bool {
+            match *cow {
+                Cow::Borrowed(_) => true,
+                Cow::Owned(_) => false,
+            }
+        }
+
+        let demangled = demangle_or_borrow("__ZN5space3fooEibc");
+        assert!(!is_borrowed(&demangled));
+        assert_eq!(demangled, "space::foo(int, bool, char)");
+
+        for input in &["", "_", "_Z", "printf", "i", "_ZN3foo", "_Z1fIT_EvT_"] {
+            let unchanged = demangle_or_borrow(input);
+            assert!(is_borrowed(&unchanged));
+            assert_eq!(unchanged, *input);
+        }
+    }
+
+    #[test]
+    fn demangle_function_type_without_params() {
+        assert_demangle_symbol(b"_Z1fPFivE", "f(int (*)())");
+        assert_demangle_symbol(b"_Z1fRFivE", "f(int (&)())");
+        // A degenerate bare function type with only a return type still gets
+        // an (empty) argument list.
+        assert_demangle_symbol(b"_Z1fPFiE", "f(int (*)())");
+        assert_demangle_symbol(b"_Z1fPFvPFivEE", "f(void (*)(int (*)()))");
+    }
+
+    #[test]
+    fn demangle_max_width() {
+        let options = |max_width| {
+            DemangleOptions {
+                max_width: max_width,
+                ..Default::default()
+            }
+        };
+
+        let mangled = b"_Z8functionP9ClassNameS0_S0_S0_";
+        assert_eq!(demangle_with(mangled, options(None)),
+                   "function(ClassName*, ClassName*, ClassName*, ClassName*)");
+        assert_eq!(demangle_with(mangled, options(Some(20))),
+                   "function(ClassName*, ClassName*,\nClassName*, ClassName*)");
+        assert_eq!(demangle_with(mangled, options(Some(0))),
+                   "function(ClassName*,\nClassName*,\nClassName*,\nClassName*)");
+
+        let mangled = b"_Z1fI9ClassName9ClassName9ClassNameEvv";
+        assert_eq!(demangle_with(mangled, options(Some(9))),
+                   "void f<ClassName,\nClassName,\nClassName>()");
+    }
+
+    #[test]
+    fn demangle_context_column() {
+        use std::io::Write;
+
+        let subs = SubstitutionTable::new();
+        let mut out = vec![];
+        {
+            let mut ctx = DemangleContext::new(&subs, b"", &mut out);
+            assert_eq!(ctx.column(), 0);
+            write!(ctx, "foo").unwrap();
+            assert_eq!(ctx.column(), 3);
+            write!(ctx, "(bar,\nbaz").unwrap();
+            assert_eq!(ctx.column(), 3);
+            write!(ctx, "\n").unwrap();
+            assert_eq!(ctx.column(), 0);
+        }
+    }
+
+    #[test]
+    fn demangle_pointer_to_member_expressions() {
+        // `.*` and `->*` parenthesize their operands like every other binary
+        // operator, except for bare names.
+        assert_demangle_symbol(b"_Z1fIiEv1AIXds1a1bEE", "void f<int>(A<a.*b>)");
+        assert_demangle_symbol(b"_Z1fIiEv1AIXpm1a1bEE", "void f<int>(A<a->*b>)");
+        assert_demangle_symbol(b"_Z1fIiEv1AIXdsT_plT_Li1EEE",
+                               "void f<int>(A<(int).*((int)+(1))>)");
+        assert_demangle_symbol(b"_Z1fIiEv1AIXdsdsT_T_T_EE",
+                               "void f<int>(A<((int).*(int)).*(int)>)");
+        assert_demangle_symbol(b"_Z1fIiEv1AIXpm1aplLi1ELi2EEE",
+                               "void f<int>(A<a->*((1)+(2))>)");
+    }
+
+    #[test]
+    fn demangle_binary_operator_spacing() {
+        let spaced_options = DemangleOptions {
+            spaced_binary_operators: true,
+            ..Default::default()
+        };
+
+        // By default, match libiberty, which doesn't space binary operators.
+        let cases: &[(&[u8], &str, &str)] = &[
+            (b"_Z1fIiEv1AIXplT_Li1EEE",
+             "void f<int>(A<(int)+(1)>)",
+             "void f<int>(A<(int) + (1)>)"),
+            (b"_Z1fIiEv1AIXaaT_Li1EEE",
+             "void f<int>(A<(int)&&(1)>)",
+             "void f<int>(A<(int) && (1)>)"),
+            (b"_Z1fIiEv1AIXlsT_Li1EEE",
+             "void f<int>(A<(int)<<(1)>)",
+             "void f<int>(A<(int) << (1)>)"),
+            (b"_Z1fIiEvDTcm1a1bE",
+             "void f<int>(decltype (a,b))",
+             "void f<int>(decltype (a, b))"),
+            (b"_Z1fIiEvDTcmT_Li1EE",
+             "void f<int>(decltype ((int),(1)))",
+             "void f<int>(decltype ((int), (1)))"),
+            (b"_Z1fIiEvDTcm1acm1b1cE",
+             "void f<int>(decltype (a,(b,c)))",
+             "void f<int>(decltype (a, (b, c)))"),
+        ];
+
+        for &(mangled, default, spaced) in cases {
+            assert_eq!(demangle_with(mangled, Default::default()), default);
+            assert_eq!(demangle_with(mangled, spaced_options.clone()), spaced);
+        }
+    }
+
+    #[test]
+    fn demangle_template_template_params() {
+        assert_demangle_symbol(b"_Z1fI1AEvT_IiE", "void f<A>(A<int>)");
+        assert_demangle_symbol(b"_Z1fI1AEvPT_IiE", "void f<A>(A<int>*)");
+        assert_demangle_symbol(b"_Z1fISt6vectorEvT_IiE",
+                               "void f<std::vector>(std::vector<int>)");
+        assert_demangle_symbol(b"_Z1gI1AEvT_IT_E", "void g<A>(A<A>)");
+        // `S1_` is the template template param `T_`, which must still resolve
+        // to `A` rather than to its own `char` arg.
+        assert_demangle_symbol(b"_Z1fI1AEvT_IiES1_IcE", "void f<A>(A<int>, A<char>)");
+        assert_demangle_symbol(b"_Z1fI1AEvT_IiES2_", "void f<A>(A<int>, A<int>)");
+    }
+
+    #[test]
+    fn demangle_cv_qualifier_combinations() {
+        // Mangled in `r V K` order, but printed as const, volatile, restrict,
+        // like libiberty does.
+        assert_demangle_symbol(b"_Z1fPi", "f(int*)");
+        assert_demangle_symbol(b"_Z1fPKi", "f(int const*)");
+        assert_demangle_symbol(b"_Z1fPVi", "f(int volatile*)");
+        assert_demangle_symbol(b"_Z1fPri", "f(int restrict*)");
+        assert_demangle_symbol(b"_Z1fPVKi", "f(int const volatile*)");
+        assert_demangle_symbol(b"_Z1fPrKi", "f(int const restrict*)");
+        assert_demangle_symbol(b"_Z1fPrVi", "f(int volatile restrict*)");
+        assert_demangle_symbol(b"_Z1fPrVKi", "f(int const volatile restrict*)");
+    }
+
+    #[test]
+    fn demangle_function_type_template_args() {
+        assert_demangle_symbol(b"_Z1fIFivEEvv", "void f<int ()>()");
+        assert_demangle_symbol(b"_Z1fIFiRKiEEvv", "void f<int (int const&)>()");
+        assert_demangle_symbol(b"_Z1fIFPivEEvv", "void f<int* ()>()");
+        assert_demangle_symbol(b"_Z1fIKFivEEvv", "void f<int () const>()");
+        assert_demangle_symbol(b"_Z1fIFSt6vectorIiEvEEvv", "void f<std::vector<int> ()>()");
+        assert_demangle_symbol(b"_Z1fIFivEEvT_", "void f<int ()>(int ())");
+        assert_demangle_symbol(b"_Z1fIPFivEEvv", "void f<int (*)()>()");
+    }
+
+    #[test]
+    fn demangle_dependent_array_dimensions() {
+        assert_demangle_symbol(b"_Z1fILi3EEvRAT__i", "void f<3>(int (&) [3])");
+        assert_demangle_symbol(b"_Z1fILi3EEvPAT__i", "void f<3>(int (*) [3])");
+        assert_demangle_symbol(b"_Z1fIiEvRAstT__i", "void f<int>(int (&) [sizeof (int)])");
+        assert_demangle_symbol(b"_Z1fILi3EEvRAplT_Li1E_i", "void f<3>(int (&) [(3)+(1)])");
+    }
+
+    #[test]
+    fn demangle_variable_templates() {
+        assert_demangle_symbol(b"_Z3fooIiE", "foo<int>");
+        assert_demangle_symbol(b"_ZN1A3fooIiEE", "A::foo<int>");
+        assert_demangle_symbol(b"_ZN1AIiE3fooIcEE", "A<int>::foo<char>");
+        assert_demangle_symbol(b"_ZZ1fvE3fooIiE", "f()::foo<int>");
+
+        // With a parameter list, it's a function template instead.
+        assert_demangle_symbol(b"_Z3fooIiEvv", "void foo<int>()");
+    }
+
+    #[test]
+    fn demangle_well_known_nested_names() {
+        assert_demangle_symbol(b"_ZNSt3fooEv", "std::foo()");
+        assert_demangle_symbol(b"_ZNKSs4sizeEv", "std::string::size() const");
+        assert_demangle_symbol(b"_ZNSaIiE8allocateEm",
+                               "std::allocator<int>::allocate(unsigned long)");
+        assert_demangle_symbol(b"_ZNSbIcE4sizeEv", "std::basic_string<char>::size()");
+    }
+
+    #[test]
+    fn demangle_thunks() {
+        assert_demangle_symbol(b"_ZTh8_N1D1fEv", "non-virtual thunk to D::f()");
+        assert_demangle_symbol(b"_ZThn8_N1D1fEv", "non-virtual thunk to D::f()");
+        assert_demangle_symbol(b"_ZTv0_n8_N1D1fEv", "virtual thunk to D::f()");
+        assert_demangle_symbol(b"_ZTch0_h16_N1D1fEv", "covariant return thunk to D::f()");
+        assert_demangle_symbol(b"_ZTcv0_n12_h8_N1D1fEv", "covariant return thunk to D::f()");
+    }
+
+    #[test]
+    fn demangle_guard_temporaries() {
+        assert_demangle_symbol(b"_ZGR1x_", "reference temporary #0 for x");
+        assert_demangle_symbol(b"_ZGR1x0_", "reference temporary #1 for x");
+        assert_demangle_symbol(b"_ZGR1x1_", "reference temporary #2 for x");
+        assert_demangle_symbol(b"_ZGRN1A1xE_", "reference temporary #0 for A::x");
+    }
+
+    #[test]
+    fn demangle_member_access_template_args() {
+        assert_demangle_symbol(b"_Z1fI1AEv1BIXdtT_1xEE", "void f<A>(B<A.x>)");
+        assert_demangle_symbol(b"_Z1fI1AEv1BIXptT_1xEE", "void f<A>(B<A->x>)");
+        assert_demangle_symbol(b"_Z1fI1AEv1BIXdsT_T_EE", "void f<A>(B<(A).*(A)>)");
+        assert_demangle_symbol(b"_Z1fI1AEv1BIXadsrT_1xEE", "void f<A>(B<&A::x>)");
+        assert_demangle_symbol(b"_Z1fI1AEv1BIXdtL_Z1aE1xEE", "void f<A>(B<a.x>)");
+    }
+
+    #[test]
+    fn demangle_function_address_template_args() {
+        assert_demangle_symbol(b"_Z1fIXadL_Z1gvEEEvv", "void f<&g>()");
+        assert_demangle_symbol(b"_Z1fIXadL_ZN2ns4funcEiEEEvv", "void f<&ns::func>()");
+        assert_demangle_symbol(b"_ZN1KIXadL_ZN1S1mEiEEE1fEv", "K<&S::m>::f()");
+        // Without an explicit `ad`, the external name is printed as is.
+        assert_demangle_symbol(b"_Z1fIL_Z1gvEEvv", "void f<g()>()");
+        assert_demangle_symbol(b"_Z1fIL_ZN2ns4funcEiEEvv", "void f<ns::func(int)>()");
+    }
+
+    #[test]
+    fn demangle_unresolved_qualifier_level_template_args() {
+        assert_demangle_symbol(b"_Z1fIiEvDTsr1AIiE1BIcEE3fooE",
+                               "void f<int>(decltype (A<int>::B<char>::foo))");
+        assert_demangle_symbol(b"_Z1fIiEvDTsr1AIiE1BIcEE3fooIdEE",
+                               "void f<int>(decltype (A<int>::B<char>::foo<double>))");
+        assert_demangle_symbol(b"_Z1fIiEvDTsr1AIiE1BIcEEonplIdEE",
+                               "void f<int>(decltype (A<int>::B<char>::operator+<double>))");
+        assert_demangle_symbol(b"_Z1fIiEvDTgssr1AIiE1BIcEE3fooE",
+                               "void f<int>(decltype (::A<int>::B<char>::foo))");
+        assert_demangle_symbol(b"_Z1fIiEvDTgs3fooE", "void f<int>(decltype (::foo))");
+        assert_demangle_symbol(b"_Z1fIiEvDTgsnw_iEE", "void f<int>(decltype (::new int))");
+    }
+
+    #[test]
+    fn demangle_unresolved_template_type() {
+        // `T_` and its args both refer to `f`'s template args.
+        assert_demangle_symbol(b"_Z1fI1AEvDTsrT_IiE1xE", "void f<A>(decltype (A<int>::x))");
+        assert_demangle_symbol(b"_Z1fI1AEvDTsrT_IT_E1xE", "void f<A>(decltype (A<A>::x))");
+        assert_demangle_symbol(b"_Z1fIiEvDTsrNT_1A1BE1xE",
+                               "void f<int>(decltype (int::A::B::x))");
+        assert_demangle_symbol(b"_Z1fIiEvDTsr1A1BE1xE", "void f<int>(decltype (A::B::x))");
+    }
+
+    #[test]
+    fn d_prefix_disambiguation() {
+        fn peek(input: &[u8]) -> Option<DPrefix> {
+            peek_d_prefix(IndexStr::from(input))
+        }
+
+        assert_eq!(peek(b"DtT_E"), Some(DPrefix::Decltype));
+        assert_eq!(peek(b"DTT_E"), Some(DPrefix::Decltype));
+        assert_eq!(peek(b"DpT_"), Some(DPrefix::PackExpansion));
+        assert_eq!(peek(b"DxFvvE"), Some(DPrefix::TransactionSafe));
+        assert_eq!(peek(b"D1Ev"), Some(DPrefix::Destructor));
+        assert_eq!(peek(b"Dn"), Some(DPrefix::BuiltinType));
+        assert_eq!(peek(b"Dk1C"), Some(DPrefix::BuiltinType));
+        assert_eq!(peek(b"D"), None);
+        assert_eq!(peek(b"i"), None);
+
+        assert_demangle_symbol(b"_Z1fIiEvDtT_E", "void f<int>(decltype (int))");
+        assert_demangle_symbol(b"_Z1fIiEvDTplT_T_E", "void f<int>(decltype ((int)+(int)))");
+        assert_demangle_symbol(b"_Z1fIJiEEvDpT_", "void f<int>(int)");
+        assert_demangle_symbol(b"_Z1fPDxFvvE", "f(void (*)())");
+        assert_demangle_symbol(b"_Z1fDd", "f(_Decimal64)");
+        assert_demangle_symbol(b"_Z1fDk1C", "f(C auto)");
+        assert_demangle_symbol(b"_ZN1AD1Ev", "A::complete object destructor()");
+        assert_demangle_symbol(b"_Z1fIiEvNDtT_E1xE", "void f<int>(decltype (int)::x)");
+
+        assert_eq!(Symbol::new(&b"_Z1fDz"[..]).map(|_| ()), Err(Error::UnexpectedText));
+        assert_eq!(Symbol::new(&b"_Z1fD1"[..]).map(|_| ()), Err(Error::UnexpectedText));
+    }
+
+    #[test]
+    fn parse_deeply_nested_pointers() {
+        use std::iter;
+
+        // Parsing these used to recurse once per layer and overflow the stack.
+        let depth = 1000;
+        let mut mangled = b"_Z1f".to_vec();
+        mangled.extend(iter::repeat(b'P').take(depth));
+        mangled.push(b'i');
+
+        let sym = Symbol::new(&mangled[..]).expect("should parse deeply nested pointers");
+        assert_eq!(sym.substitutions.len(), depth);
+
+        let mut mangled = b"_Z1f".to_vec();
+        for _ in 0..depth / 4 {
+            mangled.extend(b"PKRV");
+        }
+        mangled.push(b'i');
+        assert!(Symbol::new(&mangled[..]).is_ok());
+    }
+
+    #[test]
+    fn symbol_prefixes() {
+        assert_demangle_symbol(b"_Z3fooi", "foo(int)");
+        assert_demangle_symbol(b"__Z3fooi", "foo(int)");
+
+        // No platform adds more than one underscore to `_Z`.
+        assert_eq!(Symbol::new(&b"___Z3fooi"[..]).map(|_| ()),
+                   Err(Error::UnexpectedText));
+        assert_eq!(Symbol::new_lenient(&b"___Z3fooi"[..]).map(|_| ()),
+                   Err(Error::UnexpectedText));
+    }
+
+    #[test]
+    fn symbol_rejects_trailing_top_level_type_input() {
+        assert_eq!(Symbol::new_lenient(&b"i"[..]).map(|s| s.to_string()),
+                   Ok("int".into()));
+        assert_eq!(Symbol::new_lenient(&b"i3foo"[..]).map(|_| ()),
+                   Err(Error::UnexpectedText));
+        assert_eq!(Symbol::new(&b"i3foo"[..]).map(|_| ()),
+                   Err(Error::UnexpectedText));
+
+        // Only `with_tail` hands back what follows the type.
+        let (sym, tail) = BorrowedSymbol::with_tail(b"i3foo").expect("should parse a type");
+        assert_eq!(sym.to_string(), "int");
+        assert_eq!(tail, b"3foo");
+    }
+
+    #[test]
+    fn demangle_non_utf8_identifiers() {
+        use demangle_bytes;
+
+        assert_demangle_symbol(b"_Z5caf\xc3\xa9v", "caf\u{e9}()");
+        assert_eq!(demangle_bytes(b"_Z5caf\xc3\xa9v").unwrap(), b"caf\xc3\xa9()");
+        assert_eq!(demangle_bytes(b"_ZN4caf\xe91fEv").unwrap(), b"caf\xe9::f()");
+        assert_eq!(demangle_bytes(b"_Z4caf\xe9v").unwrap(), b"caf\xe9()");
+        match demangle_bytes(b"_Z4caf") {
+            Err(DemangleError::Parse(Error::UnexpectedEnd)) => {}
+            otherwise => panic!("unexpected result: {:?}", otherwise),
+        }
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn demangle_to_failing_sink() {
+        use error::DemangleError;
+        use std::error::Error as StdError;
+        use std::io;
+
+        struct FullPipe;
+
+        impl io::Write for FullPipe {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::Error::new(io::ErrorKind::BrokenPipe, "pipe is full"))
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let sym = Symbol::new(&b"_Z3fooi"[..]).unwrap();
+        match sym.demangle_to(FullPipe, Default::default()) {
+            Err(ref e @ DemangleError::Io(_)) => {
+                let cause = e.cause().expect("should chain to the IO error");
+                assert_eq!(cause.to_string(), "pipe is full");
+            }
+            otherwise => panic!("unexpected result: {:?}", otherwise),
+        }
+
+        // A semantic error has no underlying cause, whatever the sink.
+        let sym = Symbol::new(&b"_Z1fIT_EvT_"[..]).unwrap();
+        match sym.demangle_to(vec![], Default::default()) {
+            Err(ref e @ DemangleError::Demangle(Error::RecursiveDemangling)) => {
+                assert!(e.cause().is_none());
+            }
+            otherwise => panic!("unexpected result: {:?}", otherwise),
+        }
+    }
+
+    #[test]
+    fn demangle_fixed_point_types() {
+        assert_demangle_symbol(b"_Z1fDF16i15n", "f(_Accum)");
+        assert_demangle_symbol(b"_Z1fDFs7s", "f(_Sat short _Fract)");
+        assert_demangle_symbol(b"_Z1fDFl31n", "f(long _Fract)");
+        assert_demangle_symbol(b"_Z1fDF8t8s", "f(_Sat unsigned short _Accum)");
+        assert_demangle_symbol(b"_Z1fPDFi15n", "f(_Fract*)");
+    }
+
+    #[test]
+    fn demangle_half_precision_float_types() {
+        assert_demangle_symbol(b"_Z1fDh", "f(half)");
+        assert_demangle_symbol(b"_Z1fPDh", "f(half*)");
+        assert_demangle_symbol(b"_Z1fDF16b", "f(std::bfloat16_t)");
+        assert_demangle_symbol(b"_Z1fDF16bDF16i15n", "f(std::bfloat16_t, _Accum)");
+        assert_demangle_symbol(b"_Z1fDF16_", "f(_Float16)");
+        assert_demangle_symbol(b"_Z1fDF32_DF16b", "f(_Float32, std::bfloat16_t)");
+    }
+
+    #[test]
+    fn demangle_with_markup() {
+        fn demangle_with_markup(mangled: &[u8], options: DemangleOptions) -> String {
+            let sym = Symbol::new(mangled).expect("should parse mangled symbol");
+            let mut out = vec![];
+            sym.demangle_to_with_markup(&mut out, options, |markup, out| {
+                    let marker: &[u8] = match markup {
+                        Markup::Start(MarkupRegion::Name) => b"{name:",
+                        Markup::Start(MarkupRegion::Type) => b"{type:",
+                        Markup::Start(MarkupRegion::TemplateArgs) => b"{args:",
+                        Markup::End(_) => b"}",
+                    };
+                    out.write_all(marker)
+                })
+                .expect("should demangle");
+            String::from_utf8(out).expect("should be utf-8")
+        }
+
+        assert_eq!(demangle_with_markup(b"_Z3fooIiEvT_", Default::default()),
+                   "{type:void} {name:foo}{args:<{type:int}>}({type:int})");
+        assert_eq!(demangle_with_markup(b"_ZN5space3fooEPKc", Default::default()),
+                   "{name:space}::{name:foo}({type:char const*})");
+
+        // Markers don't count towards the width of a line.
+        let options = DemangleOptions { max_width: Some(12), ..Default::default() };
+        assert_eq!(demangle_with_markup(b"_Z3fooii", options),
+                   "{name:foo}({type:int}, {type:int})");
+
+        // Elided template args are still a region.
+        let options = DemangleOptions { max_template_depth: Some(0), ..Default::default() };
+        assert_eq!(demangle_with_markup(b"_Z3fooIiEvT_", options),
+                   "{type:void} {name:foo}{args:<...>}({type:int})");
+
+        // Without a hook, there is no markup.
+        let sym = Symbol::new(&b"_Z3fooIiEvT_"[..]).expect("should parse mangled symbol");
+        assert_eq!(sym.demangle().unwrap(), "void foo<int>(int)");
+    }
+
+    #[test]
+    fn demangle_decltype_id_expressions_and_expressions() {
+        assert_demangle_symbol(b"_Z1fI1AEvDtdtT_1xE", "void f<A>(decltype (A.x))");
+        assert_demangle_symbol(b"_Z1fI1AEvDTdtT_1xE", "void f<A>(decltype (A.x))");
+        assert_demangle_symbol(b"_Z1fIiEvDtT_E", "void f<int>(decltype (int))");
+        assert_demangle_symbol(b"_Z1fIiEvDTT_E", "void f<int>(decltype (int))");
+    }
+
+    #[test]
+    fn demangle_local_member_functions() {
+        assert_demangle_symbol(b"_ZZ4mainEN1A1fIiEEiv", "int main::A::f<int>()");
+        assert_demangle_symbol(b"_ZZN1B1gEvEN1A1fIiEEiT_", "int B::g()::A::f<int>(int)");
+
+        // The member function's qualifiers follow its parameter list.
+        assert_demangle_symbol(b"_ZZ4mainENK1A1fEv", "main::A::f() const");
+        assert_demangle_symbol(b"_ZZ4mainENK1A1fIiEEiv", "int main::A::f<int>() const");
+        assert_demangle_symbol(b"_ZZN1B1gEvENK1A1fIiEEiT_", "int B::g()::A::f<int>(int) const");
+        assert_demangle_symbol(b"_ZZ4mainENKR1A1fEv", "main::A::f() const &");
+    }
+
+    #[test]
+    fn demangle_vendor_extended_types() {
+        // The qualifier, with its template args, follows the type it
+        // qualifies.
+        assert_demangle_symbol(b"_Z1fU6vendorIiEc", "f(char vendor<int>)");
+        assert_demangle_symbol(b"_Z1fPU6vendorIiEc", "f(char vendor<int>*)");
+        assert_demangle_symbol(b"_Z1fU3fooU3barc", "f(char bar foo)");
+
+        // Except on function types, where it goes in the parentheses before
+        // the parameter list, along with any pointer or reference.
+        assert_demangle_symbol(b"_Z1fPU7stdcallFviE", "f(void ( stdcall*)(int))");
+        assert_demangle_symbol(b"_Z1fRU7stdcallFviE", "f(void ( stdcall&)(int))");
+        assert_demangle_symbol(b"_Z18IndirectExternCallIPU7stdcallU7regparmILi3EEFviiE\
+                                 iEvT_T0_S3_",
+                               "void IndirectExternCall<void ( regparm<3> stdcall*)(int, \
+                                int), int>(void ( regparm<3> stdcall*)(int, int), int, \
+                                void ( regparm<3> stdcall*)(int, int))");
+    }
+
+    #[test]
+    fn demangle_global_unresolved_operators() {
+        assert_demangle_symbol(b"_Z1fIiEvDTgsonplIiEE", "void f<int>(decltype (::operator+<int>))");
+        assert_demangle_symbol(b"_Z1fIiEvDTgsonplE", "void f<int>(decltype (::operator+))");
+        assert_demangle_symbol(b"_Z1fIiEvDTonplIiEE", "void f<int>(decltype (operator+<int>))");
+    }
+
+    #[test]
+    fn demangle_member_function_qualifiers() {
+        // The qualifiers of a member function's implicit object parameter
+        // are printed once, after its parameter list.
+        assert_demangle_symbol(b"_ZNK1A1fEv", "A::f() const");
+        assert_demangle_symbol(b"_Z1fM1AKFvvE", "f(void (A::*)() const)");
+        assert_demangle_symbol(b"_Z1fM1AVKFivE", "f(int (A::*)() const volatile)");
+        assert_demangle_symbol(b"_ZNK1A1fEM1AKFvvE", "A::f(void (A::*)() const) const");
+        assert_demangle_symbol(b"_Z1lM1AKFvvRE", "l(void (A::*)() const &)");
+        assert_demangle_symbol(b"_Z1nIM1AKFvvREEvT_",
+                               "void n<void (A::*)() const &>(void (A::*)() const &)");
+
+        // Including when the member's function type is a template argument.
+        assert_demangle_symbol(b"_Z1fIKFvvEEvM1AT_", "void f<void () const>(void (A::*)() const)");
+        assert_demangle_symbol(b"_Z1mIFvvOEEvM1AT_", "void m<void () &&>(void (A::*)() &&)");
+    }
+
+    #[test]
+    fn demangle_keyword_source_names() {
+        // These are ordinary identifiers that happen to spell keywords, not
+        // the `<operator-name>` production, and print verbatim.
+        assert_demangle_symbol(b"_Z8operatorv", "operator()");
+        assert_demangle_symbol(b"_ZN8operator3fooEv", "operator::foo()");
+        assert_demangle_symbol(b"_Z1fI8operatorEvv", "void f<operator>()");
+        assert_demangle_symbol(b"_Z6sizeofi", "sizeof(int)");
+    }
+
+    #[test]
+    fn demangle_member_access_callees() {
+        assert_demangle_symbol(b"_Z1fIiEvDTclptL_Z1pE1gEE", "void f<int>(decltype (p->g()))");
+        assert_demangle_symbol(b"_Z1fIiEvDTcldtL_Z1pE1gLi1EEE", "void f<int>(decltype (p.g(1)))");
+
+        // Other callees are still parenthesized.
+        assert_demangle_symbol(b"_Z1fIiEvDTclL_Z1gEEE", "void f<int>(decltype ((g)()))");
+    }
+
+    #[test]
+    fn demangle_std_operator_names() {
+        assert_demangle_symbol(b"_ZStnwm", "std::operator new(unsigned long)");
+        assert_demangle_symbol(b"_ZStdlPv", "std::operator delete(void*)");
+        assert_demangle_symbol(b"_ZStplRKSsS0_",
+                               "std::operator+(std::string const&, std::string const&)");
+        assert_demangle_symbol(b"_ZStlsRSoPKc", "std::operator<<(std::ostream&, char const*)");
+    }
+
+    #[test]
+    fn demangle_pack_expansion_function_params() {
+        assert_demangle_symbol(b"_Z1fIJicEEvDpT_", "void f<int, char>(int, char)");
+        assert_demangle_symbol(b"_Z1fIJicEEvDpPT_", "void f<int, char>(int*, char*)");
+        assert_demangle_symbol(b"_Z1fIJicEEvDp1AIT_E", "void f<int, char>(A<int>, A<char>)");
+        assert_demangle_symbol(b"_Z1fIJicEEvbDpRKT_", "void f<int, char>(bool, int const&, char const&)");
+
+        // An empty pack contributes no parameters, and no separators.
+        assert_demangle_symbol(b"_Z1fIJEEvDpT_", "void f<>()");
+        assert_demangle_symbol(b"_Z1fIJEEviDpT_", "void f<>(int)");
+
+        // When the pattern doesn't refer to a pack, the `...` follows it
+        // directly.
+        assert_demangle_symbol(b"_Z1fIiEvDpT_", "void f<int>(int...)");
+    }
+
+    #[test]
+    fn demangle_unresolved_function_params() {
+        // There is no function argument scope to resolve these in, so they are
+        // printed by position.
+        assert_demangle_symbol(b"_Z1fIiEvT_DTngfp_E", "void f<int>(int, decltype (-{parm#1}))");
+        assert_demangle_symbol(b"_Z1fIiEvT_T_DTmifp_fp0_E",
+                               "void f<int>(int, int, decltype ({parm#1}-{parm#2}))");
+        assert_demangle_symbol(b"_Z1fIiEvT_DTfL0p_E", "void f<int>(int, decltype ({parm#1}))");
+        assert_demangle_symbol(b"_Z1fIJicEEvDpT_DTszspfp_E",
+                               "void f<int, char>(int, char, decltype (sizeof ({parm#1}...)))");
+    }
+
+    #[test]
+    fn demangle_requires_expressions() {
+        assert_demangle_symbol(b"_Z1fIiEvDTrqXT_TNT_4typeEEE",
+                               "void f<int>(decltype (requires { int; \
+                                typename int::type; }))");
+        assert_demangle_symbol(b"_Z1fIiEvDTrqXT_NR1CQT_EE",
+                               "void f<int>(decltype (requires { { int } noexcept -> C; \
+                                requires int; }))");
+        assert_demangle_symbol(b"_Z1fIiEvDTrQiT__XT_EE",
+                               "void f<int>(decltype (requires (int, int) { int; }))");
+    }
+
+    #[test]
+    fn demangle_trailing_requires_clauses() {
+        assert_demangle_symbol(b"_Z1fIiEvT_Q1CIT_E", "void f<int>(int) requires C<int>");
+        assert_demangle_symbol(b"_ZNK1A1fIiEEvT_Q1CIT_E",
+                               "void A::f<int>(int) const requires C<int>");
+        assert_demangle_symbol(b"_Z1fIiEvT_QrqXplT_Li1EE",
+                               "void f<int>(int) requires requires { (int)+(1); }");
+        assert_demangle_symbol(b"_Z1fIiEvT_QaaL_Z1bEL_Z1cE",
+                               "void f<int>(int) requires (b)&&(c)");
+
+        // Unconstrained functions, and data, are unaffected.
+        assert_demangle_symbol(b"_Z1fIiEvT_", "void f<int>(int)");
+        assert!(Symbol::new(&b"_Z1fIiEvT_Q"[..]).is_err());
+    }
+
+    #[test]
+    fn demangle_empty_template_args() {
+        assert_demangle_symbol(b"_Z1fIJEEvv", "void f<>()");
+        assert_demangle_symbol(b"_ZN1AIJEE1fEv", "A<>::f()");
+        assert!(Symbol::new(&b"_Z1fIEvv"[..]).is_err());
+    }
+
+    #[test]
+    fn demangle_template_param_prefixes() {
+        assert_demangle_symbol(b"_Z1fI1AEvNT_3fooE", "void f<A>(A::foo)");
+        assert_demangle_symbol(b"_Z1fI1AEvNT_3foo3barE", "void f<A>(A::foo::bar)");
+        assert_demangle_symbol(b"_Z1fI1AEvPNT_3fooE", "void f<A>(A::foo*)");
+        assert_demangle_symbol(b"_Z1fIN1A1BEEvNT_3fooE", "void f<A::B>(A::B::foo)");
+
+        // Outside of any template, there is nothing for `T_` to refer to.
+        let sym = Symbol::new(&b"_ZNT_3fooEv"[..]).expect("should parse");
+        match sym.demangle() {
+            Err(DemangleError::Demangle(Error::BadTemplateArgReference)) => {}
+            otherwise => panic!("unexpected result: {:?}", otherwise),
+        }
+    }
+
+    #[test]
+    fn demangle_vendor_extended_expressions() {
+        assert_demangle_symbol(b"_Z1fIiEvDTu3fooEE", "void f<int>(decltype (foo()))");
+        assert_demangle_symbol(b"_Z1fIiEvDTu3fooT_iEE",
+                               "void f<int>(decltype (foo(int, int)))");
+    }
+
+    #[test]
+    fn demangle_throw_in_call_args() {
+        assert_demangle_symbol(b"_Z1fIiEDTcl1gtrEEv", "decltype ((g)(throw)) f<int>()");
+        assert_demangle_symbol(b"_Z1fIiEDTcl1gtwLi1EEEv", "decltype ((g)(throw 1)) f<int>()");
+        assert_demangle_symbol(b"_Z1fIiEDTcl1gtwLi1EtrEEv",
+                               "decltype ((g)(throw 1, throw)) f<int>()");
+        assert_demangle_symbol(b"_Z1fIiEDTcl1gtwcl1hEEEv",
+                               "decltype ((g)(throw (h)())) f<int>()");
+    }
+
+    #[test]
+    fn demangle_parsed_symbol_repeatedly() {
+        let sym = Symbol::new(&b"_Z1fDnCf"[..]).unwrap();
+
+        let gnu = DemangleOptions::default();
+        let llvm = DemangleOptions {
+            flavor: Flavor::Llvm,
+            complex_spelling: ComplexSpelling::Gnu,
+            ..Default::default()
+        };
+
+        assert_eq!(sym.demangle_with_options(gnu.clone()).unwrap(),
+                   "f(decltype(nullptr), float _Complex)");
+        assert_eq!(sym.demangle_with_options(llvm.clone()).unwrap(),
+                   "f(std::nullptr_t, float __complex__)");
+
+        let mut out = vec![];
+        sym.demangle_to(&mut out, llvm).unwrap();
+        sym.demangle_to(&mut out, gnu).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(),
+                   "f(std::nullptr_t, float __complex__)f(decltype(nullptr), float _Complex)");
+    }
+
+    #[test]
+    fn demangle_empty_arg_packs() {
+        // Like libiberty, an empty pack still gets its angle brackets, and
+        // doesn't leave a stray comma behind.
+        assert_demangle("IJEE", [], TemplateArgs(vec![TemplateArg::ArgPack(vec![])]), "<>");
+        assert_demangle_symbol(b"_Z1fIJEEvv", "void f<>()");
+        assert_demangle_symbol(b"_Z1fI1AIJEEEvv", "void f<A<> >()");
+        assert_demangle_symbol(b"_ZN1AIJEE1fEv", "A<>::f()");
+        assert_demangle_symbol(b"_Z1fI1AIJEiEEvv", "void f<A<int> >()");
+        assert_demangle_symbol(b"_Z1fI1AIiJEEEvv", "void f<A<int> >()");
+        assert_demangle_symbol(b"_Z1fI1AIiJJEEcEEvv", "void f<A<int, char> >()");
+    }
+
+    #[test]
+    fn parse_with_spans() {
+        let sym = Symbol::new_with_spans(&b"_Z3fooi"[..]).unwrap();
+        let spans = sym.spans().unwrap();
+
+        let span_of = |production| {
+            spans.iter()
+                .find(|span| span.production == production)
+                .map(|span| (span.start, span.end))
+        };
+        assert_eq!(span_of("SourceName"), Some((2, 6)));
+        assert_eq!(span_of("UnqualifiedName"), Some((2, 6)));
+        assert_eq!(span_of("Name"), Some((2, 6)));
+        assert_eq!(span_of("TypeHandle"), Some((6, 7)));
+        assert_eq!(span_of("Encoding"), Some((2, 7)));
+        assert_eq!(span_of("MangledName"), Some((0, 7)));
+
+        // The outermost production always completes last.
+        assert_eq!(spans.last().map(|span| span.production), Some("MangledName"));
+    }
+
+    #[test]
+    fn stats() {
+        fn stats(mangled: &[u8]) -> DemangleStats {
+            Symbol::new(mangled).expect("should parse mangled symbol").stats()
+        }
+
+        // `space`, `space::Bar`, and `space::Bar*`. The function's name and the
+        // last component of the type's name are not substitution candidates.
+        let s = stats(b"_ZN5space3fooEPNS_3BarES1_");
+        assert_eq!(s.substitutions, 3);
+        assert_eq!(s.demangled_len,
+                   Some("space::foo(space::Bar*, space::Bar*)".len()));
+
+        // Repeating a type through back references doesn't add substitutions.
+        assert_eq!(stats(b"_Z1fP3FooS0_S0_S0_S0_").substitutions, 2);
+
+        // More deeply nested template args take a deeper parse.
+        assert!(stats(b"_Z1fI1AI1BI1CEEEvv").max_parse_depth >
+                stats(b"_Z1fI1AEvv").max_parse_depth);
+
+        // A symbol that parses but fails to demangle has no length.
+        assert_eq!(stats(b"_Z1fIT_EvT_").demangled_len, None);
+    }
+
+    #[test]
+    fn demangler_reuse() {
+        let symbols: &[&[u8]] = &[
+            b"_ZN5space3fooEPNS_3BarES1_",
+            b"_Z3fooi",
+            b"_Zzzz",
+            b"_Z1fI1AI1BIiEEEvv",
+            b"main",
+            b"_ZN5space3fooEPNS_3BarES1_",
+        ];
+
+        let mut demangler = Demangler::new();
+        for mangled in symbols {
+            let expected = Symbol::new(*mangled).map_err(DemangleError::Parse)
+                .and_then(|sym| sym.demangle());
+            let actual = demangler.demangle(mangled).map(|s| s.to_string());
+            assert_eq!(format!("{:?}", actual), format!("{:?}", expected));
+        }
+
+        // The previous symbol's substitutions are gone, so they can't be
+        // referenced.
+        assert!(demangler.demangle(b"_ZN5space3fooEPNS_3BarES1_").is_ok());
+        assert!(demangler.demangle(b"_Z1fS_").is_err());
+
+        // Input that fails to parse is reported as such.
+        match demangler.demangle(b"main") {
+            Err(DemangleError::Parse(Error::UnexpectedText)) => {}
+            otherwise => panic!("unexpected result: {:?}", otherwise),
+        }
+    }
+
+    #[test]
+    fn parse_max_substitutions() {
+        // Every `P` adds another pointer type to the substitution table.
+        let mut mangled = b"_Z1f".to_vec();
+        mangled.extend(::std::iter::repeat(b'P').take(1000));
+        mangled.push(b'i');
+
+        let parse = |max_substitutions| {
+            let options = ParseOptions { max_substitutions: max_substitutions };
+            Symbol::new_with_options(&mangled[..], options).map(|_| ())
+        };
+
+        assert_eq!(parse(None), Ok(()));
+        assert_eq!(parse(Some(1000)), Ok(()));
+        assert_eq!(parse(Some(999)), Err(Error::TooManySubstitutions));
+        assert_eq!(parse(Some(0)), Err(Error::TooManySubstitutions));
+
+        // Symbols without any substitutions fit in an empty table.
+        let options = ParseOptions { max_substitutions: Some(0) };
+        assert!(Symbol::new_with_options(&b"_Z3fooi"[..], options).is_ok());
+    }
+
+    #[test]
+    fn demangle_flavors() {
+        let llvm_options = DemangleOptions {
+            flavor: Flavor::Llvm,
+            ..Default::default()
+        };
+
+        // By default, match libiberty.
+        assert_demangle_symbol(b"_Z1fDn", "f(decltype(nullptr))");
+
+        let cases: &[(&[u8], &str, &str)] = &[
+            (b"_Z1fDn", "f(decltype(nullptr))", "f(std::nullptr_t)"),
+            (b"_Z1fSs", "f(std::string)", "f(std::string)"),
+            (b"_Z1fSo", "f(std::ostream)", "f(std::ostream)"),
+            (b"_Z1fSi", "f(std::istream)", "f(std::istream)"),
+            (b"_Z1fSd", "f(std::iostream)", "f(std::iostream)"),
+            (b"_ZZ1gvEUt_", "g()::{unnamed type#1}", "g()::'unnamed'"),
+            (b"_ZZ1gvEUt0_", "g()::{unnamed type#2}", "g()::'unnamed0'"),
+            (b"_ZZ1fvENKUlvE_clEv",
+             "f()::{lambda()#1}::operator()() const",
+             "f()::'lambda'()::operator()() const"),
+            (b"_ZZ1fvENKUlvE0_clEv",
+             "f()::{lambda()#2}::operator()() const",
+             "f()::'lambda0'()::operator()() const"),
+        ];
+
+        for &(mangled, gnu_v3, llvm) in cases {
+            assert_eq!(demangle_with(mangled, Default::default()), gnu_v3);
+            assert_eq!(demangle_with(mangled, llvm_options.clone()), llvm);
+        }
+    }
+
+    #[test]
+    fn demangle_generic_lambda() {
+        // `[]<typename T>(T) {}`, called and passed as a template argument.
+        assert_demangle_symbol(b"_ZZ1fvENKUlTyT_E_clIiEEDaS_",
+                               "auto f()::{lambda<typename $T0>($T0)#1}\
+                                ::operator()<int>(int) const");
+        assert_demangle_symbol(b"_Z1gIZ1fvEUlTyT_E_EvT_",
+                               "void g<f()::{lambda<typename $T0>($T0)#1}>\
+                                (f()::{lambda<typename $T0>($T0)#1})");
+        assert_demangle_symbol(b"_ZZ1fvENKUlTyTyT_T0_E_clIicEEDaS_S0_",
+                               "auto f()::{lambda<typename $T0, typename $T1>\
+                                ($T0, $T1)#1}::operator()<int, char>(int, char) const");
+        assert_demangle_symbol(b"_ZZ1fvENKUlTnivE_clILi1EEEDav",
+                               "auto f()::{lambda<int $N0>()#1}::operator()<1>() const");
+
+        let llvm = DemangleOptions {
+            flavor: Flavor::Llvm,
+            ..Default::default()
+        };
+
+        // Unlike libiberty, LLVM numbers each kind of parameter separately.
+        let mangled = b"_ZZ1fvENKUlTyTniTyT_T1_E_clIiLi1EcEEDaS_S0_";
+        assert_eq!(demangle_with(mangled, llvm.clone()),
+                   "auto f()::'lambda'<typename $T, int $N, typename $T0>($T, $T0)\
+                    ::operator()<int, 1, char>(int, char) const");
+
+        // `[]<typename T, int N, typename... Ts>(T, Ts...) {}`
+        let mangled = b"_Z1gIZ1fvEUlTyTniTpTyT_DpT1_E0_EvT_";
+        assert_demangle_symbol(mangled,
+                               "void g<f()::{lambda<typename $T0, int $N1, \
+                                typename ...$T2>($T0, $T2...)#2}>(f()::{lambda\
+                                <typename $T0, int $N1, typename ...$T2>\
+                                ($T0, $T2...)#2})");
+        assert_eq!(demangle_with(mangled, llvm.clone()),
+                   "void g<f()::'lambda0'<typename $T, int $N, typename ...$T0>\
+                    ($T, $T0...)>(f()::'lambda0'<typename $T, int $N, \
+                    typename ...$T0>($T, $T0...))");
+
+        // `[]<template <typename> class TT, auto N>(TT<int>) {}`
+        assert_eq!(demangle_with(b"_Z1gIZ1fvEUlTtTyETnDaT_IiEE_EvT_", llvm),
+                   "void g<f()::'lambda'<template<typename $T> typename $TT, auto $N>\
+                    ($TT<int>)>(f()::'lambda'<template<typename $T> typename $TT, \
+                    auto $N>($TT<int>))");
+    }
+
+    #[test]
+    fn demangle_operator_keyword_expressions() {
+        let compact_options = DemangleOptions {
+            compact_keyword_operators: true,
+            ..Default::default()
+        };
+
+        // By default, match libiberty, which puts a space before the parenthesis.
+        let cases: &[(&[u8], &str, &str)] = &[
+            (b"_Z1fIiEvP1AIXstT_EE",
+             "void f<int>(A<sizeof (int)>*)",
+             "void f<int>(A<sizeof(int)>*)"),
+            (b"_Z1fIiEvP1AIXszLi1EEE",
+             "void f<int>(A<sizeof (1)>*)",
+             "void f<int>(A<sizeof(1)>*)"),
+            (b"_Z1fIiEvP1AIXatT_EE",
+             "void f<int>(A<alignof (int)>*)",
+             "void f<int>(A<alignof(int)>*)"),
+            (b"_Z1fIiEvP1AIXazLi1EEE",
+             "void f<int>(A<alignof (1)>*)",
+             "void f<int>(A<alignof(1)>*)"),
+            (b"_Z1fIiEvP1AIXnxLi1EEE",
+             "void f<int>(A<noexcept (1)>*)",
+             "void f<int>(A<noexcept(1)>*)"),
+            (b"_Z1fIiEvP1AIXtiT_EE",
+             "void f<int>(A<typeid (int)>*)",
+             "void f<int>(A<typeid(int)>*)"),
+            (b"_Z1fIiEvP1AIXteLi1EEE",
+             "void f<int>(A<typeid (1)>*)",
+             "void f<int>(A<typeid(1)>*)"),
+        ];
+
+        for &(mangled, spaced, compact) in cases {
+            assert_eq!(demangle_with(mangled, Default::default()), spaced);
+            assert_eq!(demangle_with(mangled, compact_options.clone()), compact);
+        }
+    }
+
+    #[test]
+    fn demangle_raw_template_params() {
+        let raw = DemangleOptions {
+            raw_template_params: true,
+            ..Default::default()
+        };
+
+        // Unbound template params can't be resolved, but can be printed raw.
+        let sym = Symbol::new(&b"_Z1fP1AIXcvT_Li1EEE"[..]).expect("should parse mangled symbol");
+        assert!(sym.demangle().is_err());
+        assert_eq!(demangle_with(b"_Z1fP1AIXcvT_Li1EEE", raw.clone()),
+                   "f(A<T_(1)>*)");
+        assert_eq!(demangle_with(b"_Z1fP1AIXplT_T0_EE", raw.clone()),
+                   "f(A<(T_)+(T0_)>*)");
+
+        // Bound ones are printed raw too, rather than resolved.
+        assert_eq!(demangle_with(b"_Z1fIicEvT_T0_", Default::default()),
+                   "void f<int, char>(int, char)");
+        assert_eq!(demangle_with(b"_Z1fIicEvT_T0_", raw.clone()),
+                   "void f<int, char>(T_, T0_)");
+        assert_eq!(demangle_with(b"_Z1fIRiEvOT_", raw.clone()),
+                   "void f<int&>(T_&&)");
+        assert_eq!(demangle_with(b"_Z1fIJicEEvDpT_", raw.clone()),
+                   "void f<int, char>(T_...)");
+        assert_eq!(demangle_with(b"_Z1fIFvvEEvM1AT_", raw.clone()),
+                   "void f<void ()>(T_ A::*)");
+    }
+
+    #[test]
+    fn demangle_unary_and_binary_operators() {
+        // `ng` is unary minus, `mi` binary minus, and likewise for `ps`/`pl`,
+        // `de`/`ml` and `ad`/`an`.
+        assert_demangle_symbol(b"_Z1fILi1ELi2EEv1AIXngT_EE", "void f<1, 2>(A<-(1)>)");
+        assert_demangle_symbol(b"_Z1fILi1ELi2EEv1AIXmiT_T0_EE", "void f<1, 2>(A<(1)-(2)>)");
+        assert_demangle_symbol(b"_Z1fILi1ELi2EEv1AIXpsT_EE", "void f<1, 2>(A<+(1)>)");
+        assert_demangle_symbol(b"_Z1fILi1ELi2EEv1AIXplT_T0_EE", "void f<1, 2>(A<(1)+(2)>)");
+        assert_demangle_symbol(b"_Z1fILi1ELi2EEv1AIXmlT_T0_EE", "void f<1, 2>(A<(1)*(2)>)");
+        assert_demangle_symbol(b"_Z1fILi1ELi2EEv1AIXanT_T0_EE", "void f<1, 2>(A<(1)&(2)>)");
+        assert_demangle_symbol(b"_Z1fIiEv1AIXszadL_Z1xEEE", "void f<int>(A<sizeof (&x)>)");
+
+        // The operand of a unary operator is parenthesized, so that it can't
+        // run together with the operator or be mistaken for a binary
+        // expression.
+        assert_demangle_symbol(b"_Z1fILi1ELi2EEv1AIXngmiT_T0_EE",
+                               "void f<1, 2>(A<-((1)-(2))>)");
+        assert_demangle_symbol(b"_Z1fILin1EEv1AIXngT_EE", "void f<-1>(A<-(-1)>)");
+        assert_demangle_symbol(b"_Z1fILi1EEv1AIXngngT_EE", "void f<1>(A<-(-(1))>)");
+    }
+
+    #[test]
+    fn demangle_cv_qualified_member_function_template() {
+        assert_demangle_symbol(b"_ZNK1A1fIiEEvv", "void A::f<int>() const");
+        assert_demangle_symbol(b"_ZNK1AIcE1fIiEEvT_", "void A<char>::f<int>(int) const");
+        assert_demangle_symbol(b"_ZNVK1A1fIiEEvv", "void A::f<int>() const volatile");
+        assert_demangle_symbol(b"_ZNKR1A1fIiEEvv", "void A::f<int>() const &");
+    }
+
+    #[test]
+    fn demangle_constrained_auto() {
+        assert_demangle_symbol(b"_Z1fIiEDk1Cv", "C auto f<int>()");
+        assert_demangle_symbol(b"_Z1fIiEDKN1A1CEv", "A::C decltype(auto) f<int>()");
+        assert_demangle_symbol(b"_Z1fIiEDk1CIT_Ev", "C<int> auto f<int>()");
+    }
+
+    #[test]
+    fn demangle_max_template_depth() {
+        let options = |max_template_depth| {
+            DemangleOptions {
+                max_template_depth: max_template_depth,
+                ..Default::default()
+            }
+        };
+
+        let mangled = b"_Z1fI1AI1BI1CIiEEEEvv";
+        assert_eq!(demangle_with(mangled, options(None)), "void f<A<B<C<int> > > >()");
+        assert_eq!(demangle_with(mangled, options(Some(3))), "void f<A<B<C<...> > > >()");
+        assert_eq!(demangle_with(mangled, options(Some(2))), "void f<A<B<...> > >()");
+        assert_eq!(demangle_with(mangled, options(Some(1))), "void f<A<...> >()");
+        assert_eq!(demangle_with(mangled, options(Some(0))), "void f<...>()");
+
+        // Sibling template args are not nested within each other.
+        assert_eq!(demangle_with(b"_ZN1AIiE1fI1BIcEEEvv", options(Some(1))),
+                   "void A<int>::f<B<...> >()");
+    }
+
+    #[test]
+    fn matches_prefix() {
+        let symbols: &[&[u8]] = &[b"_ZN2ns3sub3fooEv",
+                                  b"_ZN2ns3sub3BarC1Ev",
+                                  b"_ZN2ns3sub3BazIiE3quxEv",
+                                  b"_ZZN2ns3sub3fooEvE5count",
+                                  b"_ZN2ns3subplERKiS2_",
+                                  b"_ZN2ns3subE",
+                                  b"_ZN2ns5other3fooEv",
+                                  b"_ZN2ns3fooEv",
+                                  b"_ZN3sub2ns3fooEv",
+                                  b"_Z3foov",
+                                  b"_ZTVN2ns3sub3BarE"];
+
+        let matching: Vec<&[u8]> = symbols.iter()
+            .cloned()
+            .filter(|mangled| {
+                Symbol::new(*mangled)
+                    .expect("should parse mangled symbol")
+                    .matches_prefix(&["ns", "sub"])
+            })
+            .collect();
+
+        assert_eq!(matching,
+                   vec![&b"_ZN2ns3sub3fooEv"[..],
+                        &b"_ZN2ns3sub3BarC1Ev"[..],
+                        &b"_ZN2ns3sub3BazIiE3quxEv"[..],
+                        &b"_ZZN2ns3sub3fooEvE5count"[..],
+                        &b"_ZN2ns3subplERKiS2_"[..]]);
+
+        fn matches(mangled: &[u8], scope: &[&str]) -> bool {
+            Symbol::new(mangled).expect("should parse mangled symbol").matches_prefix(scope)
+        }
+
+        assert!(matches(b"_Z3foov", &[]));
+        assert!(!matches(b"_ZTV3Foo", &[]));
+        assert!(matches(b"_ZNSt6vectorIiE9push_backERKi", &["std", "vector"]));
+        assert!(matches(b"_ZNKSs4sizeEv", &["std", "string"]));
+        assert!(matches(b"_ZSt4swapIiEvRT_S1_", &["std"]));
+        assert!(matches(b"_ZZN2ns3sub3fooEvE5count", &["ns", "sub", "foo"]));
+    }
+
+    #[test]
+    fn ctor_dtor_kind() {
+        fn kind(mangled: &[u8]) -> Option<CtorDtorKind> {
+            Symbol::new(mangled).expect("should parse mangled symbol").ctor_dtor_kind()
+        }
+
+        assert_eq!(kind(b"_ZN5space3FooC1Ev"), Some(CtorDtorKind::CompleteConstructor));
+        assert_eq!(kind(b"_ZN5space3FooC2ERKS0_"), Some(CtorDtorKind::BaseConstructor));
+        assert_eq!(kind(b"_ZN3FooC3Ev"),
+                   Some(CtorDtorKind::CompleteAllocatingConstructor));
+        assert_eq!(kind(b"_ZN5space3FooD0Ev"), Some(CtorDtorKind::DeletingDestructor));
+        assert_eq!(kind(b"_ZN3FooIiED1Ev"), Some(CtorDtorKind::CompleteDestructor));
+        assert_eq!(kind(b"_ZNSt6vectorIiED2Ev"), Some(CtorDtorKind::BaseDestructor));
+        assert_eq!(kind(b"_ZN3FooC1IiEET_"), Some(CtorDtorKind::CompleteConstructor));
+        assert_eq!(kind(b"_ZN3FooC4Ev"), Some(CtorDtorKind::MaybeInChargeConstructor));
+        assert_eq!(kind(b"_ZN3FooD4Ev"), Some(CtorDtorKind::MaybeInChargeDestructor));
+
+        assert_eq!(kind(b"_ZN5space3fooEibc"), None);
+        assert_eq!(kind(b"_ZZN3FooC1EvE3bar"), None);
+        assert_eq!(kind(b"_ZTV3Foo"), None);
+
+        assert!(CtorDtorKind::BaseConstructor.is_constructor());
+        assert!(CtorDtorKind::DeletingDestructor.is_destructor());
+        assert!(CtorDtorKind::MaybeInChargeConstructor.is_constructor());
+        assert!(CtorDtorKind::MaybeInChargeDestructor.is_destructor());
+    }
+
+    #[test]
+    fn demangle_recursive_substitution_is_typed_error() {
+        // The template's only argument `T_` refers back to itself.
+        let input = b"_Z1fIT_EvT_";
+        let mut subs = SubstitutionTable::new();
+        let (mangled, tail) = MangledName::parse(&mut subs, IndexStr::from(&input[..]))
+            .expect("should parse");
+        assert!(tail.is_empty());
+
+        let mut buf: Vec<u8> = vec![];
+        let mut ctx = DemangleContext::new(&subs, &input[..], &mut buf);
+        match mangled.demangle(&mut ctx, None) {
+            Err(DemangleError::Demangle(Error::RecursiveDemangling)) => {}
+            otherwise => panic!("expected a recursive demangling error, found {:?}", otherwise),
+        }
+    }
 }
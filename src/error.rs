@@ -2,11 +2,16 @@
 
 use std::error;
 use std::fmt;
+use std::io;
 
 /// Errors that can occur while demangling a symbol.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Error {
     /// The mangled symbol ends abruptly.
+    ///
+    /// This is reported when the input is a truncated prefix of what could
+    /// otherwise be a well-formed mangled symbol, such as `_Z` or `_ZN3foo`,
+    /// so callers reading partial data may retry once they have more of it.
     UnexpectedEnd,
 
     /// The mangled symbol is not well-formed.
@@ -31,6 +36,10 @@ pub enum Error {
     /// The act of demangling some part of the AST attempted to demangle itself
     /// again.
     RecursiveDemangling,
+
+    /// The mangled symbol needs more substitution table entries than the
+    /// `ParseOptions::max_substitutions` it was parsed with allows.
+    TooManySubstitutions,
 }
 
 impl fmt::Display for Error {
@@ -55,6 +64,9 @@ impl fmt::Display for Error {
             Error::RecursiveDemangling => {
                 write!(f, "demangling some part of the AST attempted to demangle itself again")
             }
+            Error::TooManySubstitutions => {
+                write!(f, "the mangled symbol needs too many substitution table entries")
+            }
         }
     }
 }
@@ -69,9 +81,74 @@ impl error::Error for Error {
             Error::BadFunctionArgReference => "reference to a function arg that is either out-of-bounds, or in a context without function args",
             Error::Overflow => "an overflow or underflow would occur when parsing an integer in a mangled symbol",
             Error::RecursiveDemangling => "demangling some part of the AST attempted to demangle itself again",
+            Error::TooManySubstitutions => "the mangled symbol needs too many substitution table entries",
         }
     }
 }
 
 /// A demangling result of `T` or a `cpp_demangle::error::Error`.
 pub type Result<T> = ::std::result::Result<T, Error>;
+
+/// Errors that can occur while demangling a symbol into some output.
+#[derive(Debug)]
+pub enum DemangleError {
+    /// Writing the demangled output failed.
+    Io(io::Error),
+
+    /// The input could not be parsed as a mangled symbol, for example because
+    /// it lacks the `_Z` prefix or is truncated. Only functions that parse as
+    /// well as demangle, such as `demangle_bytes`, report this.
+    Parse(Error),
+
+    /// The AST is semantically malformed, for example it contains a template
+    /// arg reference that cannot be resolved, or a substitution that refers
+    /// back to itself.
+    Demangle(Error),
+}
+
+impl From<io::Error> for DemangleError {
+    fn from(e: io::Error) -> DemangleError {
+        DemangleError::Io(e)
+    }
+}
+
+impl From<Error> for DemangleError {
+    fn from(e: Error) -> DemangleError {
+        DemangleError::Demangle(e)
+    }
+}
+
+impl fmt::Display for DemangleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DemangleError::Io(ref e) => write!(f, "error writing demangled output: {}", e),
+            DemangleError::Parse(ref e) => fmt::Display::fmt(e, f),
+            DemangleError::Demangle(ref e) => fmt::Display::fmt(e, f),
+        }
+    }
+}
+
+impl error::Error for DemangleError {
+    fn description(&self) -> &str {
+        match *self {
+            DemangleError::Io(ref e) => e.description(),
+            DemangleError::Parse(ref e) => e.description(),
+            DemangleError::Demangle(ref e) => e.description(),
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            // The sink failed, so the IO error is the underlying cause.
+            DemangleError::Io(ref e) => Some(e),
+            // A parse or semantic error is the whole story, and is displayed
+            // as is.
+            DemangleError::Parse(_) |
+            DemangleError::Demangle(_) => None,
+        }
+    }
+}
+
+/// A demangling-to-output result of `T` or a
+/// `cpp_demangle::error::DemangleError`.
+pub type DemangleResult<T> = ::std::result::Result<T, DemangleError>;
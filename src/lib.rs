@@ -38,10 +38,169 @@ pub mod error;
 mod index_str;
 mod subs;
 
-use ast::{Demangle, Parse};
-use error::{Error, Result};
+use ast::{Demangle, Parse, Remangle, SemanticEq};
+use error::{DemangleError, DemangleResult, Error, Result};
 use index_str::IndexStr;
+use std::borrow::Cow;
 use std::fmt;
+use std::io;
+
+/// Options to control how a symbol is parsed.
+///
+/// ```
+/// use cpp_demangle::{ParseOptions, Symbol};
+/// use cpp_demangle::error::Error;
+///
+/// let options = ParseOptions {
+///     max_substitutions: Some(2),
+///     ..Default::default()
+/// };
+///
+/// // `space` fits within the limit...
+/// assert!(Symbol::new_with_options(&b"_ZN5space3fooEv"[..], options.clone()).is_ok());
+///
+/// // ...but `space`, `space::Bar` and `space::Bar*` don't.
+/// assert_eq!(Symbol::new_with_options(&b"_ZN5space3fooEPNS_3BarE"[..], options),
+///            Err(Error::TooManySubstitutions));
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// The maximum number of entries to allow in the symbol's substitution
+    /// table. Parsing a symbol that needs more fails with
+    /// `Error::TooManySubstitutions`, which bounds the memory a crafted symbol
+    /// can make the parse use. If `None`, the table may grow without limit.
+    pub max_substitutions: Option<usize>,
+}
+
+/// Options to control how a parsed symbol is demangled.
+///
+/// ```
+/// use cpp_demangle::{DemangleOptions, Symbol};
+///
+/// let sym = Symbol::new(&b"_Z1fI1AI1BIiEEEvv"[..])
+///     .expect("Could not parse mangled symbol!");
+///
+/// let options = DemangleOptions {
+///     max_template_depth: Some(1),
+///     ..Default::default()
+/// };
+/// let demangled = sym.demangle_with_options(options).unwrap();
+/// assert_eq!(demangled, "void f<A<...> >()");
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DemangleOptions {
+    /// The maximum depth of nested template arguments to print. Template
+    /// arguments nested any deeper than this are elided as `<...>`. If `None`,
+    /// all template arguments are printed.
+    pub max_template_depth: Option<usize>,
+
+    /// How to spell complex and imaginary types, such as `Cf` and `Gf`.
+    pub complex_spelling: ComplexSpelling,
+
+    /// If true, print keyword operators without a space before their
+    /// parenthesized operand, as in `sizeof(int)`. By default, we match
+    /// libiberty's `sizeof (int)`. This applies to `sizeof`, `alignof`,
+    /// `typeid`, and `noexcept`.
+    pub compact_keyword_operators: bool,
+
+    /// If true, put spaces around binary operators, as in `(a) + (b)`, and
+    /// after the comma operator, as in `a, b`. By default, we match
+    /// libiberty's `(a)+(b)` and `a,b`.
+    pub spaced_binary_operators: bool,
+
+    /// If set, break long lines after the comma separating two function or
+    /// template arguments, once the line is at least this many bytes wide.
+    /// Lines may still exceed this width, since nothing else is broken. If
+    /// `None`, the demangled symbol is always printed on a single line.
+    pub max_width: Option<usize>,
+
+    /// Which demangler's spellings to follow where the output is otherwise
+    /// ambiguous, such as the type of `nullptr`, and unnamed types and
+    /// lambdas.
+    pub flavor: Flavor,
+
+    /// If true, print references to template parameters as they were
+    /// mangled, as in `T_` and `T0_`, instead of the template arguments they
+    /// refer to. This also allows demangling symbols whose template parameters
+    /// are not bound to any arguments.
+    pub raw_template_params: bool,
+}
+
+/// The spelling used for complex and imaginary types.
+///
+/// In either case, the qualifier follows the type it modifies, as in libiberty.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ComplexSpelling {
+    /// The C99 spelling: `float _Complex` and `float _Imaginary`.
+    C,
+    /// The GNU extension spelling: `float __complex__` and `float
+    /// __imaginary__`.
+    Gnu,
+}
+
+impl Default for ComplexSpelling {
+    fn default() -> ComplexSpelling {
+        ComplexSpelling::C
+    }
+}
+
+/// The demangler whose spellings to follow.
+///
+/// The flavors only differ for constructs that have no single canonical
+/// spelling; everything else is printed the same way by both.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Flavor {
+    /// GNU's libiberty, as used by `c++filt`: `decltype(nullptr)`,
+    /// `{unnamed type#1}`, and `{lambda(int)#1}`.
+    GnuV3,
+    /// LLVM's demangler: `std::nullptr_t`, `'unnamed'`, and `'lambda'(int)`.
+    Llvm,
+}
+
+impl Default for Flavor {
+    fn default() -> Flavor {
+        Flavor::GnuV3
+    }
+}
+
+/// A semantic region of a demangled symbol, whose boundaries are reported to
+/// the markup hook passed to `Symbol::demangle_to_with_markup`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MarkupRegion {
+    /// An unqualified name, such as `foo` in `ns::foo<int>`, including
+    /// operator, constructor, and destructor names.
+    Name,
+    /// A whole type in a template argument, function parameter, or return
+    /// type position.
+    Type,
+    /// A template argument list, including its angle brackets.
+    TemplateArgs,
+}
+
+/// The start or end of a `MarkupRegion`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Markup {
+    /// The region begins with the next byte of output.
+    Start(MarkupRegion),
+    /// The region ended with the last byte of output.
+    End(MarkupRegion),
+}
+
+/// Statistics about parsing and demangling a symbol, for diagnosing symbols
+/// that are slow to demangle or that demangle to something huge.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct DemangleStats {
+    /// The number of components in the symbol's substitution table.
+    pub substitutions: usize,
+
+    /// The deepest that major productions (such as `Encoding`, `Name`, and
+    /// `TypeHandle`) were nested within each other while parsing.
+    pub max_parse_depth: usize,
+
+    /// The length in bytes of the symbol demangled with the default options,
+    /// or `None` if it fails to demangle.
+    pub demangled_len: Option<usize>,
+}
 
 /// A `Symbol` which owns the underlying storage for the mangled name.
 pub type OwnedSymbol = Symbol<Vec<u8>>;
@@ -65,6 +224,12 @@ impl<T> Symbol<T>
 {
     /// Given some raw storage, parse the mangled symbol from it.
     ///
+    /// The input must start with the `_Z` (or `__Z`) mangling prefix, or be
+    /// one of GCC's `_GLOBAL_` static constructor or destructor names, and
+    /// anything else is quickly rejected with `Error::UnexpectedText`. Use
+    /// `Symbol::new_lenient` to also accept bare types and encodings without
+    /// the prefix.
+    ///
     /// ```
     /// use cpp_demangle::Symbol;
     ///
@@ -93,17 +258,72 @@ impl<T> Symbol<T>
     /// );
     /// ```
     pub fn new(raw: T) -> Result<Symbol<T>> {
+        try!(Symbol::check_prefix(&raw));
+        Symbol::parse(raw, subs::SubstitutionTable::new())
+    }
+
+    /// Like `Symbol::new`, but also record the span of input that each major
+    /// production (such as `Encoding`, `Name`, `SourceName`, and `TypeHandle`)
+    /// was parsed from. This is useful for tools that map parts of the
+    /// demangled output back to the mangled input.
+    ///
+    /// ```
+    /// use cpp_demangle::Symbol;
+    ///
+    /// let sym = Symbol::new_with_spans(&b"_Z3fooi"[..])
+    ///     .expect("Could not parse mangled symbol!");
+    ///
+    /// let spans = sym.spans().expect("spans were recorded");
+    /// let source_name = spans.iter()
+    ///     .find(|span| span.production == "SourceName")
+    ///     .expect("the function name is a source name");
+    /// assert_eq!((source_name.start, source_name.end), (2, 6));
+    ///
+    /// assert!(Symbol::new(&b"_Z3fooi"[..]).unwrap().spans().is_none());
+    /// ```
+    pub fn new_with_spans(raw: T) -> Result<Symbol<T>> {
+        try!(Symbol::check_prefix(&raw));
+        Symbol::parse(raw, subs::SubstitutionTable::with_spans())
+    }
+
+    /// Like `Symbol::new`, but parse with the given options. See
+    /// `ParseOptions` for an example.
+    pub fn new_with_options(raw: T, options: ParseOptions) -> Result<Symbol<T>> {
+        try!(Symbol::check_prefix(&raw));
         let mut substitutions = subs::SubstitutionTable::new();
+        substitutions.set_max_len(options.max_substitutions);
+        Symbol::parse(raw, substitutions)
+    }
 
-        let parsed = {
-            let input = IndexStr::new(raw.as_ref());
-            let (parsed, tail) = try!(ast::MangledName::parse(&mut substitutions, input));
-            if tail.is_empty() {
-                parsed
-            } else {
-                return Err(Error::UnexpectedText.into());
-            }
-        };
+    fn check_prefix(raw: &T) -> Result<()> {
+        let input = raw.as_ref();
+        if !input.starts_with(b"_Z") && !input.starts_with(b"__Z") &&
+           !input.starts_with(b"_GLOBAL_") {
+            return Err(Error::UnexpectedText);
+        }
+        Ok(())
+    }
+
+    /// Like `Symbol::new`, but does not require the `_Z` mangling prefix, so
+    /// that bare types and encodings (as found in libiberty's test suite) can
+    /// also be parsed. This is slower to reject input that isn't a symbol at
+    /// all.
+    ///
+    /// ```
+    /// use cpp_demangle::Symbol;
+    ///
+    /// assert!(Symbol::new(&b"St9bad_alloc"[..]).is_err());
+    ///
+    /// let sym = Symbol::new_lenient(&b"St9bad_alloc"[..])
+    ///     .expect("Could not parse mangled symbol!");
+    /// assert_eq!(format!("{}", sym), "std::bad_alloc");
+    /// ```
+    pub fn new_lenient(raw: T) -> Result<Symbol<T>> {
+        Symbol::parse(raw, subs::SubstitutionTable::new())
+    }
+
+    fn parse(raw: T, mut substitutions: subs::SubstitutionTable) -> Result<Symbol<T>> {
+        let parsed = try!(parse_all(&mut substitutions, raw.as_ref()));
 
         let symbol = Symbol {
             raw: raw,
@@ -124,9 +344,362 @@ substitutions = {:#?}",
 
         Ok(symbol)
     }
+
+    /// The spans of input that the major productions of this symbol were
+    /// parsed from, in the order that their parses completed, or `None` if the
+    /// symbol was not parsed with `Symbol::new_with_spans`. Like the
+    /// substitution table, this can include productions parsed while trying an
+    /// alternative that was later abandoned.
+    pub fn spans(&self) -> Option<&[ast::ParsedSpan]> {
+        self.substitutions.spans()
+    }
+
+    /// Get statistics about parsing and demangling this symbol.
+    ///
+    /// This demangles the symbol to measure its length, without keeping the
+    /// demangled output around.
+    ///
+    /// ```
+    /// use cpp_demangle::Symbol;
+    ///
+    /// let sym = Symbol::new(&b"_Z1fP3FooS0_"[..])
+    ///     .expect("Could not parse mangled symbol!");
+    /// let stats = sym.stats();
+    ///
+    /// // `Foo` and `Foo*` are both substitutable.
+    /// assert_eq!(stats.substitutions, 2);
+    /// assert_eq!(stats.demangled_len, Some("f(Foo*, Foo*)".len()));
+    /// ```
+    pub fn stats(&self) -> DemangleStats {
+        let mut ctx = ast::DemangleContext::new(&self.substitutions,
+                                                self.raw.as_ref(),
+                                                io::sink());
+        let demangled_len = match self.parsed.demangle(&mut ctx, None) {
+            Ok(()) => Some(ctx.bytes_written()),
+            Err(_) => None,
+        };
+
+        DemangleStats {
+            substitutions: self.substitutions.len(),
+            max_parse_depth: self.substitutions.max_depth(),
+            demangled_len: demangled_len,
+        }
+    }
+
+    /// Demangle the symbol and return it as a `String`.
+    ///
+    /// Unlike formatting the symbol with `Display`, which can only report a
+    /// bare `fmt::Error`, this reports why demangling failed.
+    ///
+    /// ```
+    /// use cpp_demangle::Symbol;
+    /// use cpp_demangle::error::{DemangleError, Error};
+    ///
+    /// let sym = Symbol::new(&b"_ZN5space3fooEibc"[..])
+    ///     .expect("Could not parse mangled symbol!");
+    /// assert_eq!(sym.demangle().unwrap(), "space::foo(int, bool, char)");
+    ///
+    /// // This template's only argument is a reference to itself.
+    /// let sym = Symbol::new(&b"_Z1fIT_EvT_"[..])
+    ///     .expect("Could not parse mangled symbol!");
+    /// match sym.demangle() {
+    ///     Err(DemangleError::Demangle(Error::RecursiveDemangling)) => {}
+    ///     otherwise => panic!("unexpected result: {:?}", otherwise),
+    /// }
+    /// ```
+    pub fn demangle(&self) -> DemangleResult<String> {
+        self.demangle_with_options(Default::default())
+    }
+
+    /// Demangle the symbol with the given options and return it as a
+    /// `String`.
+    pub fn demangle_with_options(&self, options: DemangleOptions) -> DemangleResult<String> {
+        let mut out = vec![];
+        try!(self.demangle_to(&mut out, options));
+        Ok(String::from_utf8_lossy(&out).into_owned())
+    }
+
+    /// Demangle the symbol with the given options, writing it to `out`.
+    ///
+    /// The parsed symbol isn't modified by demangling, so it can be demangled
+    /// any number of times, with different options or into different sinks,
+    /// without parsing it again.
+    ///
+    /// ```
+    /// use cpp_demangle::{DemangleOptions, Symbol};
+    ///
+    /// let sym = Symbol::new(&b"_Z1fI1AI1BIiEEEvv"[..])
+    ///     .expect("Could not parse mangled symbol!");
+    ///
+    /// let mut full = vec![];
+    /// sym.demangle_to(&mut full, Default::default()).unwrap();
+    /// assert_eq!(full, b"void f<A<B<int> > >()");
+    ///
+    /// let options = DemangleOptions {
+    ///     max_template_depth: Some(1),
+    ///     ..Default::default()
+    /// };
+    /// let mut elided = vec![];
+    /// sym.demangle_to(&mut elided, options).unwrap();
+    /// assert_eq!(elided, b"void f<A<...> >()");
+    /// ```
+    pub fn demangle_to<W>(&self, out: W, options: DemangleOptions) -> DemangleResult<()>
+        where W: io::Write
+    {
+        let mut ctx = ast::DemangleContext::new_with_options(&self.substitutions,
+                                                             self.raw.as_ref(),
+                                                             out,
+                                                             options);
+        self.parsed.demangle(&mut ctx, None)
+    }
+
+    /// Demangle the symbol to the given output like `demangle_to`, calling
+    /// `markup` at the start and end of each `MarkupRegion`.
+    ///
+    /// The hook may write markers to the output it is given, such as ANSI
+    /// escape codes to colorize names, without disturbing the demangler's
+    /// spacing or line breaking. Regions nest, and they are always closed in
+    /// the reverse order that they were opened.
+    ///
+    /// ```
+    /// use cpp_demangle::{Markup, MarkupRegion, Symbol};
+    /// use std::io::Write;
+    ///
+    /// let sym = Symbol::new(&b"_ZN5space3fooEi"[..])
+    ///     .expect("Could not parse mangled symbol!");
+    ///
+    /// let mut bolded = vec![];
+    /// sym.demangle_to_with_markup(&mut bolded, Default::default(), |markup, out| {
+    ///     match markup {
+    ///         Markup::Start(MarkupRegion::Name) => out.write_all(b"\x1b[1m"),
+    ///         Markup::End(MarkupRegion::Name) => out.write_all(b"\x1b[0m"),
+    ///         _ => Ok(()),
+    ///     }
+    /// }).unwrap();
+    /// assert_eq!(bolded, &b"\x1b[1mspace\x1b[0m::\x1b[1mfoo\x1b[0m(int)"[..]);
+    /// ```
+    pub fn demangle_to_with_markup<W, F>(&self,
+                                         out: W,
+                                         options: DemangleOptions,
+                                         mut markup: F)
+                                         -> DemangleResult<()>
+        where W: io::Write,
+              F: FnMut(Markup, &mut io::Write) -> io::Result<()>
+    {
+        let mut ctx = ast::DemangleContext::new_with_options(&self.substitutions,
+                                                             self.raw.as_ref(),
+                                                             out,
+                                                             options);
+        ctx.set_markup(&mut markup);
+        self.parsed.demangle(&mut ctx, None)
+    }
+
+    /// Is this symbol semantically equal to `other`?
+    ///
+    /// Unlike `==`, this compares what the symbols' back references refer to,
+    /// rather than their indices into the substitution table, so the same
+    /// entity mangled with and without substitutions is considered equal.
+    ///
+    /// ```
+    /// use cpp_demangle::Symbol;
+    ///
+    /// // Both of these are `f(Foo*, Foo*)`, but the first uses a substitution
+    /// // for the second parameter's type.
+    /// let with_subs = Symbol::new(&b"_Z1fP3FooS0_"[..])
+    ///     .expect("Could not parse mangled symbol!");
+    /// let without_subs = Symbol::new(&b"_Z1fP3FooP3Foo"[..])
+    ///     .expect("Could not parse mangled symbol!");
+    ///
+    /// assert!(with_subs.semantic_eq(&without_subs));
+    /// ```
+    pub fn semantic_eq<U>(&self, other: &Symbol<U>) -> bool
+        where U: AsRef<[u8]>
+    {
+        let ctx = ast::SemanticEqContext::new(&self.substitutions,
+                                              self.raw.as_ref(),
+                                              &other.substitutions,
+                                              other.raw.as_ref());
+        self.parsed.semantic_eq(&ctx, &other.parsed)
+    }
+
+    /// Serialize this symbol's parsed AST back into a mangled symbol.
+    ///
+    /// Substitutions are regenerated as the AST is written out, so the result
+    /// is the symbol that was parsed, normalized to a single `_Z` prefix.
+    /// Parsing the result and remangling it again yields the same bytes.
+    ///
+    /// Returns `None` if the symbol contains a construct that remangling does
+    /// not support yet, such as special names, local names, and expressions.
+    ///
+    /// ```
+    /// use cpp_demangle::Symbol;
+    ///
+    /// let sym = Symbol::new(&b"_ZN5space3fooEPKcS1_"[..])
+    ///     .expect("Could not parse mangled symbol!");
+    /// assert_eq!(sym.remangle(), Some(b"_ZN5space3fooEPKcS1_".to_vec()));
+    ///
+    /// let sym = Symbol::new(&b"_ZTV3Foo"[..])
+    ///     .expect("Could not parse mangled symbol!");
+    /// assert_eq!(sym.remangle(), None);
+    /// ```
+    pub fn remangle(&self) -> Option<Vec<u8>> {
+        let mut ctx = ast::RemangleContext::new(&self.substitutions, self.raw.as_ref());
+        match self.parsed.remangle(&mut ctx) {
+            Ok(()) => Some(ctx.finish()),
+            Err(()) => None,
+        }
+    }
+
+    /// Demangle just the return type of this symbol with the given options,
+    /// if it is a function whose return type is part of its mangled name.
+    ///
+    /// Only function templates (other than constructors and destructors) have
+    /// their return types mangled, so this returns `None` for any other
+    /// symbol.
+    ///
+    /// ```
+    /// use cpp_demangle::Symbol;
+    ///
+    /// let sym = Symbol::new(&b"_Z1fIiEPKcT_"[..])
+    ///     .expect("Could not parse mangled symbol!");
+    /// assert_eq!(sym.demangle().unwrap(), "char const* f<int>(int)");
+    /// let part = sym.demangle_return_type(Default::default()).unwrap();
+    /// assert_eq!(part, Some("char const*".into()));
+    ///
+    /// let sym = Symbol::new(&b"_ZN5space3fooEibc"[..])
+    ///     .expect("Could not parse mangled symbol!");
+    /// assert_eq!(sym.demangle_return_type(Default::default()).unwrap(), None);
+    /// ```
+    pub fn demangle_return_type(&self,
+                                options: DemangleOptions)
+                                -> DemangleResult<Option<String>> {
+        self.demangle_part(options, |parsed, ctx| parsed.demangle_return_type(ctx, None))
+    }
+
+    /// Demangle just the function that this symbol is declared within with
+    /// the given options, if it is a local entity, such as a function's static
+    /// variable.
+    ///
+    /// Returns `None` for any symbol that is not a local entity.
+    ///
+    /// ```
+    /// use cpp_demangle::Symbol;
+    ///
+    /// let sym = Symbol::new(&b"_ZZ4mainE5count"[..])
+    ///     .expect("Could not parse mangled symbol!");
+    /// assert_eq!(sym.demangle().unwrap(), "main::count");
+    /// let part = sym.demangle_enclosing_function(Default::default()).unwrap();
+    /// assert_eq!(part, Some("main".into()));
+    ///
+    /// let sym = Symbol::new(&b"_ZN5space3fooEibc"[..])
+    ///     .expect("Could not parse mangled symbol!");
+    /// assert_eq!(sym.demangle_enclosing_function(Default::default()).unwrap(), None);
+    /// ```
+    pub fn demangle_enclosing_function(&self,
+                                       options: DemangleOptions)
+                                       -> DemangleResult<Option<String>> {
+        self.demangle_part(options, |parsed, ctx| parsed.demangle_enclosing_function(ctx, None))
+    }
+
+    /// Demangle just the template arguments of this symbol with the given
+    /// options, if it is a template function or variable.
+    ///
+    /// Returns `None` for any symbol that is not a template.
+    ///
+    /// ```
+    /// use cpp_demangle::Symbol;
+    ///
+    /// let sym = Symbol::new(&b"_Z3fooIicEvv"[..])
+    ///     .expect("Could not parse mangled symbol!");
+    /// assert_eq!(sym.demangle().unwrap(), "void foo<int, char>()");
+    /// let part = sym.demangle_template_args(Default::default()).unwrap();
+    /// assert_eq!(part, Some("<int, char>".into()));
+    ///
+    /// let sym = Symbol::new(&b"_ZN5space3fooEibc"[..])
+    ///     .expect("Could not parse mangled symbol!");
+    /// assert_eq!(sym.demangle_template_args(Default::default()).unwrap(), None);
+    /// ```
+    pub fn demangle_template_args(&self,
+                                  options: DemangleOptions)
+                                  -> DemangleResult<Option<String>> {
+        self.demangle_part(options, |parsed, ctx| parsed.demangle_template_args(ctx, None))
+    }
+
+    /// Demangle the part of this symbol that `part` writes, if it reports
+    /// that the symbol has that part.
+    fn demangle_part<F>(&self,
+                        options: DemangleOptions,
+                        part: F)
+                        -> DemangleResult<Option<String>>
+        where F: FnOnce(&ast::MangledName, &mut ast::DemangleContext<&mut Vec<u8>>)
+                        -> DemangleResult<bool>
+    {
+        let mut out = vec![];
+        let has_part = {
+            let mut ctx = ast::DemangleContext::new_with_options(&self.substitutions,
+                                                                 self.raw.as_ref(),
+                                                                 &mut out,
+                                                                 options);
+            try!(part(&self.parsed, &mut ctx))
+        };
+
+        if has_part {
+            Ok(Some(String::from_utf8_lossy(&out).into_owned()))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Is this symbol a function or variable declared within the given scope,
+    /// whose identifiers are listed outermost first?
+    ///
+    /// This walks the symbol's scope chain without demangling it, and stops at
+    /// the first identifier that doesn't match. Template arguments are not
+    /// part of the scope chain, and neither is the entity's own name. Special
+    /// names, such as vtables, are not declared within any scope.
+    ///
+    /// ```
+    /// use cpp_demangle::Symbol;
+    ///
+    /// let sym = Symbol::new(&b"_ZN5space3FooIiE3barEv"[..])
+    ///     .expect("Could not parse mangled symbol!");
+    /// assert_eq!(sym.demangle().unwrap(), "space::Foo<int>::bar()");
+    /// assert!(sym.matches_prefix(&["space"]));
+    /// assert!(sym.matches_prefix(&["space", "Foo"]));
+    /// assert!(!sym.matches_prefix(&["space", "Foo", "bar"]));
+    /// assert!(!sym.matches_prefix(&["Foo"]));
+    /// ```
+    pub fn matches_prefix(&self, scope: &[&str]) -> bool {
+        let scope: Vec<&[u8]> = scope.iter().map(|s| s.as_bytes()).collect();
+        self.parsed.matches_scope(&self.substitutions, self.raw.as_ref(), &scope)
+    }
 }
 
 impl<T> Symbol<T> {
+    /// If this symbol is a constructor or destructor, get which kind of
+    /// constructor or destructor it is. Otherwise, return `None`.
+    ///
+    /// ```
+    /// use cpp_demangle::Symbol;
+    /// use cpp_demangle::ast::CtorDtorKind;
+    ///
+    /// let sym = Symbol::new(&b"_ZN5space3FooC1Ev"[..])
+    ///     .expect("Could not parse mangled symbol!");
+    /// assert_eq!(sym.ctor_dtor_kind(), Some(CtorDtorKind::CompleteConstructor));
+    ///
+    /// let sym = Symbol::new(&b"_ZN5space3fooEibc"[..])
+    ///     .expect("Could not parse mangled symbol!");
+    /// assert_eq!(sym.ctor_dtor_kind(), None);
+    /// ```
+    pub fn ctor_dtor_kind(&self) -> Option<ast::CtorDtorKind> {
+        self.parsed.ctor_dtor_kind(&self.substitutions)
+    }
+
+    /// Is this symbol a constructor or destructor?
+    pub fn is_ctor_or_dtor(&self) -> bool {
+        self.ctor_dtor_kind().is_some()
+    }
+
     /// Parse a mangled symbol from input and return it and the trailing tail of
     /// bytes that come after the symbol.
     ///
@@ -178,13 +751,139 @@ impl<T> fmt::Display for Symbol<T>
     where T: AsRef<[u8]>
 {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let mut out = vec![];
+        let demangled = try!(self.demangle().map_err(|_| fmt::Error));
+        write!(f, "{}", demangled)
+    }
+}
+
+// Parse all of `input` as a mangled name, building up `substitutions`.
+fn parse_all(substitutions: &mut subs::SubstitutionTable,
+             input: &[u8])
+             -> Result<ast::MangledName> {
+    let result = ast::MangledName::parse(substitutions, IndexStr::new(input));
+    // Refusing a substitution can send the parse down some other alternative,
+    // so report the limit rather than whatever happened next.
+    if substitutions.exceeded_max_len() {
+        return Err(Error::TooManySubstitutions);
+    }
+    let (parsed, tail) = try!(result);
+    if tail.is_empty() {
+        Ok(parsed)
+    } else {
+        Err(Error::UnexpectedText)
+    }
+}
+
+/// Demangle `input` if it is a mangled C++ symbol, or return it unchanged,
+/// without allocating, if it isn't.
+///
+/// The input is left unchanged if it does not start with the `_Z` (or `__Z`)
+/// mangling prefix, or if it fails to parse or demangle. This is handy for
+/// filtering arbitrary tokens, most of which aren't symbols at all.
+///
+/// ```
+/// use cpp_demangle::demangle_or_borrow;
+/// use std::borrow::Cow;
+///
+/// match demangle_or_borrow("_ZN5space3fooEibc") {
+///     Cow::Owned(demangled) => assert_eq!(demangled, "space::foo(int, bool, char)"),
+///     Cow::Borrowed(_) => panic!("should have demangled"),
+/// }
+///
+/// match demangle_or_borrow("main") {
+///     Cow::Borrowed(unchanged) => assert_eq!(unchanged, "main"),
+///     Cow::Owned(_) => panic!("should have borrowed"),
+/// }
+///
+/// // Not a valid mangled symbol, despite the prefix.
+/// match demangle_or_borrow("_Zzzz") {
+///     Cow::Borrowed(unchanged) => assert_eq!(unchanged, "_Zzzz"),
+///     Cow::Owned(_) => panic!("should have borrowed"),
+/// }
+/// ```
+pub fn demangle_or_borrow(input: &str) -> Cow<str> {
+    match Symbol::new(input.as_bytes()).map(|sym| sym.demangle()) {
+        Ok(Ok(demangled)) => Cow::Owned(demangled),
+        _ => Cow::Borrowed(input),
+    }
+}
+
+/// Demangle `input` and return the demangled symbol as raw bytes.
+///
+/// Identifiers are copied from the mangled input unchanged, so unlike
+/// `Symbol::demangle`, which lossily converts the output to a `String`, this
+/// leaves any non-UTF-8 identifier bytes for the caller to interpret.
+///
+/// ```
+/// use cpp_demangle::{demangle_bytes, Symbol};
+///
+/// // `café()` with its identifier in Latin-1.
+/// let mangled = b"_Z4caf\xe9v";
+/// assert_eq!(demangle_bytes(mangled).unwrap(), b"caf\xe9()");
+///
+/// let sym = Symbol::new(&mangled[..]).unwrap();
+/// assert_eq!(sym.demangle().unwrap(), "caf\u{fffd}()");
+/// ```
+pub fn demangle_bytes(input: &[u8]) -> DemangleResult<Vec<u8>> {
+    let sym = try!(Symbol::new(input).map_err(DemangleError::Parse));
+    let mut out = vec![];
+    try!(sym.demangle_to(&mut out, Default::default()));
+    Ok(out)
+}
+
+/// Demangles many symbols one after another, reusing the same buffers for
+/// each, rather than allocating fresh ones every time as `Symbol` does. This
+/// is the fastest way to demangle a whole symbol table.
+///
+/// ```
+/// use cpp_demangle::Demangler;
+///
+/// let mut demangler = Demangler::new();
+/// assert_eq!(demangler.demangle(b"_ZN5space3fooEibc").unwrap(),
+///            "space::foo(int, bool, char)");
+/// assert_eq!(demangler.demangle(b"_Z3barv").unwrap(), "bar()");
+/// assert!(demangler.demangle(b"main").is_err());
+/// ```
+#[derive(Debug)]
+pub struct Demangler {
+    substitutions: subs::SubstitutionTable,
+    out: Vec<u8>,
+    demangled: String,
+}
+
+impl Default for Demangler {
+    fn default() -> Demangler {
+        Demangler::new()
+    }
+}
+
+impl Demangler {
+    /// Construct a new `Demangler`, with empty buffers.
+    pub fn new() -> Demangler {
+        Demangler {
+            substitutions: subs::SubstitutionTable::new(),
+            out: vec![],
+            demangled: String::new(),
+        }
+    }
+
+    /// Parse and demangle `input`, like `Symbol::new` followed by
+    /// `Symbol::demangle`. The result borrows this `Demangler`'s buffer, and
+    /// is overwritten by the next call.
+    pub fn demangle(&mut self, input: &[u8]) -> DemangleResult<&str> {
+        self.substitutions.clear();
+        self.out.clear();
+        self.demangled.clear();
+
+        try!(Symbol::check_prefix(&input).map_err(DemangleError::Parse));
+        let parsed = try!(parse_all(&mut self.substitutions, input)
+            .map_err(DemangleError::Parse));
         {
-            let mut ctx = ast::DemangleContext::new(&self.substitutions,
-                                                    self.raw.as_ref(),
-                                                    &mut out);
-            try!(self.parsed.demangle(&mut ctx, None).map_err(|_| fmt::Error));
+            let mut ctx = ast::DemangleContext::new(&self.substitutions, input, &mut self.out);
+            try!(parsed.demangle(&mut ctx, None));
         }
-        write!(f, "{}", String::from_utf8_lossy(&out))
+
+        self.demangled.push_str(&String::from_utf8_lossy(&self.out));
+        Ok(&self.demangled)
     }
 }
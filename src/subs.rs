@@ -1,7 +1,9 @@
 //! Types dealing with the substitutions table.
 
 use ast;
+use error::{self, DemangleResult, Result};
 use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::io;
 use std::iter::FromIterator;
 use std::ops::Deref;
@@ -31,7 +33,7 @@ impl ast::Demangle for Substitutable {
     fn demangle<W>(&self,
                    ctx: &mut ast::DemangleContext<W>,
                    stack: Option<ast::ArgStack>)
-                   -> io::Result<()>
+                   -> DemangleResult<()>
         where W: io::Write
     {
         match *self {
@@ -44,44 +46,225 @@ impl ast::Demangle for Substitutable {
     }
 }
 
+impl ast::SemanticEq for Substitutable {
+    fn semantic_eq(&self, ctx: &ast::SemanticEqContext, other: &Substitutable) -> bool {
+        match (self, other) {
+            (&Substitutable::UnscopedTemplateName(ref lhs),
+             &Substitutable::UnscopedTemplateName(ref rhs)) => lhs.semantic_eq(ctx, rhs),
+            (&Substitutable::Type(ref lhs), &Substitutable::Type(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            (&Substitutable::TemplateTemplateParam(ref lhs),
+             &Substitutable::TemplateTemplateParam(ref rhs)) => lhs.semantic_eq(ctx, rhs),
+            (&Substitutable::UnresolvedType(ref lhs),
+             &Substitutable::UnresolvedType(ref rhs)) => lhs.semantic_eq(ctx, rhs),
+            (&Substitutable::Prefix(ref lhs), &Substitutable::Prefix(ref rhs)) => {
+                lhs.semantic_eq(ctx, rhs)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl ast::Remangle for Substitutable {
+    fn remangle(&self, ctx: &mut ast::RemangleContext) -> ast::RemangleResult {
+        match *self {
+            Substitutable::UnscopedTemplateName(ref name) => name.remangle(ctx),
+            Substitutable::Type(ref ty) => ty.remangle(ctx),
+            Substitutable::TemplateTemplateParam(ref ttp) => ttp.remangle(ctx),
+            Substitutable::Prefix(ref prefix) => prefix.remangle(ctx),
+            Substitutable::UnresolvedType(_) => Err(()),
+        }
+    }
+}
+
 /// The table of substitutable components that we have parsed thus far, and for
 /// which there are potential back-references.
 #[doc(hidden)]
-#[derive(Clone, Hash, PartialEq, Eq)]
-pub struct SubstitutionTable(Vec<Substitutable>);
+#[derive(Clone)]
+pub struct SubstitutionTable {
+    subs: Vec<Substitutable>,
+
+    // Components that are referenced by other AST nodes, but which are not
+    // substitution candidates themselves, such as the last component of a
+    // `<nested-name>`'s `<prefix>`. These are never the target of a back
+    // reference, so they live apart from `subs` to keep its numbering intact.
+    non_substitutions: Vec<Substitutable>,
+
+    // The spans of the major productions parsed so far, in the order that
+    // their parses completed, or `None` if we aren't recording them.
+    spans: Option<Vec<ast::ParsedSpan>>,
+
+    // How many major productions we are currently nested within, and the
+    // most that we have ever been nested within.
+    depth: usize,
+    max_depth: usize,
+
+    // The most components we are willing to insert, if limited, and whether
+    // an insertion has ever been refused for exceeding it.
+    max_len: Option<usize>,
+    exceeded_max_len: bool,
+}
+
+// How deeply the parse that built a table was nested is a statistic about the
+// parse, and its size limit is a parse option, so neither is part of the
+// table, and both are ignored when comparing and hashing.
+impl PartialEq for SubstitutionTable {
+    fn eq(&self, other: &SubstitutionTable) -> bool {
+        self.subs == other.subs && self.non_substitutions == other.non_substitutions &&
+        self.spans == other.spans
+    }
+}
+
+impl Eq for SubstitutionTable {}
+
+impl Hash for SubstitutionTable {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.subs.hash(state);
+        self.non_substitutions.hash(state);
+        self.spans.hash(state);
+    }
+}
 
 impl fmt::Debug for SubstitutionTable {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         try!(f.pad("SubstitutionTable "));
-        f.debug_map().entries(self.0.iter().enumerate()).finish()
+        try!(f.debug_map().entries(self.subs.iter().enumerate()).finish());
+        if !self.non_substitutions.is_empty() {
+            try!(f.pad(" non-substitutions "));
+            try!(f.debug_map().entries(self.non_substitutions.iter().enumerate()).finish());
+        }
+        Ok(())
     }
 }
 
 impl SubstitutionTable {
     /// Construct a new `SubstitutionTable`.
     pub fn new() -> SubstitutionTable {
-        SubstitutionTable(Vec::new())
+        SubstitutionTable {
+            subs: Vec::new(),
+            non_substitutions: Vec::new(),
+            spans: None,
+            depth: 0,
+            max_depth: 0,
+            max_len: None,
+            exceeded_max_len: false,
+        }
+    }
+
+    /// Construct a new `SubstitutionTable` that also records the span of input
+    /// that each major production is parsed from.
+    pub fn with_spans() -> SubstitutionTable {
+        SubstitutionTable {
+            subs: Vec::new(),
+            non_substitutions: Vec::new(),
+            spans: Some(Vec::new()),
+            depth: 0,
+            max_depth: 0,
+            max_len: None,
+            exceeded_max_len: false,
+        }
+    }
+
+    /// Remove every component from the table, keeping its allocation for
+    /// reuse, and forget everything recorded about the previous parse.
+    pub fn clear(&mut self) {
+        self.subs.clear();
+        self.non_substitutions.clear();
+        if let Some(ref mut spans) = self.spans {
+            spans.clear();
+        }
+        self.depth = 0;
+        self.max_depth = 0;
+        self.exceeded_max_len = false;
+    }
+
+    /// Record that the given production was parsed from `input[start..end]`,
+    /// if we are recording spans.
+    pub fn record_span(&mut self, production: &'static str, start: usize, end: usize) {
+        if let Some(ref mut spans) = self.spans {
+            spans.push(ast::ParsedSpan {
+                production: production,
+                start: start,
+                end: end,
+            });
+        }
+    }
+
+    /// The spans recorded so far, or `None` if we aren't recording them.
+    pub fn spans(&self) -> Option<&[ast::ParsedSpan]> {
+        self.spans.as_ref().map(|spans| &spans[..])
+    }
+
+    /// Note that we are starting to parse a major production.
+    pub fn enter_production(&mut self) {
+        self.depth += 1;
+        if self.depth > self.max_depth {
+            self.max_depth = self.depth;
+        }
+    }
+
+    /// Note that we finished parsing a major production, successfully or not.
+    pub fn exit_production(&mut self) {
+        debug_assert!(self.depth > 0);
+        self.depth -= 1;
+    }
+
+    /// The deepest that major productions have been nested while parsing.
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    /// Limit the table to at most `max_len` components, or lift the limit if
+    /// `None`.
+    pub fn set_max_len(&mut self, max_len: Option<usize>) {
+        self.max_len = max_len;
+    }
+
+    /// Has an insertion ever been refused because the table was full?
+    pub fn exceeded_max_len(&self) -> bool {
+        self.exceeded_max_len
     }
 
     /// Insert a freshly-parsed substitutable component into the table and
-    /// return the index at which it now lives.
-    pub fn insert(&mut self, entity: Substitutable) -> usize {
-        let idx = self.0.len();
+    /// return the index at which it now lives, or
+    /// `Error::TooManySubstitutions` if the table is already full.
+    pub fn insert(&mut self, entity: Substitutable) -> Result<usize> {
+        let idx = self.subs.len();
+        if self.max_len.map_or(false, |max_len| idx >= max_len) {
+            self.exceeded_max_len = true;
+            return Err(error::Error::TooManySubstitutions);
+        }
         log!("SubstitutionTable::insert @ {}: {:?}", idx, entity);
-        self.0.push(entity);
+        self.subs.push(entity);
+        Ok(idx)
+    }
+
+    /// Insert a freshly-parsed component that is not a substitution candidate,
+    /// and return the index at which it now lives among the other
+    /// non-substitutions.
+    pub fn insert_non_substitution(&mut self, entity: Substitutable) -> usize {
+        let idx = self.non_substitutions.len();
+        log!("SubstitutionTable::insert_non_substitution @ {}: {:?}", idx, entity);
+        self.non_substitutions.push(entity);
         idx
     }
 
+    /// Get the non-substitution component at the given index, if any.
+    pub fn non_substitution(&self, idx: usize) -> Option<&Substitutable> {
+        self.non_substitutions.get(idx)
+    }
+
     /// Does this substitution table contain a component at the given index?
     pub fn contains(&self, idx: usize) -> bool {
-        idx < self.0.len()
+        idx < self.subs.len()
     }
 
     /// Get the type referenced by the given handle, or None if there is no such
     /// entry, or there is an entry that is not a type.
     pub fn get_type(&self, handle: &ast::TypeHandle) -> Option<&ast::Type> {
         if let ast::TypeHandle::BackReference(idx) = *handle {
-            self.0.get(idx).and_then(|s| match *s {
+            self.subs.get(idx).and_then(|s| match *s {
                 Substitutable::Type(ref ty) => Some(ty),
                 _ => None,
             })
@@ -89,11 +272,83 @@ impl SubstitutionTable {
             None
         }
     }
+
+    /// Get the `<unscoped-template-name>` referenced by the given handle, or
+    /// None if there is no such entry, or there is an entry that is not an
+    /// `<unscoped-template-name>`.
+    pub fn get_unscoped_template_name(&self,
+                                      handle: &ast::UnscopedTemplateNameHandle)
+                                      -> Option<&ast::UnscopedTemplateName> {
+        if let ast::UnscopedTemplateNameHandle::BackReference(idx) = *handle {
+            self.subs.get(idx).and_then(|s| match *s {
+                Substitutable::UnscopedTemplateName(ref name) => Some(name),
+                _ => None,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Get the `<prefix>` referenced by the given handle, or None if there is
+    /// no such entry, or there is an entry that is not a `<prefix>`.
+    pub fn get_prefix(&self, handle: &ast::PrefixHandle) -> Option<&ast::Prefix> {
+        let entity = match *handle {
+            ast::PrefixHandle::BackReference(idx) => self.subs.get(idx),
+            ast::PrefixHandle::NonSubstitution(ast::NonSubstitution(idx)) => {
+                self.non_substitutions.get(idx)
+            }
+            ast::PrefixHandle::WellKnown(_) => None,
+        };
+        entity.and_then(|s| match *s {
+            Substitutable::Prefix(ref prefix) => Some(prefix),
+            _ => None,
+        })
+    }
+
+    /// Get the `<template-template-param>` referenced by the given handle, or
+    /// None if there is no such entry, or there is an entry that is not a
+    /// `<template-template-param>`.
+    pub fn get_template_template_param(&self,
+                                       handle: &ast::TemplateTemplateParamHandle)
+                                       -> Option<&ast::TemplateTemplateParam> {
+        if let ast::TemplateTemplateParamHandle::BackReference(idx) = *handle {
+            self.subs.get(idx).and_then(|s| match *s {
+                Substitutable::TemplateTemplateParam(ref ttp) => Some(ttp),
+                _ => None,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Get the `<unresolved-type>` referenced by the given handle, or None if
+    /// there is no such entry, or there is an entry that is not an
+    /// `<unresolved-type>`.
+    pub fn get_unresolved_type(&self,
+                               handle: &ast::UnresolvedTypeHandle)
+                               -> Option<&ast::UnresolvedType> {
+        if let ast::UnresolvedTypeHandle::BackReference(idx) = *handle {
+            self.subs.get(idx).and_then(|s| match *s {
+                Substitutable::UnresolvedType(ref ty) => Some(ty),
+                _ => None,
+            })
+        } else {
+            None
+        }
+    }
 }
 
 impl FromIterator<Substitutable> for SubstitutionTable {
     fn from_iter<I: IntoIterator<Item = Substitutable>>(iter: I) -> Self {
-        SubstitutionTable(Vec::from_iter(iter))
+        SubstitutionTable {
+            subs: Vec::from_iter(iter),
+            non_substitutions: Vec::new(),
+            spans: None,
+            depth: 0,
+            max_depth: 0,
+            max_len: None,
+            exceeded_max_len: false,
+        }
     }
 }
 
@@ -101,6 +356,6 @@ impl Deref for SubstitutionTable {
     type Target = [Substitutable];
 
     fn deref(&self) -> &Self::Target {
-        &self.0[..]
+        &self.subs[..]
     }
 }
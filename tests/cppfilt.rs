@@ -0,0 +1,73 @@
+use std::env;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+/// Get the path to the `cppfilt` example, which cargo builds alongside the
+/// integration tests.
+fn cppfilt_path() -> PathBuf {
+    let mut path = env::current_exe().expect("should have a path to the test executable");
+    path.pop();
+    if path.ends_with("deps") {
+        path.pop();
+    }
+    path.push("examples");
+    path.push(format!("cppfilt{}", env::consts::EXE_SUFFIX));
+    path
+}
+
+fn cppfilt(input: &str) -> String {
+    let mut child = Command::new(cppfilt_path())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("should spawn cppfilt");
+
+    child.stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .expect("should write to cppfilt's stdin");
+
+    let output = child.wait_with_output().expect("should wait on cppfilt");
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).expect("cppfilt should output utf-8")
+}
+
+#[test]
+fn one_symbol_per_line() {
+    assert_eq!(cppfilt("_ZN5space3fooEibc\n_Z3foov\n"),
+               "space::foo(int, bool, char)\nfoo()\n");
+}
+
+#[test]
+fn symbols_embedded_in_text() {
+    assert_eq!(cppfilt("call _ZN5space3fooEibc and then _Z3barv; done\n"),
+               "call space::foo(int, bool, char) and then bar(); done\n");
+}
+
+#[test]
+fn leading_underscores() {
+    assert_eq!(cppfilt("__ZN5space3fooEibc\n"), "space::foo(int, bool, char)\n");
+}
+
+#[test]
+fn invalid_symbols_are_unchanged() {
+    assert_eq!(cppfilt("_Z _Zx __Zx _ZN3foo\n"), "_Z _Zx __Zx _ZN3foo\n");
+
+    // A valid symbol followed by more of the same token is not a symbol.
+    assert_eq!(cppfilt("_Z3foo3\n"), "_Z3foo3\n");
+    assert_eq!(cppfilt("_Z1fIiEvDTtecvT__E _Z1fIiEvDTsrNT_1A1BIcE3fooE\n"),
+               "_Z1fIiEvDTtecvT__E _Z1fIiEvDTsrNT_1A1BIcE3fooE\n");
+}
+
+#[test]
+fn no_trailing_newline() {
+    assert_eq!(cppfilt("_Z3foov"), "foo()");
+}
+
+#[test]
+fn global_ctor_dtor_names() {
+    assert_eq!(cppfilt("_GLOBAL__sub_I_main.cpp _GLOBAL__D__Z3foov\n"),
+               "global constructors keyed to main.cpp global destructors keyed to foo()\n");
+}
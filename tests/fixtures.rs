@@ -0,0 +1,47 @@
+//! Check that we reproduce libiberty's expected output for every
+//! `mangled => demangled` pair listed in `tests/demangle-fixtures`.
+
+extern crate cpp_demangle;
+
+const FIXTURES: &'static str = include_str!("demangle-fixtures");
+
+#[test]
+fn demangle_fixtures() {
+    let mut failures = vec![];
+    let mut count = 0;
+
+    for (line_no, line) in FIXTURES.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut parts = line.splitn(2, " => ");
+        let (mangled, expected) = match (parts.next(), parts.next()) {
+            (Some(mangled), Some(expected)) => (mangled.trim(), expected.trim()),
+            _ => panic!("demangle-fixtures:{}: expected `mangled => demangled`",
+                        line_no + 1),
+        };
+        count += 1;
+
+        let actual = match cpp_demangle::Symbol::new_lenient(mangled) {
+            Ok(sym) => format!("{}", sym),
+            Err(e) => format!("<error: {}>", e),
+        };
+
+        if actual != expected {
+            failures.push(format!("demangle-fixtures:{}: {}\n    expected: {}\n    actual:   {}",
+                                  line_no + 1,
+                                  mangled,
+                                  expected,
+                                  actual));
+        }
+    }
+
+    assert!(count > 0, "should have read some fixtures");
+    assert!(failures.is_empty(),
+            "{} of {} fixtures failed to demangle as expected:\n{}",
+            failures.len(),
+            count,
+            failures.join("\n"));
+}